@@ -0,0 +1,12 @@
+create table "signing_key" (
+  "id" uuid primary key not null default gen_random_uuid(),
+  "kid" text unique not null,
+  "purpose" text not null,
+  "material" text not null,
+  "status" text not null,
+  "created_at" timestamptz not null default now(),
+  "retires_at" timestamptz
+);
+
+-- Only one key per purpose may be used for signing at a time.
+create unique index "signing_key_active_purpose" on "signing_key" ("purpose") where "status" = 'active';
@@ -0,0 +1,14 @@
+alter table "refresh_session" drop constraint "refresh_session_user_id_device_id_key";
+
+alter table "refresh_session"
+  drop column "device_id",
+  drop column "device_label",
+  drop column "platform",
+  drop column "user_agent",
+  drop column "created_at";
+
+-- Keep only the most recent session of each user.
+delete from "refresh_session" a using "refresh_session" b
+  where a."user_id" = b."user_id" and a."issued_at" < b."issued_at";
+
+alter table "refresh_session" add constraint "refresh_session_user_id_key" unique ("user_id");
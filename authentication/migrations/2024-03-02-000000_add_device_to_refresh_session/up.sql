@@ -0,0 +1,12 @@
+alter table "refresh_session" drop constraint "refresh_session_user_id_key";
+
+alter table "refresh_session"
+  add column "device_id" text,
+  add column "device_label" text,
+  add column "platform" text,
+  add column "user_agent" text,
+  add column "created_at" timestamptz not null default now();
+
+-- Signing in again on the same device replaces that device's session.
+-- Sessions without a device id are never replaced.
+alter table "refresh_session" add constraint "refresh_session_user_id_device_id_key" unique ("user_id", "device_id");
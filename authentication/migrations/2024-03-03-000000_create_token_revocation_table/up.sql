@@ -0,0 +1,8 @@
+create table "token_revocation" (
+  "id" uuid primary key not null default gen_random_uuid(),
+  "kind" text not null,
+  "subject_id" uuid not null,
+  "revoked_at" timestamptz not null default now(),
+  "expires_at" timestamptz not null,
+  unique ("kind", "subject_id")
+);
@@ -0,0 +1,2 @@
+alter table "auth_provider"
+  drop constraint "auth_provider_provider_type_provider_id_key";
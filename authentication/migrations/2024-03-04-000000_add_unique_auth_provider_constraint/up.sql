@@ -0,0 +1,2 @@
+alter table "auth_provider"
+  add constraint "auth_provider_provider_type_provider_id_key" unique ("provider_type", "provider_id");
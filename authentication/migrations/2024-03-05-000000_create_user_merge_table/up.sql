@@ -0,0 +1,8 @@
+create table "user_merge" (
+  "id" uuid primary key not null default gen_random_uuid(),
+  "surviving_user_id" uuid not null,
+  "merged_user_id" uuid not null,
+  "moved_provider_ids" uuid[] not null,
+  "initiated_by" text not null,
+  "merged_at" timestamptz not null default now()
+);
@@ -0,0 +1,4 @@
+alter table "auth_provider"
+  drop column "family_shared_owner_id",
+  drop column "vac_banned",
+  drop column "publisher_banned";
@@ -0,0 +1,4 @@
+alter table "auth_provider"
+  add column "family_shared_owner_id" text,
+  add column "vac_banned" boolean not null default false,
+  add column "publisher_banned" boolean not null default false;
@@ -0,0 +1,5 @@
+create table "password_credential" (
+  "auth_provider_id" uuid primary key not null references "auth_provider"(id) on delete cascade,
+  "password_hash" text not null,
+  "updated_at" timestamptz not null default now()
+);
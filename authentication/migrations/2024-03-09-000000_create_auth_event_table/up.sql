@@ -0,0 +1,13 @@
+create table "auth_event" (
+  "id" uuid primary key not null default gen_random_uuid(),
+  "user_id" uuid not null references "user"(id) on delete cascade,
+  "kind" text not null,
+  "outcome" text not null,
+  "provider_type" text,
+  "session_id" uuid,
+  "ip" text,
+  "user_agent" text,
+  "created_at" timestamptz not null default now()
+);
+
+create index "auth_event_user_id_created_at_idx" on "auth_event" ("user_id", "created_at" desc);
@@ -0,0 +1,3 @@
+drop index "user_name_unique";
+
+alter table "user" drop column "discriminator";
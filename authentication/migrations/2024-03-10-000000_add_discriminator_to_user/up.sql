@@ -0,0 +1,19 @@
+alter table "user" add column "discriminator" smallint;
+
+-- Existing names keep their user. Users who share a name regardless of case
+-- are told apart by numbering them.
+update "user" set "discriminator" = "numbered"."discriminator"
+  from (
+    select "id",
+      row_number() over (partition by lower("name") order by "id") as "discriminator",
+      count(*) over (partition by lower("name")) as "name_count"
+    from "user"
+    where "name" is not null
+  ) as "numbered"
+  where "user"."id" = "numbered"."id" and "numbered"."name_count" > 1;
+
+-- Names are unique regardless of case among users with the same
+-- discriminator, where users without a discriminator share 0.
+create unique index "user_name_unique"
+  on "user" (lower("name"), coalesce("discriminator", 0))
+  where "name" is not null;
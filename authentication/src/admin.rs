@@ -0,0 +1,34 @@
+use crate::config::ADMIN_KEY;
+use crate::{auth, user};
+use actix_web::{error, web, FromRequest};
+use openssl::memcmp;
+use std::future::{ready, Ready};
+
+pub fn config_service(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::scope("/keys").configure(auth::keys::admin::config_service))
+        .service(web::scope("/users").configure(user::admin::config_service));
+}
+
+/// Proof that the request was made by an operator holding the `ADMIN_KEY`.
+pub struct AdminKey;
+
+impl FromRequest for AdminKey {
+    type Future = Ready<Result<Self, Self::Error>>;
+    type Error = error::Error;
+
+    fn from_request(req: &actix_web::HttpRequest, _: &mut actix_web::dev::Payload) -> Self::Future {
+        let Some(admin_key) = ADMIN_KEY.as_ref() else {
+            return ready(Err(error::ErrorNotFound("Admin routes are disabled")));
+        };
+
+        let Some(key) = req.headers().get("Admin-Key").and_then(|h| h.to_str().ok()) else {
+            return ready(Err(error::ErrorUnauthorized("Missing Admin-Key header")));
+        };
+
+        if key.len() != admin_key.len() || !memcmp::eq(key.as_bytes(), admin_key.as_bytes()) {
+            return ready(Err(error::ErrorUnauthorized("Invalid Admin-Key header")));
+        }
+
+        ready(Ok(AdminKey))
+    }
+}
@@ -0,0 +1,93 @@
+use crate::config::TRUST_FORWARDED_FOR;
+use actix_web::{error, http, FromRequest, HttpRequest};
+use std::future::{ready, Ready};
+use std::net::SocketAddr;
+
+/// Longer header values are truncated before they are stored.
+const MAX_HEADER_LENGTH: usize = 256;
+
+/// The device or client that a user signs in from.
+///
+/// Clients identify themselves with the `X-Device-Id`, `X-Device-Label` and
+/// `X-Client-Platform` headers. A device id should be generated once per
+/// installation and kept, so that signing in again on the same device replaces
+/// that device's refresh session instead of adding a new one.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DeviceInfo {
+    pub device_id: Option<String>,
+    pub device_label: Option<String>,
+    pub platform: Option<String>,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+}
+
+impl DeviceInfo {
+    pub fn from_headers(req: &HttpRequest) -> Self {
+        DeviceInfo {
+            device_id: header_value(req, "X-Device-Id"),
+            device_label: header_value(req, "X-Device-Label"),
+            platform: header_value(req, "X-Client-Platform"),
+            user_agent: header_value(req, http::header::USER_AGENT.as_str()),
+            ip: client_ip(req, *TRUST_FORWARDED_FOR),
+        }
+    }
+}
+
+/// The IP of the client, from `Forwarded` or `X-Forwarded-For` if the server
+/// runs behind a trusted proxy.
+pub fn client_ip(req: &HttpRequest, trust_forwarded_for: bool) -> Option<String> {
+    if !trust_forwarded_for {
+        return req.peer_addr().map(|addr| addr.ip().to_string());
+    }
+    let connection_info = req.connection_info();
+    let addr = connection_info.realip_remote_addr()?;
+    // Without a forwarded IP, the peer address is used with its port.
+    match addr.parse::<SocketAddr>() {
+        Ok(addr) => Some(addr.ip().to_string()),
+        Err(_) => Some(addr.to_string()),
+    }
+}
+
+fn header_value(req: &HttpRequest, name: &str) -> Option<String> {
+    let value = req.headers().get(name)?.to_str().ok()?.trim();
+    if value.is_empty() {
+        return None;
+    }
+    Some(value.chars().take(MAX_HEADER_LENGTH).collect())
+}
+
+impl FromRequest for DeviceInfo {
+    type Error = error::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut actix_web::dev::Payload) -> Self::Future {
+        ready(Ok(DeviceInfo::from_headers(req)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test;
+
+    use super::*;
+
+    #[actix_web::test]
+    async fn device_info_is_read_from_headers() {
+        let req = test::TestRequest::default()
+            .insert_header(("X-Device-Id", "device-1"))
+            .insert_header(("X-Device-Label", "  Adam's phone "))
+            .insert_header(("X-Client-Platform", ""))
+            .insert_header((http::header::USER_AGENT, "a".repeat(1000)))
+            .to_http_request();
+
+        let device = DeviceInfo::from_headers(&req);
+
+        assert_eq!(device.device_id.as_deref(), Some("device-1"));
+        assert_eq!(device.device_label.as_deref(), Some("Adam's phone"));
+        assert_eq!(device.platform, None);
+        assert_eq!(
+            device.user_agent.map(|ua| ua.len()),
+            Some(MAX_HEADER_LENGTH)
+        );
+    }
+}
@@ -0,0 +1,168 @@
+use crate::auth::device::DeviceInfo;
+use crate::auth::provider::AuthProviderType;
+use crate::db::{DbConnection, DbError};
+use crate::{diesel_insertable, schema};
+use chrono::{DateTime, Utc};
+use diesel::{
+    deserialize::{FromSql, FromSqlRow},
+    expression::AsExpression,
+    pg::Pg,
+    prelude::*,
+    serialize::ToSql,
+    sql_types,
+};
+use diesel_async::RunQueryDsl;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+diesel_insertable! {
+    #[derive(Queryable, Selectable, Insertable)]
+    #[diesel(table_name = schema::auth_event)]
+    #[diesel(check_for_backend(Pg))]
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct AuthEvent {
+        pub user_id: Uuid,
+        pub kind: AuthEventKind,
+        pub outcome: AuthEventOutcome,
+        pub provider_type: Option<AuthProviderType>,
+        pub session_id: Option<Uuid>,
+        pub ip: Option<String>,
+        pub user_agent: Option<String>,
+        pub created_at: DateTime<Utc>,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[diesel(sql_type = sql_types::Text)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthEventKind {
+    SignIn,
+    Refresh,
+    SignOut,
+    SignOutEverywhere,
+    /// A session was revoked from another session.
+    SessionRevoked,
+    PasswordReset,
+}
+
+impl FromSql<sql_types::Text, Pg> for AuthEventKind {
+    fn from_sql(
+        bytes: <Pg as diesel::backend::Backend>::RawValue<'_>,
+    ) -> diesel::deserialize::Result<Self> {
+        match String::from_sql(bytes)?.as_ref() {
+            "sign_in" => Ok(AuthEventKind::SignIn),
+            "refresh" => Ok(AuthEventKind::Refresh),
+            "sign_out" => Ok(AuthEventKind::SignOut),
+            "sign_out_everywhere" => Ok(AuthEventKind::SignOutEverywhere),
+            "session_revoked" => Ok(AuthEventKind::SessionRevoked),
+            "password_reset" => Ok(AuthEventKind::PasswordReset),
+            _ => Err("Unknown `AuthEventKind` received".into()),
+        }
+    }
+}
+
+impl ToSql<sql_types::Text, Pg> for AuthEventKind {
+    fn to_sql<'b>(
+        &'b self,
+        out: &mut diesel::serialize::Output<'b, '_, Pg>,
+    ) -> diesel::serialize::Result {
+        <str as ToSql<sql_types::Text, Pg>>::to_sql(
+            match self {
+                AuthEventKind::SignIn => "sign_in",
+                AuthEventKind::Refresh => "refresh",
+                AuthEventKind::SignOut => "sign_out",
+                AuthEventKind::SignOutEverywhere => "sign_out_everywhere",
+                AuthEventKind::SessionRevoked => "session_revoked",
+                AuthEventKind::PasswordReset => "password_reset",
+            },
+            out,
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[diesel(sql_type = sql_types::Text)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthEventOutcome {
+    Success,
+    WrongPassword,
+    /// A refresh token was used again, so its session was invalidated.
+    TokenAlreadyUsed,
+    SessionExpired,
+    SessionInvalidated,
+}
+
+impl FromSql<sql_types::Text, Pg> for AuthEventOutcome {
+    fn from_sql(
+        bytes: <Pg as diesel::backend::Backend>::RawValue<'_>,
+    ) -> diesel::deserialize::Result<Self> {
+        match String::from_sql(bytes)?.as_ref() {
+            "success" => Ok(AuthEventOutcome::Success),
+            "wrong_password" => Ok(AuthEventOutcome::WrongPassword),
+            "token_already_used" => Ok(AuthEventOutcome::TokenAlreadyUsed),
+            "session_expired" => Ok(AuthEventOutcome::SessionExpired),
+            "session_invalidated" => Ok(AuthEventOutcome::SessionInvalidated),
+            _ => Err("Unknown `AuthEventOutcome` received".into()),
+        }
+    }
+}
+
+impl ToSql<sql_types::Text, Pg> for AuthEventOutcome {
+    fn to_sql<'b>(
+        &'b self,
+        out: &mut diesel::serialize::Output<'b, '_, Pg>,
+    ) -> diesel::serialize::Result {
+        <str as ToSql<sql_types::Text, Pg>>::to_sql(
+            match self {
+                AuthEventOutcome::Success => "success",
+                AuthEventOutcome::WrongPassword => "wrong_password",
+                AuthEventOutcome::TokenAlreadyUsed => "token_already_used",
+                AuthEventOutcome::SessionExpired => "session_expired",
+                AuthEventOutcome::SessionInvalidated => "session_invalidated",
+            },
+            out,
+        )
+    }
+}
+
+impl AuthEventInsert {
+    pub fn new(
+        user_id: Uuid,
+        kind: AuthEventKind,
+        outcome: AuthEventOutcome,
+        device: &DeviceInfo,
+    ) -> Self {
+        AuthEventInsert {
+            user_id,
+            kind,
+            outcome,
+            provider_type: None,
+            session_id: None,
+            ip: device.ip.clone(),
+            user_agent: device.user_agent.clone(),
+            created_at: Utc::now(),
+        }
+    }
+
+    pub fn provider_type(self, provider_type: &AuthProviderType) -> Self {
+        AuthEventInsert {
+            provider_type: Some(provider_type.clone()),
+            ..self
+        }
+    }
+
+    pub fn session_id(self, session_id: Uuid) -> Self {
+        AuthEventInsert {
+            session_id: Some(session_id),
+            ..self
+        }
+    }
+
+    pub async fn record(&self, conn: &mut DbConnection) -> Result<(), DbError> {
+        diesel::insert_into(schema::auth_event::table)
+            .values(self)
+            .execute(conn)
+            .await
+            .map(|_| ())
+    }
+}
@@ -0,0 +1,224 @@
+use chrono::{DateTime, Duration, Utc};
+use openssl::x509::X509;
+use reqwest::header::{HeaderMap, CACHE_CONTROL, EXPIRES};
+use reqwest::Url;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::RwLock;
+
+/// How long a certificate is cached when the response has no cache headers.
+const DEFAULT_MAX_AGE_SECS: i64 = 60 * 60;
+
+/// How long a certificate is cached at most, whatever its cache headers say.
+const MAX_MAX_AGE_SECS: i64 = 24 * 60 * 60;
+
+/// A downloaded certificate, with how long it may be cached for.
+pub struct FetchedCertificate {
+    pub cert: X509,
+    pub max_age: Option<Duration>,
+}
+
+impl FetchedCertificate {
+    /// Reads how long the certificate may be cached for from the
+    /// `Cache-Control` and `Expires` headers of its response.
+    pub fn new(cert: X509, headers: &HeaderMap) -> Self {
+        FetchedCertificate {
+            cert,
+            max_age: get_max_age(headers),
+        }
+    }
+}
+
+fn get_max_age(headers: &HeaderMap) -> Option<Duration> {
+    if let Some(cache_control) = headers.get(CACHE_CONTROL).and_then(|h| h.to_str().ok()) {
+        for directive in cache_control.split(',').map(str::trim) {
+            if directive == "no-store" || directive == "no-cache" {
+                return Some(Duration::zero());
+            }
+            if let Some(seconds) = directive
+                .strip_prefix("max-age=")
+                .and_then(|s| s.parse::<i64>().ok())
+            {
+                return Some(Duration::seconds(seconds));
+            }
+        }
+    }
+
+    let expires = headers.get(EXPIRES).and_then(|h| h.to_str().ok())?;
+    let expires = DateTime::parse_from_rfc2822(expires).ok()?;
+    Some(expires.with_timezone(&Utc) - Utc::now())
+}
+
+struct CacheEntry {
+    cert: X509,
+    expires_at: DateTime<Utc>,
+}
+
+/// Certificates by the URL they were downloaded from.
+///
+/// Entries expire according to the cache headers of their response, bounded
+/// to a day. An expired entry is still served if it cannot be downloaded again,
+/// so that sign-in keeps working while Apple's CDN is unavailable.
+#[derive(Default)]
+pub struct CertificateCache {
+    entries: RwLock<HashMap<Url, CacheEntry>>,
+}
+
+impl CertificateCache {
+    pub async fn get<F, Fut>(&self, url: &Url, fetch: F) -> Result<X509, Box<dyn std::error::Error>>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<FetchedCertificate, Box<dyn std::error::Error>>>,
+    {
+        let stale = {
+            let entries = self
+                .entries
+                .read()
+                .expect("Failed to get read lock on certificate cache");
+            match entries.get(url) {
+                Some(entry) if entry.expires_at > Utc::now() => return Ok(entry.cert.clone()),
+                Some(entry) => Some(entry.cert.clone()),
+                None => None,
+            }
+        };
+
+        let fetched = match fetch().await {
+            Ok(fetched) => fetched,
+            Err(err) => return stale.ok_or(err),
+        };
+
+        let max_age = fetched
+            .max_age
+            .unwrap_or(Duration::seconds(DEFAULT_MAX_AGE_SECS))
+            .clamp(Duration::zero(), Duration::seconds(MAX_MAX_AGE_SECS));
+        self.entries
+            .write()
+            .expect("Failed to get write lock on certificate cache")
+            .insert(
+                url.clone(),
+                CacheEntry {
+                    cert: fetched.cert.clone(),
+                    expires_at: Utc::now() + max_age,
+                },
+            );
+        Ok(fetched.cert)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use openssl::asn1::Asn1Time;
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::hash::MessageDigest;
+    use openssl::nid::Nid;
+    use openssl::pkey::PKey;
+    use openssl::x509::X509Builder;
+    use reqwest::header::HeaderValue;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    fn generate_certificate() -> X509 {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+        let mut builder = X509Builder::new().unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder
+            .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        builder
+            .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        builder.sign(&key, MessageDigest::sha256()).unwrap();
+        builder.build()
+    }
+
+    fn url() -> Url {
+        Url::parse("https://static.gc.apple.com/public-key/gc-prod-10.cer").unwrap()
+    }
+
+    #[actix_web::test]
+    async fn fresh_certificates_are_not_downloaded_again() {
+        let cache = CertificateCache::default();
+        let cert = generate_certificate();
+        let fetches = AtomicUsize::new(0);
+
+        for _ in 0..2 {
+            let cached = cache
+                .get(&url(), || async {
+                    fetches.fetch_add(1, Ordering::SeqCst);
+                    Ok(FetchedCertificate {
+                        cert: cert.clone(),
+                        max_age: Some(Duration::minutes(5)),
+                    })
+                })
+                .await
+                .unwrap();
+            assert_eq!(cached.to_der().unwrap(), cert.to_der().unwrap());
+        }
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+    }
+
+    #[actix_web::test]
+    async fn expired_certificates_are_downloaded_again_or_served_stale() {
+        let cache = CertificateCache::default();
+        let cert = generate_certificate();
+        let new_cert = generate_certificate();
+
+        cache
+            .get(&url(), || async {
+                Ok(FetchedCertificate {
+                    cert: cert.clone(),
+                    max_age: Some(Duration::zero()),
+                })
+            })
+            .await
+            .unwrap();
+
+        let stale = cache
+            .get(&url(), || async { Err("Apple's CDN is down".into()) })
+            .await
+            .unwrap();
+        assert_eq!(stale.to_der().unwrap(), cert.to_der().unwrap());
+
+        let refreshed = cache
+            .get(&url(), || async {
+                Ok(FetchedCertificate {
+                    cert: new_cert.clone(),
+                    max_age: None,
+                })
+            })
+            .await
+            .unwrap();
+        assert_eq!(refreshed.to_der().unwrap(), new_cert.to_der().unwrap());
+
+        let other_url =
+            Url::parse("https://static.gc.apple.com/public-key/gc-prod-11.cer").unwrap();
+        let missing = cache
+            .get(&other_url, || async { Err("Apple's CDN is down".into()) })
+            .await;
+        assert!(missing.is_err());
+    }
+
+    #[test]
+    fn max_age_is_read_from_cache_headers() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(get_max_age(&headers), None);
+
+        headers.insert(
+            CACHE_CONTROL,
+            HeaderValue::from_static("public, max-age=600"),
+        );
+        assert_eq!(get_max_age(&headers), Some(Duration::seconds(600)));
+
+        headers.insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+        assert_eq!(get_max_age(&headers), Some(Duration::zero()));
+
+        headers.remove(CACHE_CONTROL);
+        headers.insert(
+            EXPIRES,
+            HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"),
+        );
+        assert!(get_max_age(&headers).is_some_and(|max_age| max_age < Duration::zero()));
+    }
+}
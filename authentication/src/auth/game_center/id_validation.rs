@@ -1,11 +1,19 @@
+use crate::auth::game_center::certificate_cache::{CertificateCache, FetchedCertificate};
 use crate::auth::provider::{
     AuthProviderChangeset, AuthProviderInsert, AuthProviderType, IntoAuthProviderInsert,
 };
+use crate::auth::sign_in_error::SignInError;
+use crate::config::{GameCenterConfig, GAME_CENTER_CONFIG};
 use crate::user::{User, UserInsert};
 use base64::engine::general_purpose::STANDARD as BASE64_STANDARD;
 use base64::prelude::*;
-use openssl::{hash::MessageDigest, sign::Verifier, x509::X509};
-use reqwest::StatusCode;
+use chrono::Utc;
+use openssl::nid::Nid;
+use openssl::stack::Stack;
+use openssl::x509::store::X509StoreBuilder;
+use openssl::x509::{X509StoreContext, X509VerifyResult, X509};
+use openssl::{hash::MessageDigest, sign::Verifier};
+use reqwest::{StatusCode, Url};
 use serde::Deserialize;
 use std::time::Duration;
 
@@ -23,22 +31,22 @@ pub struct IdentitySignature {
 impl From<&IdentitySignature> for AuthProviderChangeset {
     fn from(value: &IdentitySignature) -> Self {
         AuthProviderChangeset {
-            order: 0,
             email: None,
             email_verified: false,
             display_name: value.user_name.clone(),
             user_name: value.user_name.clone(),
             picture_url: None,
             locale: None,
+            family_shared_owner_id: None,
+            vac_banned: false,
+            publisher_banned: false,
         }
     }
 }
 
 impl From<&IdentitySignature> for UserInsert {
-    fn from(value: &IdentitySignature) -> Self {
-        UserInsert {
-            name: value.user_name.clone(),
-        }
+    fn from(_value: &IdentitySignature) -> Self {
+        UserInsert::default()
     }
 }
 
@@ -55,45 +63,110 @@ impl IntoAuthProviderInsert for IdentitySignature {
             user_name: self.user_name.clone(),
             picture_url: None,
             locale: None,
+            family_shared_owner_id: None,
+            vac_banned: false,
+            publisher_banned: false,
         }
     }
 }
 
+const APPLE_ORGANIZATION_NAME: &str = "Apple Inc.";
+
+/// How many intermediate certificates are fetched to complete a chain.
+const MAX_INTERMEDIATE_CERTIFICATES: usize = 3;
+
 #[async_trait::async_trait]
 pub trait GameCenterIdValidationService: Sync {
-    async fn is_validated(
-        &self,
-        identity: &IdentitySignature,
-    ) -> Result<bool, Box<dyn std::error::Error>> {
-        let cert = get_certificate(&identity.public_key_url).await?;
+    fn config(&self) -> &GameCenterConfig;
 
-        let cert_verified = verify_certificate(&cert)?;
-        if !cert_verified {
-            return Err("Failed to verify Apple's public key certificate".into());
+    /// Gets the DER encoded certificate at a URL.
+    async fn get_certificate(&self, url: &Url) -> Result<X509, Box<dyn std::error::Error>>;
+
+    /// Validates an identity, or explains why it could not be validated.
+    async fn validate(&self, identity: &IdentitySignature) -> Result<(), SignInError> {
+        if self.is_validated(identity).await? {
+            Ok(())
+        } else {
+            Err(SignInError::InvalidCredential(
+                "Failed to validate identity".to_string(),
+            ))
+        }
+    }
+
+    /// Whether an identity is signed by Apple for one of our games.
+    ///
+    /// Fails if Apple's certificates cannot be downloaded, or if the signature
+    /// is malformed.
+    async fn is_validated(&self, identity: &IdentitySignature) -> Result<bool, SignInError> {
+        let config = self.config();
+        if !config.bundle_ids.contains(&identity.bundle_id) {
+            return Ok(false);
+        }
+        if !is_within_window(identity.timestamp, config.timestamp_window) {
+            return Ok(false);
         }
+        let Some(public_key_url) = parse_apple_url(&identity.public_key_url) else {
+            return Ok(false);
+        };
 
-        let identity_data = get_identity_data(&identity)?;
+        let cert = self
+            .get_certificate(&public_key_url)
+            .await
+            .map_err(SignInError::upstream_unavailable)?;
 
-        let public_key = cert.public_key()?;
-        let mut verifier = Verifier::new(MessageDigest::sha256(), &public_key)?;
-        verifier.update(&identity_data)?;
+        let mut intermediates = vec![];
+        let mut issued = cert.clone();
+        while intermediates.len() < MAX_INTERMEDIATE_CERTIFICATES
+            && !config.certificates.iter().any(|c| is_issuer(c, &issued))
+        {
+            let Some(issuer_url) = get_issuer_url(&issued) else {
+                break;
+            };
+            let issuer = self
+                .get_certificate(&issuer_url)
+                .await
+                .map_err(SignInError::upstream_unavailable)?;
+            intermediates.push(issuer.clone());
+            issued = issuer;
+        }
+
+        let cert_verified = verify_certificate(&cert, &intermediates, &config.certificates)
+            .map_err(|err| SignInError::InvalidCredential(err.to_string()))?;
+        if !cert_verified {
+            return Ok(false);
+        }
 
-        let signature = BASE64_STANDARD.decode(&identity.signature)?;
-        Ok(verifier.verify(&signature)?)
+        verify_signature(&cert, identity)
+            .map_err(|err| SignInError::InvalidCredential(err.to_string()))
     }
 }
 
-async fn get_certificate(public_key_url: &str) -> Result<X509, Box<dyn std::error::Error>> {
+fn verify_signature(
+    cert: &X509,
+    identity: &IdentitySignature,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let identity_data = get_identity_data(identity)?;
+
+    let public_key = cert.public_key()?;
+    let mut verifier = Verifier::new(MessageDigest::sha256(), &public_key)?;
+    verifier.update(&identity_data)?;
+
+    let signature = BASE64_STANDARD.decode(&identity.signature)?;
+    Ok(verifier.verify(&signature)?)
+}
+
+async fn download_certificate(url: &Url) -> Result<FetchedCertificate, Box<dyn std::error::Error>> {
     let client = reqwest::Client::new();
     let resp = client
-        .get(public_key_url)
+        .get(url.clone())
         .timeout(Duration::from_secs(5))
         .send()
         .await?;
     match resp.status() {
         StatusCode::OK => {
+            let headers = resp.headers().clone();
             let bytes = resp.bytes().await?;
-            Ok(X509::from_der(&bytes)?)
+            Ok(FetchedCertificate::new(X509::from_der(&bytes)?, &headers))
         }
         _ => {
             let text = resp.text().await?;
@@ -102,10 +175,68 @@ async fn get_certificate(public_key_url: &str) -> Result<X509, Box<dyn std::erro
     }
 }
 
-/// This is a stub implementation until we actually figure out how to load all
-/// the certificates required in the verification chain.
-fn verify_certificate(_cert: &X509) -> Result<bool, Box<dyn std::error::Error>> {
-    Ok(true)
+/// Only Apple's hosts are trusted to serve the public key certificate, so that
+/// clients cannot make the server fetch arbitrary URLs.
+fn parse_apple_url(public_key_url: &str) -> Option<Url> {
+    let url = Url::parse(public_key_url).ok()?;
+    let domain = url.domain()?;
+    let is_apple_host = domain == "apple.com" || domain.ends_with(".apple.com");
+    (url.scheme() == "https" && is_apple_host).then_some(url)
+}
+
+/// Whether a timestamp in milliseconds since the epoch is close enough to the
+/// current time.
+fn is_within_window(timestamp: u64, window: chrono::Duration) -> bool {
+    let now = Utc::now().timestamp_millis();
+    (now - timestamp as i64).abs() <= window.num_milliseconds()
+}
+
+fn is_issuer(issuer: &X509, issued: &X509) -> bool {
+    issuer.issued(issued) == X509VerifyResult::OK
+}
+
+/// The URL of the certificate that issued a certificate, from its Authority
+/// Information Access extension.
+fn get_issuer_url(cert: &X509) -> Option<Url> {
+    cert.authority_info()?
+        .iter()
+        .filter(|access| access.method().nid() == Nid::AD_CA_ISSUERS)
+        .filter_map(|access| access.location().uri())
+        .filter_map(|uri| Url::parse(uri).ok())
+        .find(|url| matches!(url.scheme(), "http" | "https"))
+}
+
+/// Checks that the certificate belongs to Apple and chains to one of the
+/// trusted roots.
+fn verify_certificate(
+    cert: &X509,
+    intermediates: &[X509],
+    certificates: &[X509],
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let is_apple = cert
+        .subject_name()
+        .entries_by_nid(Nid::ORGANIZATIONNAME)
+        .any(|entry| entry.data().as_slice() == APPLE_ORGANIZATION_NAME.as_bytes());
+    if !is_apple {
+        return Ok(false);
+    }
+
+    let mut store = X509StoreBuilder::new()?;
+    let mut chain = Stack::new()?;
+    for certificate in certificates {
+        if is_issuer(certificate, certificate) {
+            store.add_cert(certificate.clone())?;
+        } else {
+            chain.push(certificate.clone())?;
+        }
+    }
+    for intermediate in intermediates {
+        chain.push(intermediate.clone())?;
+    }
+    let store = store.build();
+
+    let mut context = X509StoreContext::new()?;
+    Ok(context.init(&store, cert, &chain, |c| c.verify_cert())?)
 }
 
 fn get_identity_data(identity: &IdentitySignature) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
@@ -117,6 +248,240 @@ fn get_identity_data(identity: &IdentitySignature) -> Result<Vec<u8>, Box<dyn st
     Ok(data)
 }
 
+lazy_static::lazy_static! {
+    static ref CERTIFICATE_CACHE: CertificateCache = CertificateCache::default();
+}
+
 pub struct RealGameCenterIdValidationService;
 
-impl GameCenterIdValidationService for RealGameCenterIdValidationService {}
+#[async_trait::async_trait]
+impl GameCenterIdValidationService for RealGameCenterIdValidationService {
+    fn config(&self) -> &GameCenterConfig {
+        &GAME_CENTER_CONFIG
+    }
+
+    async fn get_certificate(&self, url: &Url) -> Result<X509, Box<dyn std::error::Error>> {
+        CERTIFICATE_CACHE
+            .get(url, || download_certificate(url))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use openssl::asn1::Asn1Time;
+    use openssl::bn::BigNum;
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::pkey::{PKey, Private};
+    use openssl::sign::Signer;
+    use openssl::x509::extension::BasicConstraints;
+    use openssl::x509::{X509Builder, X509NameBuilder};
+
+    use super::*;
+
+    const BUNDLE_ID: &str = "com.example.game";
+    const PUBLIC_KEY_URL: &str = "https://static.gc.apple.com/public-key/gc-prod-10.cer";
+
+    fn generate_key() -> PKey<Private> {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap()
+    }
+
+    /// Generates a certificate for `key`, signed by `issuer` or self-signed.
+    fn generate_certificate(
+        organization: &str,
+        key: &PKey<Private>,
+        issuer: Option<(&X509, &PKey<Private>)>,
+        is_ca: bool,
+    ) -> X509 {
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("O", organization).unwrap();
+        name.append_entry_by_text("CN", organization).unwrap();
+        let name = name.build();
+
+        let mut builder = X509Builder::new().unwrap();
+        builder.set_version(2).unwrap();
+        let serial = BigNum::from_u32(rand_serial()).unwrap();
+        builder
+            .set_serial_number(&serial.to_asn1_integer().unwrap())
+            .unwrap();
+        builder.set_subject_name(&name).unwrap();
+        builder
+            .set_issuer_name(issuer.map_or(&name, |(cert, _)| cert.subject_name()))
+            .unwrap();
+        builder.set_pubkey(key).unwrap();
+        builder
+            .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        builder
+            .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        if is_ca {
+            builder
+                .append_extension(BasicConstraints::new().critical().ca().build().unwrap())
+                .unwrap();
+        }
+        let signing_key = issuer.map_or(key, |(_, key)| key);
+        builder.sign(signing_key, MessageDigest::sha256()).unwrap();
+        builder.build()
+    }
+
+    fn rand_serial() -> u32 {
+        uuid::Uuid::new_v4().as_u128() as u32
+    }
+
+    /// A root, an intermediate and an Apple certificate signed by them.
+    struct Chain {
+        root: X509,
+        intermediate: X509,
+        cert: X509,
+        key: PKey<Private>,
+    }
+
+    fn generate_chain(organization: &str) -> Chain {
+        let root_key = generate_key();
+        let root = generate_certificate("Test Root", &root_key, None, true);
+        let intermediate_key = generate_key();
+        let intermediate = generate_certificate(
+            "Test Intermediate",
+            &intermediate_key,
+            Some((&root, &root_key)),
+            true,
+        );
+        let key = generate_key();
+        let cert = generate_certificate(
+            organization,
+            &key,
+            Some((&intermediate, &intermediate_key)),
+            false,
+        );
+        Chain {
+            root,
+            intermediate,
+            cert,
+            key,
+        }
+    }
+
+    fn sign_identity(key: &PKey<Private>, timestamp: u64, bundle_id: &str) -> IdentitySignature {
+        let mut identity = IdentitySignature {
+            public_key_url: PUBLIC_KEY_URL.to_string(),
+            signature: String::new(),
+            salt: BASE64_STANDARD.encode(b"salt"),
+            timestamp,
+            player_id: "T:0001".to_string(),
+            user_name: Some("Bryan".to_string()),
+            bundle_id: bundle_id.to_string(),
+        };
+        let mut signer = Signer::new(MessageDigest::sha256(), key).unwrap();
+        signer
+            .update(&get_identity_data(&identity).unwrap())
+            .unwrap();
+        identity.signature = BASE64_STANDARD.encode(signer.sign_to_vec().unwrap());
+        identity
+    }
+
+    fn now() -> u64 {
+        Utc::now().timestamp_millis() as u64
+    }
+
+    struct MockGameCenterIdValidationService {
+        config: GameCenterConfig,
+        cert: X509,
+    }
+
+    impl MockGameCenterIdValidationService {
+        fn new(chain: &Chain, certificates: Vec<X509>) -> Self {
+            MockGameCenterIdValidationService {
+                config: GameCenterConfig {
+                    bundle_ids: vec![BUNDLE_ID.to_string()],
+                    timestamp_window: chrono::Duration::minutes(5),
+                    certificates,
+                },
+                cert: chain.cert.clone(),
+            }
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl GameCenterIdValidationService for MockGameCenterIdValidationService {
+        fn config(&self) -> &GameCenterConfig {
+            &self.config
+        }
+
+        async fn get_certificate(&self, url: &Url) -> Result<X509, Box<dyn std::error::Error>> {
+            assert_eq!(url.as_str(), PUBLIC_KEY_URL);
+            Ok(self.cert.clone())
+        }
+    }
+
+    #[actix_web::test]
+    async fn identity_signed_by_a_trusted_chain_is_validated() {
+        let chain = generate_chain(APPLE_ORGANIZATION_NAME);
+        let service = MockGameCenterIdValidationService::new(
+            &chain,
+            vec![chain.root.clone(), chain.intermediate.clone()],
+        );
+
+        let identity = sign_identity(&chain.key, now(), BUNDLE_ID);
+        assert!(service.is_validated(&identity).await.unwrap());
+
+        let tampered = IdentitySignature {
+            player_id: "T:0002".to_string(),
+            ..identity
+        };
+        assert!(!service.is_validated(&tampered).await.unwrap());
+    }
+
+    #[actix_web::test]
+    async fn certificate_must_chain_to_a_trusted_root() {
+        let chain = generate_chain(APPLE_ORGANIZATION_NAME);
+        let other_chain = generate_chain(APPLE_ORGANIZATION_NAME);
+        let service = MockGameCenterIdValidationService::new(
+            &chain,
+            vec![other_chain.root, chain.intermediate.clone()],
+        );
+
+        let identity = sign_identity(&chain.key, now(), BUNDLE_ID);
+        assert!(!service.is_validated(&identity).await.unwrap());
+    }
+
+    #[actix_web::test]
+    async fn certificate_must_belong_to_apple() {
+        let chain = generate_chain("Not Apple");
+        let service = MockGameCenterIdValidationService::new(
+            &chain,
+            vec![chain.root.clone(), chain.intermediate.clone()],
+        );
+
+        let identity = sign_identity(&chain.key, now(), BUNDLE_ID);
+        assert!(!service.is_validated(&identity).await.unwrap());
+    }
+
+    #[actix_web::test]
+    async fn identity_must_be_recent_and_for_an_allowed_game() {
+        let chain = generate_chain(APPLE_ORGANIZATION_NAME);
+        let service = MockGameCenterIdValidationService::new(
+            &chain,
+            vec![chain.root.clone(), chain.intermediate.clone()],
+        );
+
+        let stale = now() - 10 * 60 * 1000;
+        let identity = sign_identity(&chain.key, stale, BUNDLE_ID);
+        assert!(!service.is_validated(&identity).await.unwrap());
+
+        let identity = sign_identity(&chain.key, now(), "com.example.other");
+        assert!(!service.is_validated(&identity).await.unwrap());
+    }
+
+    #[test]
+    fn only_apple_hosts_are_trusted() {
+        assert!(parse_apple_url(PUBLIC_KEY_URL).is_some());
+        assert!(parse_apple_url("https://apple.com/key.cer").is_some());
+        assert!(parse_apple_url("http://static.gc.apple.com/key.cer").is_none());
+        assert!(parse_apple_url("https://static.gc.apple.com.evil.com/key.cer").is_none());
+        assert!(parse_apple_url("https://evilapple.com/key.cer").is_none());
+        assert!(parse_apple_url("https://static.gc.apple.com@evil.com/key.cer").is_none());
+        assert!(parse_apple_url("https://127.0.0.1/key.cer").is_none());
+    }
+}
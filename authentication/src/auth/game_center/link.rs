@@ -0,0 +1,21 @@
+use crate::auth::game_center::id_validation::{GameCenterIdValidationService, IdentitySignature};
+use crate::auth::identity::Identity;
+use crate::auth::link::link_provider;
+use crate::db::DbPool;
+use actix_web::{error, post, web, HttpResponse};
+
+/// Links a Game Center player to the caller.
+#[post("/link/")]
+async fn link(
+    id_signature: web::Json<IdentitySignature>,
+    identity: Identity,
+    pool: web::Data<DbPool>,
+    id_validation_service: web::Data<dyn GameCenterIdValidationService>,
+) -> actix_web::Result<HttpResponse> {
+    let id_signature = id_signature.0;
+    id_validation_service.validate(&id_signature).await?;
+
+    let mut conn = pool.get().await.map_err(error::ErrorInternalServerError)?;
+    let user_with_providers = link_provider(&mut conn, &identity.user_id, &id_signature).await?;
+    Ok(HttpResponse::Ok().json(user_with_providers))
+}
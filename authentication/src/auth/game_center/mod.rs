@@ -1,4 +1,6 @@
+mod certificate_cache;
 mod id_validation;
+mod link;
 mod sign_in;
 
 use self::id_validation::{GameCenterIdValidationService, RealGameCenterIdValidationService};
@@ -10,5 +12,6 @@ pub fn config_service(cfg: &mut web::ServiceConfig) {
         Arc::new(RealGameCenterIdValidationService) as Arc<dyn GameCenterIdValidationService>
     );
     cfg.app_data(id_validation_service)
-        .service(sign_in::sign_in);
+        .service(sign_in::sign_in)
+        .service(link::link);
 }
@@ -1,3 +1,4 @@
+use crate::auth::device::DeviceInfo;
 use crate::auth::game_center::id_validation::{GameCenterIdValidationService, IdentitySignature};
 use crate::auth::identity::IdentityConfig;
 use crate::auth::provider::{AuthProvider, AuthProviderChangeset, AuthProviderType};
@@ -14,13 +15,11 @@ async fn sign_in(
     id_signature: web::Json<IdentitySignature>,
     pool: web::Data<DbPool>,
     identity_config: web::Data<IdentityConfig>,
+    device: DeviceInfo,
     id_validation_service: web::Data<dyn GameCenterIdValidationService>,
 ) -> actix_web::Result<HttpResponse> {
     let id_signature = id_signature.0;
-    let validated = id_validation_service.is_validated(&id_signature).await?;
-    if !validated {
-        return Err(error::ErrorUnauthorized("Failed to validate identity"));
-    }
+    id_validation_service.validate(&id_signature).await?;
 
     let mut conn = pool.get().await.map_err(error::ErrorInternalServerError)?;
 
@@ -49,7 +48,9 @@ async fn sign_in(
         return generate_sign_in_success_response(
             &mut conn,
             UserWithAuthProviders { user, providers },
+            &AuthProviderType::AppleGameCenter,
             &identity_config,
+            &device,
         )
         .await;
     }
@@ -58,5 +59,12 @@ async fn sign_in(
         .await
         .map_err(error::ErrorInternalServerError)?;
 
-    generate_sign_in_success_response(&mut conn, new_user, &identity_config).await
+    generate_sign_in_success_response(
+        &mut conn,
+        new_user,
+        &AuthProviderType::AppleGameCenter,
+        &identity_config,
+        &device,
+    )
+    .await
 }
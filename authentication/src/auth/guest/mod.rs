@@ -0,0 +1,73 @@
+mod sign_in;
+
+use crate::auth::provider::{AuthProviderInsert, AuthProviderType, IntoAuthProviderInsert};
+use crate::db::{DbConnection, DbError};
+use crate::schema;
+use crate::user::{User, UserInsert};
+use actix_web::web;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL_SAFE_NO_PAD;
+use base64::prelude::*;
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+
+pub fn config_service(cfg: &mut web::ServiceConfig) {
+    cfg.service(sign_in::sign_in);
+}
+
+/// A guest is identified by a secret that only its device holds. The provider
+/// id is a hash of the secret, so that the secret is never stored.
+pub struct Guest {
+    pub secret_hash: String,
+}
+
+impl Guest {
+    pub fn from_secret(secret: &str) -> Self {
+        Guest {
+            secret_hash: BASE64_URL_SAFE_NO_PAD.encode(openssl::sha::sha256(secret.as_bytes())),
+        }
+    }
+}
+
+impl From<&Guest> for UserInsert {
+    fn from(_value: &Guest) -> Self {
+        UserInsert::default()
+    }
+}
+
+impl IntoAuthProviderInsert for Guest {
+    fn into_provider_insert(&self, user: &User) -> AuthProviderInsert {
+        AuthProviderInsert {
+            user_id: user.id,
+            provider_type: AuthProviderType::Guest,
+            provider_id: self.secret_hash.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Deletes users that only have a guest provider, and have not signed in or
+/// refreshed a session for `expires_in`. Returns the number of users deleted.
+pub async fn delete_unused_guests(
+    conn: &mut DbConnection,
+    expires_in: Duration,
+) -> Result<usize, DbError> {
+    let guest_user_ids = schema::auth_provider::table
+        .filter(schema::auth_provider::provider_type.eq(AuthProviderType::Guest))
+        .select(schema::auth_provider::user_id);
+    let linked_user_ids = schema::auth_provider::table
+        .filter(schema::auth_provider::provider_type.ne(AuthProviderType::Guest))
+        .select(schema::auth_provider::user_id);
+    let active_user_ids = schema::refresh_session::table
+        .filter(schema::refresh_session::issued_at.gt(Utc::now() - expires_in))
+        .select(schema::refresh_session::user_id);
+
+    diesel::delete(
+        schema::user::table
+            .filter(schema::user::id.eq_any(guest_user_ids))
+            .filter(schema::user::id.ne_all(linked_user_ids))
+            .filter(schema::user::id.ne_all(active_user_ids)),
+    )
+    .execute(conn)
+    .await
+}
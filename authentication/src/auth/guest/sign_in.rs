@@ -0,0 +1,207 @@
+use crate::auth::device::DeviceInfo;
+use crate::auth::guest::Guest;
+use crate::auth::identity::IdentityConfig;
+use crate::auth::provider::{AuthProvider, AuthProviderType};
+use crate::auth::sign_in_error::SignInError;
+use crate::auth::token::generate_secret;
+use crate::auth::{
+    create_new_user, generate_sign_in_success, generate_sign_in_success_response, sign_in_cookies,
+    SignInResult,
+};
+use crate::db::DbPool;
+use crate::schema;
+use crate::user::{User, UserWithAuthProviders};
+use actix_web::{error, post, web, HttpResponse};
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use serde::Serialize;
+
+/// A newly created guest, with the secret that its device signs in with.
+#[derive(Debug, Serialize)]
+#[cfg_attr(test, derive(serde::Deserialize))]
+struct NewGuest {
+    secret: String,
+    #[serde(flatten)]
+    result: SignInResult,
+}
+
+/// Signs in with the guest secret in the body, or creates a new guest if the
+/// body is empty.
+#[post("/sign-in/")]
+async fn sign_in(
+    secret: String,
+    pool: web::Data<DbPool>,
+    identity_config: web::Data<IdentityConfig>,
+    device: DeviceInfo,
+) -> actix_web::Result<HttpResponse> {
+    let mut conn = pool.get().await.map_err(error::ErrorInternalServerError)?;
+
+    let secret = secret.trim();
+    if secret.is_empty() {
+        let secret = generate_secret();
+        let new_user = create_new_user(&mut conn, &Guest::from_secret(&secret))
+            .await
+            .map_err(error::ErrorInternalServerError)?;
+        let success = generate_sign_in_success(
+            &mut conn,
+            new_user,
+            &AuthProviderType::Guest,
+            &identity_config,
+            &device,
+        )
+        .await?;
+
+        let mut response = HttpResponse::Ok();
+        for cookie in sign_in_cookies(&success, &identity_config) {
+            response.cookie(cookie);
+        }
+        return Ok(response.json(NewGuest {
+            secret,
+            result: SignInResult::Success(success),
+        }));
+    }
+
+    let guest = Guest::from_secret(secret);
+    let Some(provider): Option<AuthProvider> = schema::auth_provider::table
+        .filter(schema::auth_provider::provider_type.eq(AuthProviderType::Guest))
+        .filter(schema::auth_provider::provider_id.eq(&guest.secret_hash))
+        .first(&mut conn)
+        .await
+        .optional()
+        .map_err(error::ErrorInternalServerError)?
+    else {
+        return Err(SignInError::InvalidCredential("Unknown guest secret".to_string()).into());
+    };
+
+    let user: User = schema::user::table
+        .filter(schema::user::id.eq(&provider.user_id))
+        .first(&mut conn)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    let providers = user
+        .get_providers(&mut conn)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    generate_sign_in_success_response(
+        &mut conn,
+        UserWithAuthProviders { user, providers },
+        &AuthProviderType::Guest,
+        &identity_config,
+        &device,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::guest::delete_unused_guests;
+    use crate::auth::link::link_provider;
+    use crate::auth::provider::AuthProviderInsert;
+    use crate::{config, db};
+    use actix_web::{http::StatusCode, test, App};
+    use chrono::{Duration, Utc};
+
+    use super::*;
+
+    #[actix_web::test]
+    async fn guests_sign_in_with_their_secret() {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(
+                    db::initialize_db_pool(&config::DB_URL).await,
+                ))
+                .app_data(web::Data::new(config::IDENTITY_CONFIG.clone()))
+                .service(sign_in),
+        )
+        .await;
+
+        let req = test::TestRequest::post().uri("/sign-in/").to_request();
+        let new_guest: NewGuest = test::call_and_read_body_json(&app, req).await;
+        let SignInResult::Success(created) = new_guest.result else {
+            panic!("Expected the guest to be signed in");
+        };
+        assert_eq!(
+            created.user.providers[0].provider_type,
+            AuthProviderType::Guest
+        );
+        assert_ne!(created.user.providers[0].provider_id, new_guest.secret);
+
+        let req = test::TestRequest::post()
+            .uri("/sign-in/")
+            .set_payload(new_guest.secret)
+            .to_request();
+        let SignInResult::Success(signed_in) = test::call_and_read_body_json(&app, req).await
+        else {
+            panic!("Expected the guest to be signed in");
+        };
+        assert_eq!(signed_in.user.user.id, created.user.user.id);
+
+        let req = test::TestRequest::post()
+            .uri("/sign-in/")
+            .set_payload(generate_secret())
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn only_unused_guests_are_deleted() {
+        let pool = db::initialize_db_pool(&config::DB_URL).await;
+        let mut conn = pool.get().await.unwrap();
+
+        let mut guest_ids = vec![];
+        for _ in 0..3 {
+            let guest = create_new_user(&mut conn, &Guest::from_secret(&generate_secret()))
+                .await
+                .unwrap();
+            generate_sign_in_success(
+                &mut conn,
+                guest.clone(),
+                &AuthProviderType::Guest,
+                &config::IDENTITY_CONFIG,
+                &DeviceInfo::default(),
+            )
+            .await
+            .unwrap();
+            guest_ids.push(guest.user.id);
+        }
+        let [active_id, unused_id, linked_id] = guest_ids[..] else {
+            unreachable!();
+        };
+
+        diesel::update(schema::refresh_session::table)
+            .filter(schema::refresh_session::user_id.ne(active_id))
+            .set(schema::refresh_session::issued_at.eq(Utc::now() - Duration::days(60)))
+            .execute(&mut conn)
+            .await
+            .unwrap();
+        link_provider(
+            &mut conn,
+            &linked_id,
+            &AuthProviderInsert {
+                provider_type: AuthProviderType::Steam,
+                provider_id: "76561197960287930".to_string(),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        let deleted = delete_unused_guests(&mut conn, Duration::days(30))
+            .await
+            .unwrap();
+        assert_eq!(deleted, 1);
+
+        let remaining_ids: Vec<uuid::Uuid> = schema::user::table
+            .filter(schema::user::id.eq_any(&guest_ids))
+            .select(schema::user::id)
+            .load(&mut conn)
+            .await
+            .unwrap();
+        assert!(remaining_ids.contains(&active_id));
+        assert!(remaining_ids.contains(&linked_id));
+        assert!(!remaining_ids.contains(&unused_id));
+    }
+}
@@ -1,42 +1,138 @@
+use crate::auth::keys::ring::KeyRing;
+use crate::auth::refresh::session::RefreshSession;
+use crate::auth::revocation::RevocationList;
 use crate::auth::Token;
+use crate::db::{DbConnection, DbError};
 use crate::user::User;
 use actix_web::{error, http, web, FromRequest, HttpMessage};
 use chrono::{Duration, Utc};
-use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation};
-use serde::{Deserialize, Serialize};
+use jsonwebtoken::{Header, Validation};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::future::{ready, Ready};
+use std::sync::{Arc, RwLock, RwLockReadGuard};
 use uuid::Uuid;
 
 #[derive(Clone, Debug)]
 pub struct IdentityConfig {
-    pub secret: String,
+    /// Shared between all clones so that key rotations apply to every worker.
+    pub keys: Arc<RwLock<KeyRing>>,
+    pub revocations: Arc<RwLock<RevocationList>>,
     pub expires_in: Duration,
-    pub refresh_secret: String,
     pub refresh_expires_in: Duration,
 }
 
+impl IdentityConfig {
+    pub fn read_keys(&self) -> RwLockReadGuard<'_, KeyRing> {
+        self.keys.read().expect("Failed to get read lock on keys")
+    }
+
+    /// Reloads the key ring from the database.
+    pub async fn sync_keys(
+        &self,
+        conn: &mut DbConnection,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut keys = self.read_keys().clone();
+        keys.sync(conn).await?;
+        *self.keys.write().expect("Failed to get write lock on keys") = keys;
+        Ok(())
+    }
+
+    pub fn read_revocations(&self) -> RwLockReadGuard<'_, RevocationList> {
+        self.revocations
+            .read()
+            .expect("Failed to get read lock on revocations")
+    }
+
+    /// Reloads the revocations from the database to pick up revocations made
+    /// by other instances.
+    pub async fn sync_revocations(&self, conn: &mut DbConnection) -> Result<(), DbError> {
+        let revocations = RevocationList::load(conn).await?;
+        *self
+            .revocations
+            .write()
+            .expect("Failed to get write lock on revocations") = revocations;
+        Ok(())
+    }
+
+    /// Signs claims that the client carries between requests with the active
+    /// refresh token key. The claims need an `aud`, so that they are never
+    /// accepted as a refresh token.
+    pub fn encode_client_claims<T: Serialize>(&self, claims: &T) -> String {
+        let keys = self.read_keys();
+        let signing_key = keys.active_refresh_key();
+        let header = Header {
+            kid: Some(signing_key.kid.clone()),
+            ..Header::default()
+        };
+        jsonwebtoken::encode(&header, claims, &signing_key.encoding_key())
+            .expect("Failed to encode client claims")
+    }
+
+    /// Decodes claims signed by `encode_client_claims` for an audience.
+    pub fn decode_client_claims<T: DeserializeOwned>(
+        &self,
+        token: &str,
+        audience: &str,
+    ) -> Result<T, error::Error> {
+        let header = jsonwebtoken::decode_header(token).map_err(error::ErrorBadRequest)?;
+        let keys = self.read_keys();
+        let Some(signing_key) = header.kid.and_then(|kid| keys.refresh_key(Some(&kid))) else {
+            return Err(error::ErrorBadRequest("Unknown signing key"));
+        };
+        let mut validation = Validation::default();
+        validation.set_audience(&[audience]);
+        validation.set_required_spec_claims(&["exp", "aud"]);
+        match jsonwebtoken::decode::<T>(token, &signing_key.decoding_key(), &validation) {
+            Ok(payload) => Ok(payload.claims),
+            Err(err) => Err(error::ErrorBadRequest(err)),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 struct IdentityClaims {
     pub sub: Uuid,
+    pub jti: Uuid,
+    /// The refresh session that the token was issued for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<Uuid>,
     pub iat: u64,
+    /// `iat` in milliseconds, so that a token issued in the same second as a
+    /// revocation but after it is not revoked.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iat_ms: Option<u64>,
     pub exp: u64,
 }
 
 impl IdentityClaims {
+    /// When the token was issued in milliseconds. Tokens without `iat_ms`
+    /// count as issued at the end of their second.
+    fn issued_at_millis(&self) -> i64 {
+        match self.iat_ms {
+            Some(iat_ms) => iat_ms as i64,
+            None => self.iat as i64 * 1000 + 999,
+        }
+    }
+
     pub fn encode(&self, config: &IdentityConfig) -> String {
-        jsonwebtoken::encode(
-            &Header::default(),
-            self,
-            &EncodingKey::from_secret(config.secret.as_ref()),
-        )
-        .expect("Failed to encode access token")
+        let keys = config.read_keys();
+        let signing_key = keys.active_access_key();
+        let mut header = Header::new(signing_key.algorithm);
+        header.kid = Some(signing_key.kid.clone());
+        jsonwebtoken::encode(&header, self, signing_key.encoding_key())
+            .expect("Failed to encode access token")
     }
 
     pub fn decode(config: &IdentityConfig, token: &str) -> Result<Self, error::Error> {
+        let header = jsonwebtoken::decode_header(token).map_err(error::ErrorBadRequest)?;
+        let keys = config.read_keys();
+        let Some(signing_key) = header.kid.and_then(|kid| keys.access_key(&kid)) else {
+            return Err(error::ErrorBadRequest("Unknown signing key"));
+        };
         match jsonwebtoken::decode::<Self>(
             token,
-            &DecodingKey::from_secret(config.secret.as_ref()),
-            &Validation::default(),
+            signing_key.decoding_key(),
+            &Validation::new(signing_key.algorithm),
         ) {
             Ok(payload) => Ok(payload.claims),
             Err(err) => Err(error::ErrorBadRequest(err)),
@@ -48,29 +144,61 @@ impl IdentityClaims {
 #[derive(Debug, Clone)]
 pub struct Identity {
     pub user_id: Uuid,
+    pub session_id: Option<Uuid>,
+    /// The `jti` of the access token that the identity was read from.
+    pub token_id: Option<Uuid>,
 }
 
 impl Identity {
     pub fn from_user_id(user_id: &Uuid) -> Self {
         Identity {
-            user_id: user_id.clone(),
+            user_id: *user_id,
+            session_id: None,
+            token_id: None,
         }
     }
 
     pub fn from_user(user: &User) -> Self {
+        Identity::from_user_id(&user.id)
+    }
+
+    pub fn from_session(session: &RefreshSession) -> Self {
         Identity {
-            user_id: user.id.clone(),
+            user_id: session.user_id,
+            session_id: Some(session.id),
+            token_id: None,
+        }
+    }
+
+    pub fn from_token(config: &IdentityConfig, token: &str) -> Result<Self, error::Error> {
+        let claims = IdentityClaims::decode(config, token)?;
+        if config.read_revocations().is_revoked(
+            &claims.jti,
+            claims.sid.as_ref(),
+            &claims.sub,
+            claims.issued_at_millis(),
+        ) {
+            return Err(error::ErrorUnauthorized("Access token has been revoked"));
         }
+        Ok(Identity {
+            user_id: claims.sub,
+            session_id: claims.sid,
+            token_id: Some(claims.jti),
+        })
     }
 
     pub fn generate_token(&self, config: &IdentityConfig) -> Token {
         let now = Utc::now();
         let iat = now.timestamp() as u64;
+        let iat_ms = now.timestamp_millis() as u64;
         let expires_at = now + config.expires_in;
         let exp = expires_at.timestamp() as u64;
         let claims = IdentityClaims {
             sub: self.user_id,
+            jti: Uuid::new_v4(),
+            sid: self.session_id,
             iat,
+            iat_ms: Some(iat_ms),
             exp,
         };
         Token {
@@ -98,12 +226,11 @@ impl FromRequest for Identity {
             .app_data::<web::Data<IdentityConfig>>()
             .expect("IdentityConfig is available in app data");
 
-        let claims = match IdentityClaims::decode(config, &token) {
-            Ok(claims) => claims,
-            Err(err) => return ready(Err(error::ErrorBadRequest(err))),
+        let identity = match Identity::from_token(config, &token) {
+            Ok(identity) => identity,
+            Err(err) => return ready(Err(err)),
         };
 
-        let identity = Identity::from_user_id(&claims.sub);
         req.extensions_mut().insert::<Identity>(identity.clone());
 
         ready(Ok(identity))
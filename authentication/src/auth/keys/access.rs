@@ -0,0 +1,151 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL_SAFE_NO_PAD;
+use base64::prelude::*;
+use jsonwebtoken::jwk::{
+    AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, KeyAlgorithm,
+    OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType,
+};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey};
+use openssl::hash::{hash, MessageDigest};
+use openssl::pkey::{Id, PKey};
+use std::fmt;
+
+/// An asymmetric key used to sign access tokens.
+///
+/// Supports RSA keys (signed with `RS256`) and Ed25519 keys (signed with
+/// `EdDSA`). The key id is the RFC 7638 thumbprint of the public key.
+#[derive(Clone)]
+pub struct AccessSigningKey {
+    pub kid: String,
+    pub algorithm: Algorithm,
+    pem: String,
+    encoding_key: EncodingKey,
+    decoding_key: DecodingKey,
+    jwk: Jwk,
+}
+
+impl AccessSigningKey {
+    pub fn from_pem(pem: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
+        let private_key = PKey::private_key_from_pem(pem)?;
+
+        let (algorithm, encoding_key, parameters) = match private_key.id() {
+            Id::RSA => {
+                let rsa = private_key.rsa()?;
+                let parameters = AlgorithmParameters::RSA(RSAKeyParameters {
+                    key_type: RSAKeyType::RSA,
+                    n: BASE64_URL_SAFE_NO_PAD.encode(rsa.n().to_vec()),
+                    e: BASE64_URL_SAFE_NO_PAD.encode(rsa.e().to_vec()),
+                });
+                let encoding_key = EncodingKey::from_rsa_der(&rsa.private_key_to_der()?);
+                (Algorithm::RS256, encoding_key, parameters)
+            }
+            Id::ED25519 => {
+                let parameters = AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                    key_type: OctetKeyPairType::OctetKeyPair,
+                    curve: EllipticCurve::Ed25519,
+                    x: BASE64_URL_SAFE_NO_PAD.encode(private_key.raw_public_key()?),
+                });
+                let encoding_key = EncodingKey::from_ed_der(&private_key.private_key_to_pkcs8()?);
+                (Algorithm::EdDSA, encoding_key, parameters)
+            }
+            _ => return Err("Access token signing keys must be RSA or Ed25519 keys".into()),
+        };
+
+        let kid = thumbprint(&parameters)?;
+        let jwk = Jwk {
+            common: CommonParameters {
+                public_key_use: Some(PublicKeyUse::Signature),
+                key_algorithm: Some(match algorithm {
+                    Algorithm::RS256 => KeyAlgorithm::RS256,
+                    _ => KeyAlgorithm::EdDSA,
+                }),
+                key_id: Some(kid.clone()),
+                ..CommonParameters::default()
+            },
+            algorithm: parameters,
+        };
+        let decoding_key = DecodingKey::from_jwk(&jwk)?;
+
+        Ok(AccessSigningKey {
+            kid,
+            algorithm,
+            pem: String::from_utf8(private_key.private_key_to_pem_pkcs8()?)?,
+            encoding_key,
+            decoding_key,
+            jwk,
+        })
+    }
+
+    /// Generates a new Ed25519 signing key.
+    pub fn generate() -> Result<Self, Box<dyn std::error::Error>> {
+        let private_key = PKey::generate_ed25519()?;
+        Self::from_pem(&private_key.private_key_to_pem_pkcs8()?)
+    }
+
+    /// The private key in PKCS#8 PEM format.
+    pub fn pem(&self) -> &str {
+        &self.pem
+    }
+
+    pub fn encoding_key(&self) -> &EncodingKey {
+        &self.encoding_key
+    }
+
+    pub fn decoding_key(&self) -> &DecodingKey {
+        &self.decoding_key
+    }
+
+    /// The public key in JWK format.
+    pub fn jwk(&self) -> &Jwk {
+        &self.jwk
+    }
+}
+
+impl fmt::Debug for AccessSigningKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AccessSigningKey")
+            .field("kid", &self.kid)
+            .field("algorithm", &self.algorithm)
+            .finish_non_exhaustive()
+    }
+}
+
+/// https://www.rfc-editor.org/rfc/rfc7638
+fn thumbprint(parameters: &AlgorithmParameters) -> Result<String, Box<dyn std::error::Error>> {
+    let canonical = match parameters {
+        AlgorithmParameters::RSA(rsa) => {
+            format!(r#"{{"e":"{}","kty":"RSA","n":"{}"}}"#, rsa.e, rsa.n)
+        }
+        AlgorithmParameters::OctetKeyPair(okp) => {
+            format!(r#"{{"crv":"Ed25519","kty":"OKP","x":"{}"}}"#, okp.x)
+        }
+        _ => return Err("Unsupported key type".into()),
+    };
+    let digest = hash(MessageDigest::sha256(), canonical.as_bytes())?;
+    Ok(BASE64_URL_SAFE_NO_PAD.encode(digest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_web::test]
+    async fn pem_round_trips_to_the_same_key() {
+        let key = AccessSigningKey::generate().expect("Failed to generate key");
+        let loaded = AccessSigningKey::from_pem(key.pem().as_bytes()).expect("Failed to load key");
+
+        assert_eq!(loaded.kid, key.kid);
+        assert_eq!(loaded.jwk(), key.jwk());
+    }
+
+    #[actix_web::test]
+    async fn ec_keys_are_rejected() {
+        let group = openssl::ec::EcGroup::from_curve_name(openssl::nid::Nid::X9_62_PRIME256V1)
+            .expect("Failed to create curve");
+        let ec_key = openssl::ec::EcKey::generate(&group).expect("Failed to generate key");
+        let pem = PKey::from_ec_key(ec_key)
+            .and_then(|k| k.private_key_to_pem_pkcs8())
+            .expect("Failed to encode key");
+
+        assert!(AccessSigningKey::from_pem(&pem).is_err());
+    }
+}
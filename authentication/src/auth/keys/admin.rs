@@ -0,0 +1,373 @@
+use crate::admin::AdminKey;
+use crate::auth::identity::IdentityConfig;
+use crate::auth::keys::access::AccessSigningKey;
+use crate::auth::keys::ring::RefreshSigningKey;
+use crate::auth::keys::{KeyPurpose, KeyStatus, SigningKey, SigningKeyInsert};
+use crate::db::{DbError, DbPool};
+use crate::schema;
+use actix_web::{error, get, post, web, HttpResponse};
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use diesel_async::scoped_futures::ScopedFutureExt;
+use diesel_async::{AsyncConnection, RunQueryDsl};
+use serde::Serialize;
+
+pub fn config_service(cfg: &mut web::ServiceConfig) {
+    cfg.service(list)
+        .service(create)
+        .service(promote)
+        .service(retire);
+}
+
+/// A signing key without its key material.
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(test, derive(serde::Deserialize))]
+pub struct SigningKeyDescription {
+    pub kid: String,
+    pub purpose: KeyPurpose,
+    pub status: KeyStatus,
+    pub created_at: DateTime<Utc>,
+    pub retires_at: Option<DateTime<Utc>>,
+}
+
+impl From<SigningKey> for SigningKeyDescription {
+    fn from(value: SigningKey) -> Self {
+        SigningKeyDescription {
+            kid: value.kid,
+            purpose: value.purpose,
+            status: value.status,
+            created_at: value.created_at,
+            retires_at: value.retires_at,
+        }
+    }
+}
+
+#[get("/")]
+async fn list(_: AdminKey, pool: web::Data<DbPool>) -> actix_web::Result<HttpResponse> {
+    let mut conn = pool.get().await.map_err(error::ErrorInternalServerError)?;
+
+    let keys: Vec<SigningKey> = schema::signing_key::table
+        .order(schema::signing_key::created_at.desc())
+        .get_results(&mut conn)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(
+        keys.into_iter()
+            .map(SigningKeyDescription::from)
+            .collect::<Vec<_>>(),
+    ))
+}
+
+/// Adds a pending key for `purpose`.
+///
+/// The body may contain the key material, a PEM encoded private key for
+/// access tokens or a secret for refresh tokens. If it is empty, a new key is
+/// generated.
+#[post("/{purpose}/")]
+async fn create(
+    _: AdminKey,
+    purpose: web::Path<KeyPurpose>,
+    material: String,
+    pool: web::Data<DbPool>,
+    identity_config: web::Data<IdentityConfig>,
+) -> actix_web::Result<HttpResponse> {
+    let purpose = purpose.into_inner();
+    let (kid, material) = match purpose {
+        KeyPurpose::Access => {
+            let key = if material.trim().is_empty() {
+                AccessSigningKey::generate().map_err(error::ErrorInternalServerError)?
+            } else {
+                AccessSigningKey::from_pem(material.as_bytes()).map_err(error::ErrorBadRequest)?
+            };
+            (key.kid.clone(), key.pem().to_string())
+        }
+        KeyPurpose::Refresh => {
+            let key = if material.trim().is_empty() {
+                RefreshSigningKey::generate().map_err(error::ErrorInternalServerError)?
+            } else {
+                RefreshSigningKey::new(&uuid::Uuid::new_v4().to_string(), material.trim())
+            };
+            (key.kid.clone(), key.secret().to_string())
+        }
+    };
+
+    let mut conn = pool.get().await.map_err(error::ErrorInternalServerError)?;
+
+    let key: SigningKey = diesel::insert_into(schema::signing_key::table)
+        .values(SigningKeyInsert {
+            kid,
+            purpose,
+            material,
+            status: KeyStatus::Pending,
+            created_at: Utc::now(),
+            retires_at: None,
+        })
+        .get_result(&mut conn)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    identity_config
+        .sync_keys(&mut conn)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Created().json(SigningKeyDescription::from(key)))
+}
+
+/// Makes a key the active signing key for its purpose.
+///
+/// The previously active key keeps verifying tokens until every token it
+/// signed has expired.
+#[post("/{kid}/promote/")]
+async fn promote(
+    _: AdminKey,
+    kid: web::Path<String>,
+    pool: web::Data<DbPool>,
+    identity_config: web::Data<IdentityConfig>,
+) -> actix_web::Result<HttpResponse> {
+    let mut conn = pool.get().await.map_err(error::ErrorInternalServerError)?;
+
+    let Some(key) = find_key(&mut conn, &kid).await? else {
+        return Ok(HttpResponse::NotFound().body(format!("No key found with id {kid}")));
+    };
+
+    match key.status {
+        KeyStatus::Active => return Ok(HttpResponse::Ok().json(SigningKeyDescription::from(key))),
+        KeyStatus::Retired => return Err(error::ErrorBadRequest("Cannot promote a retired key")),
+        KeyStatus::Pending | KeyStatus::Retiring => (),
+    };
+
+    let token_lifetime = match key.purpose {
+        KeyPurpose::Access => identity_config.expires_in,
+        KeyPurpose::Refresh => identity_config.refresh_expires_in,
+    };
+    let retires_at = Utc::now() + token_lifetime;
+
+    let promoted: SigningKey = conn
+        .transaction::<_, DbError, _>(|conn| {
+            async move {
+                diesel::update(schema::signing_key::table)
+                    .filter(schema::signing_key::purpose.eq(key.purpose))
+                    .filter(schema::signing_key::status.eq(KeyStatus::Active))
+                    .set((
+                        schema::signing_key::status.eq(KeyStatus::Retiring),
+                        schema::signing_key::retires_at.eq(retires_at),
+                    ))
+                    .execute(conn)
+                    .await?;
+
+                diesel::update(schema::signing_key::table.find(key.id))
+                    .set((
+                        schema::signing_key::status.eq(KeyStatus::Active),
+                        schema::signing_key::retires_at.eq(None::<DateTime<Utc>>),
+                    ))
+                    .get_result(conn)
+                    .await
+            }
+            .scope_boxed()
+        })
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    identity_config
+        .sync_keys(&mut conn)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(SigningKeyDescription::from(promoted)))
+}
+
+/// Stops accepting tokens signed by a key immediately.
+#[post("/{kid}/retire/")]
+async fn retire(
+    _: AdminKey,
+    kid: web::Path<String>,
+    pool: web::Data<DbPool>,
+    identity_config: web::Data<IdentityConfig>,
+) -> actix_web::Result<HttpResponse> {
+    let mut conn = pool.get().await.map_err(error::ErrorInternalServerError)?;
+
+    let Some(key) = find_key(&mut conn, &kid).await? else {
+        return Ok(HttpResponse::NotFound().body(format!("No key found with id {kid}")));
+    };
+
+    if key.status == KeyStatus::Active {
+        return Err(error::ErrorBadRequest(
+            "Cannot retire the active key, promote another key first",
+        ));
+    }
+
+    let retired: SigningKey = diesel::update(schema::signing_key::table.find(key.id))
+        .set((
+            schema::signing_key::status.eq(KeyStatus::Retired),
+            schema::signing_key::retires_at.eq(Utc::now()),
+        ))
+        .get_result(&mut conn)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    identity_config
+        .sync_keys(&mut conn)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(SigningKeyDescription::from(retired)))
+}
+
+async fn find_key(
+    conn: &mut crate::db::DbConnection,
+    kid: &str,
+) -> actix_web::Result<Option<SigningKey>> {
+    schema::signing_key::table
+        .filter(schema::signing_key::kid.eq(kid))
+        .first(conn)
+        .await
+        .optional()
+        .map_err(error::ErrorInternalServerError)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::identity::Identity;
+    use crate::auth::keys::ring::KeyRing;
+    use crate::{config, db};
+    use actix_web::{test, App};
+    use chrono::Duration;
+    use std::sync::{Arc, RwLock};
+
+    use super::*;
+
+    fn new_identity_config() -> IdentityConfig {
+        IdentityConfig {
+            keys: Arc::new(RwLock::new(KeyRing::new(
+                AccessSigningKey::generate().unwrap(),
+                RefreshSigningKey::generate().unwrap(),
+            ))),
+            revocations: Arc::default(),
+            expires_in: Duration::hours(1),
+            refresh_expires_in: Duration::days(7),
+        }
+    }
+
+    #[actix_web::test]
+    async fn rotated_keys_keep_verifying_old_tokens_until_retired() {
+        let admin_key = config::ADMIN_KEY
+            .clone()
+            .expect("ADMIN_KEY must be set for tests");
+
+        let pool = db::initialize_db_pool(&config::DB_URL).await;
+        let identity_config = new_identity_config();
+        {
+            let mut conn = pool
+                .get()
+                .await
+                .expect("Failed to get a database connection");
+            identity_config
+                .sync_keys(&mut conn)
+                .await
+                .expect("Failed to seed keys");
+        }
+        let old_kid = identity_config.read_keys().active_access_key().kid.clone();
+        let old_token = Identity::from_user_id(&uuid::Uuid::new_v4())
+            .generate_token(&identity_config)
+            .value;
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool))
+                .app_data(web::Data::new(identity_config.clone()))
+                .configure(config_service),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .insert_header(("Admin-Key", admin_key.as_str()))
+            .uri("/access/")
+            .to_request();
+        let created: SigningKeyDescription = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(created.status, KeyStatus::Pending);
+        assert!(identity_config
+            .read_keys()
+            .jwks()
+            .find(&created.kid)
+            .is_some());
+        assert_eq!(identity_config.read_keys().active_access_key().kid, old_kid);
+
+        let req = test::TestRequest::post()
+            .insert_header(("Admin-Key", admin_key.as_str()))
+            .uri(&format!("/{}/promote/", created.kid))
+            .to_request();
+        let promoted: SigningKeyDescription = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(promoted.status, KeyStatus::Active);
+        assert_eq!(
+            identity_config.read_keys().active_access_key().kid,
+            created.kid
+        );
+        assert!(Identity::from_token(&identity_config, &old_token).is_ok());
+
+        let req = test::TestRequest::post()
+            .insert_header(("Admin-Key", admin_key.as_str()))
+            .uri(&format!("/{}/retire/", old_kid))
+            .to_request();
+        let retired: SigningKeyDescription = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(retired.status, KeyStatus::Retired);
+        assert!(Identity::from_token(&identity_config, &old_token).is_err());
+    }
+
+    #[actix_web::test]
+    async fn active_key_cannot_be_retired() {
+        let admin_key = config::ADMIN_KEY
+            .clone()
+            .expect("ADMIN_KEY must be set for tests");
+
+        let pool = db::initialize_db_pool(&config::DB_URL).await;
+        let identity_config = new_identity_config();
+        {
+            let mut conn = pool
+                .get()
+                .await
+                .expect("Failed to get a database connection");
+            identity_config
+                .sync_keys(&mut conn)
+                .await
+                .expect("Failed to seed keys");
+        }
+        let active_kid = identity_config.read_keys().active_refresh_key().kid.clone();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool))
+                .app_data(web::Data::new(identity_config))
+                .configure(config_service),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .insert_header(("Admin-Key", admin_key.as_str()))
+            .uri(&format!("/{active_kid}/retire/"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn admin_routes_require_the_admin_key() {
+        let pool = db::initialize_db_pool(&config::DB_URL).await;
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool))
+                .app_data(web::Data::new(new_identity_config()))
+                .configure(config_service),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .insert_header(("Admin-Key", "wrong"))
+            .uri("/")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+    }
+}
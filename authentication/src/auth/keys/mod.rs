@@ -0,0 +1,176 @@
+pub mod access;
+pub mod admin;
+pub mod ring;
+
+use crate::auth::identity::IdentityConfig;
+use crate::{diesel_insertable, schema};
+use actix_web::{get, web, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use diesel::{
+    deserialize::{FromSql, FromSqlRow},
+    expression::AsExpression,
+    pg::Pg,
+    prelude::*,
+    serialize::ToSql,
+    sql_types,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+pub fn config_service(cfg: &mut web::ServiceConfig) {
+    cfg.service(jwks);
+}
+
+/// Publishes the public half of every access token signing key that is still
+/// accepted, so that other services can verify access tokens without being
+/// able to mint them.
+#[get("/jwks.json/")]
+async fn jwks(identity_config: web::Data<IdentityConfig>) -> impl Responder {
+    HttpResponse::Ok().json(identity_config.read_keys().jwks())
+}
+
+diesel_insertable! {
+    #[derive(Queryable, Selectable, Insertable, AsChangeset)]
+    #[diesel(table_name = schema::signing_key)]
+    #[diesel(check_for_backend(Pg))]
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct SigningKey {
+        pub kid: String,
+        pub purpose: KeyPurpose,
+        pub material: String,
+        pub status: KeyStatus,
+        pub created_at: DateTime<Utc>,
+        pub retires_at: Option<DateTime<Utc>>,
+    }
+}
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, AsExpression, FromSqlRow,
+)]
+#[diesel(sql_type = sql_types::Text)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyPurpose {
+    Access,
+    Refresh,
+}
+
+impl FromSql<sql_types::Text, Pg> for KeyPurpose {
+    fn from_sql(
+        bytes: <Pg as diesel::backend::Backend>::RawValue<'_>,
+    ) -> diesel::deserialize::Result<Self> {
+        match String::from_sql(bytes)?.as_ref() {
+            "access" => Ok(KeyPurpose::Access),
+            "refresh" => Ok(KeyPurpose::Refresh),
+            _ => Err("Unknown `KeyPurpose` received".into()),
+        }
+    }
+}
+
+impl ToSql<sql_types::Text, Pg> for KeyPurpose {
+    fn to_sql<'b>(
+        &'b self,
+        out: &mut diesel::serialize::Output<'b, '_, Pg>,
+    ) -> diesel::serialize::Result {
+        <str as ToSql<sql_types::Text, Pg>>::to_sql(
+            match self {
+                KeyPurpose::Access => "access",
+                KeyPurpose::Refresh => "refresh",
+            },
+            out,
+        )
+    }
+}
+
+/// The lifecycle of a signing key.
+///
+/// A key is created as `Pending` so that it can be distributed before it is
+/// used. Promoting it makes it `Active`, and the previously active key becomes
+/// `Retiring` until every token it signed has expired. `Retired` keys are no
+/// longer accepted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[diesel(sql_type = sql_types::Text)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyStatus {
+    Pending,
+    Active,
+    Retiring,
+    Retired,
+}
+
+impl FromSql<sql_types::Text, Pg> for KeyStatus {
+    fn from_sql(
+        bytes: <Pg as diesel::backend::Backend>::RawValue<'_>,
+    ) -> diesel::deserialize::Result<Self> {
+        match String::from_sql(bytes)?.as_ref() {
+            "pending" => Ok(KeyStatus::Pending),
+            "active" => Ok(KeyStatus::Active),
+            "retiring" => Ok(KeyStatus::Retiring),
+            "retired" => Ok(KeyStatus::Retired),
+            _ => Err("Unknown `KeyStatus` received".into()),
+        }
+    }
+}
+
+impl ToSql<sql_types::Text, Pg> for KeyStatus {
+    fn to_sql<'b>(
+        &'b self,
+        out: &mut diesel::serialize::Output<'b, '_, Pg>,
+    ) -> diesel::serialize::Result {
+        <str as ToSql<sql_types::Text, Pg>>::to_sql(
+            match self {
+                KeyStatus::Pending => "pending",
+                KeyStatus::Active => "active",
+                KeyStatus::Retiring => "retiring",
+                KeyStatus::Retired => "retired",
+            },
+            out,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::identity::Identity;
+    use crate::config;
+    use actix_web::{test, App};
+    use jsonwebtoken::jwk::JwkSet;
+    use jsonwebtoken::{DecodingKey, Validation};
+
+    use super::*;
+
+    #[actix_web::test]
+    async fn jwks_verifies_issued_access_tokens() {
+        let identity_config = web::Data::new(config::IDENTITY_CONFIG.clone());
+
+        let app =
+            test::init_service(App::new().app_data(identity_config.clone()).service(jwks)).await;
+
+        let req = test::TestRequest::get().uri("/jwks.json/").to_request();
+        let key_set: JwkSet = test::call_and_read_body_json(&app, req).await;
+
+        let user_id = Uuid::new_v4();
+        let token = Identity::from_user_id(&user_id)
+            .generate_token(&identity_config)
+            .value;
+
+        let header = jsonwebtoken::decode_header(&token).expect("Failed to decode header");
+        let kid = header.kid.expect("Access tokens should have a key id");
+        let jwk = key_set
+            .find(&kid)
+            .expect("The signing key should be published");
+
+        #[derive(serde::Deserialize)]
+        struct Claims {
+            sub: Uuid,
+        }
+
+        let claims = jsonwebtoken::decode::<Claims>(
+            &token,
+            &DecodingKey::from_jwk(jwk).expect("Failed to read JWK"),
+            &Validation::new(header.alg),
+        )
+        .expect("Failed to verify token with published key")
+        .claims;
+        assert_eq!(claims.sub, user_id);
+    }
+}
@@ -0,0 +1,399 @@
+use crate::auth::keys::access::AccessSigningKey;
+use crate::auth::keys::{KeyPurpose, KeyStatus, SigningKey, SigningKeyInsert};
+use crate::db::DbConnection;
+use crate::schema;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL_SAFE_NO_PAD;
+use base64::prelude::*;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{DecodingKey, EncodingKey};
+use std::collections::HashMap;
+use std::fmt;
+use uuid::Uuid;
+
+/// A shared secret used to sign refresh tokens.
+///
+/// Refresh tokens are only ever verified by the authentication server, so they
+/// do not need an asymmetric key.
+#[derive(Clone)]
+pub struct RefreshSigningKey {
+    pub kid: String,
+    secret: String,
+}
+
+impl RefreshSigningKey {
+    pub fn new(kid: &str, secret: &str) -> Self {
+        RefreshSigningKey {
+            kid: kid.to_string(),
+            secret: secret.to_string(),
+        }
+    }
+
+    /// Generates a new random 256-bit secret.
+    pub fn generate() -> Result<Self, Box<dyn std::error::Error>> {
+        let mut secret = [0; 32];
+        openssl::rand::rand_bytes(&mut secret)?;
+        Ok(RefreshSigningKey::new(
+            &Uuid::new_v4().to_string(),
+            &BASE64_URL_SAFE_NO_PAD.encode(secret),
+        ))
+    }
+
+    pub fn secret(&self) -> &str {
+        &self.secret
+    }
+
+    pub fn encoding_key(&self) -> EncodingKey {
+        EncodingKey::from_secret(self.secret.as_ref())
+    }
+
+    pub fn decoding_key(&self) -> DecodingKey {
+        DecodingKey::from_secret(self.secret.as_ref())
+    }
+}
+
+impl fmt::Debug for RefreshSigningKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RefreshSigningKey")
+            .field("kid", &self.kid)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Clone)]
+struct RingEntry<K> {
+    key: K,
+    status: KeyStatus,
+    retires_at: Option<DateTime<Utc>>,
+}
+
+impl<K> RingEntry<K> {
+    fn is_accepted(&self) -> bool {
+        match self.status {
+            KeyStatus::Pending | KeyStatus::Active => true,
+            KeyStatus::Retiring => self.retires_at.is_none_or(|t| Utc::now() < t),
+            KeyStatus::Retired => false,
+        }
+    }
+}
+
+/// All keys that are currently used to sign or verify tokens.
+///
+/// Each purpose has exactly one active key that new tokens are signed with.
+/// Tokens carry the `kid` of the key that signed them in their header, so
+/// tokens signed by a key that is being rotated out remain valid until the key
+/// retires.
+#[derive(Debug, Clone)]
+pub struct KeyRing {
+    access: HashMap<String, RingEntry<AccessSigningKey>>,
+    active_access_kid: String,
+    refresh: HashMap<String, RingEntry<RefreshSigningKey>>,
+    active_refresh_kid: String,
+}
+
+impl KeyRing {
+    pub fn new(access_key: AccessSigningKey, refresh_key: RefreshSigningKey) -> Self {
+        let active_access_kid = access_key.kid.clone();
+        let active_refresh_kid = refresh_key.kid.clone();
+        KeyRing {
+            access: HashMap::from([(
+                active_access_kid.clone(),
+                RingEntry {
+                    key: access_key,
+                    status: KeyStatus::Active,
+                    retires_at: None,
+                },
+            )]),
+            active_access_kid,
+            refresh: HashMap::from([(
+                active_refresh_kid.clone(),
+                RingEntry {
+                    key: refresh_key,
+                    status: KeyStatus::Active,
+                    retires_at: None,
+                },
+            )]),
+            active_refresh_kid,
+        }
+    }
+
+    fn from_rows(rows: Vec<SigningKey>) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut access = HashMap::new();
+        let mut active_access_kid = None;
+        let mut refresh = HashMap::new();
+        let mut active_refresh_kid = None;
+
+        for row in rows {
+            match row.purpose {
+                KeyPurpose::Access => {
+                    let key = AccessSigningKey::from_pem(row.material.as_bytes())?;
+                    if row.status == KeyStatus::Active {
+                        active_access_kid = Some(row.kid.clone());
+                    }
+                    access.insert(
+                        row.kid,
+                        RingEntry {
+                            key,
+                            status: row.status,
+                            retires_at: row.retires_at,
+                        },
+                    );
+                }
+                KeyPurpose::Refresh => {
+                    let key = RefreshSigningKey::new(&row.kid, &row.material);
+                    if row.status == KeyStatus::Active {
+                        active_refresh_kid = Some(row.kid.clone());
+                    }
+                    refresh.insert(
+                        row.kid,
+                        RingEntry {
+                            key,
+                            status: row.status,
+                            retires_at: row.retires_at,
+                        },
+                    );
+                }
+            }
+        }
+
+        Ok(KeyRing {
+            access,
+            active_access_kid: active_access_kid.ok_or("No active access token key")?,
+            refresh,
+            active_refresh_kid: active_refresh_kid.ok_or("No active refresh token key")?,
+        })
+    }
+
+    /// Replaces this key ring with the keys stored in the database.
+    ///
+    /// If the database has no active key for a purpose, this key ring's active
+    /// key for that purpose is stored first. This lets the keys from the
+    /// environment seed the database on the first start.
+    pub async fn sync(
+        &mut self,
+        conn: &mut DbConnection,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let now = Utc::now();
+        let seeds = [
+            SigningKeyInsert {
+                kid: self.active_access_key().kid.clone(),
+                purpose: KeyPurpose::Access,
+                material: self.active_access_key().pem().to_string(),
+                status: KeyStatus::Active,
+                created_at: now,
+                retires_at: None,
+            },
+            SigningKeyInsert {
+                kid: self.active_refresh_key().kid.clone(),
+                purpose: KeyPurpose::Refresh,
+                material: self.active_refresh_key().secret().to_string(),
+                status: KeyStatus::Active,
+                created_at: now,
+                retires_at: None,
+            },
+        ];
+
+        for seed in seeds {
+            let has_active_key: bool = diesel::select(diesel::dsl::exists(
+                schema::signing_key::table
+                    .filter(schema::signing_key::purpose.eq(seed.purpose))
+                    .filter(schema::signing_key::status.eq(KeyStatus::Active)),
+            ))
+            .get_result(conn)
+            .await?;
+
+            if !has_active_key {
+                diesel::insert_into(schema::signing_key::table)
+                    .values(&seed)
+                    .on_conflict_do_nothing()
+                    .execute(conn)
+                    .await?;
+            }
+        }
+
+        let rows: Vec<SigningKey> = schema::signing_key::table
+            .filter(schema::signing_key::status.ne(KeyStatus::Retired))
+            .get_results(conn)
+            .await?;
+
+        *self = KeyRing::from_rows(rows)?;
+        Ok(())
+    }
+
+    pub fn active_access_key(&self) -> &AccessSigningKey {
+        &self.access[&self.active_access_kid].key
+    }
+
+    /// The access token key with the given id, if it is still accepted.
+    pub fn access_key(&self, kid: &str) -> Option<&AccessSigningKey> {
+        self.access
+            .get(kid)
+            .filter(|entry| entry.is_accepted())
+            .map(|entry| &entry.key)
+    }
+
+    pub fn active_refresh_key(&self) -> &RefreshSigningKey {
+        &self.refresh[&self.active_refresh_kid].key
+    }
+
+    /// The refresh token key with the given id, if it is still accepted.
+    ///
+    /// Refresh tokens issued before key rotation have no key id and are
+    /// verified with the active key.
+    pub fn refresh_key(&self, kid: Option<&str>) -> Option<&RefreshSigningKey> {
+        let kid = kid.unwrap_or(&self.active_refresh_kid);
+        self.refresh
+            .get(kid)
+            .filter(|entry| entry.is_accepted())
+            .map(|entry| &entry.key)
+    }
+
+    /// The public keys of every accepted access token key.
+    pub fn jwks(&self) -> JwkSet {
+        JwkSet {
+            keys: self
+                .access
+                .values()
+                .filter(|entry| entry.is_accepted())
+                .map(|entry| entry.key.jwk().clone())
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    fn row(
+        purpose: KeyPurpose,
+        kid: &str,
+        material: &str,
+        status: KeyStatus,
+        retires_at: Option<DateTime<Utc>>,
+    ) -> SigningKey {
+        SigningKey {
+            id: Uuid::new_v4(),
+            kid: kid.to_string(),
+            purpose,
+            material: material.to_string(),
+            status,
+            created_at: Utc::now(),
+            retires_at,
+        }
+    }
+
+    fn ring_with_access_keys(rows: Vec<SigningKey>) -> KeyRing {
+        let mut rows = rows;
+        rows.push(row(
+            KeyPurpose::Refresh,
+            "refresh",
+            "secret",
+            KeyStatus::Active,
+            None,
+        ));
+        KeyRing::from_rows(rows).expect("Failed to build key ring")
+    }
+
+    #[actix_web::test]
+    async fn retiring_keys_are_accepted_until_they_retire() {
+        let active = AccessSigningKey::generate().unwrap();
+        let retiring = AccessSigningKey::generate().unwrap();
+        let expired = AccessSigningKey::generate().unwrap();
+
+        let ring = ring_with_access_keys(vec![
+            row(
+                KeyPurpose::Access,
+                &active.kid,
+                active.pem(),
+                KeyStatus::Active,
+                None,
+            ),
+            row(
+                KeyPurpose::Access,
+                &retiring.kid,
+                retiring.pem(),
+                KeyStatus::Retiring,
+                Some(Utc::now() + Duration::hours(1)),
+            ),
+            row(
+                KeyPurpose::Access,
+                &expired.kid,
+                expired.pem(),
+                KeyStatus::Retiring,
+                Some(Utc::now() - Duration::hours(1)),
+            ),
+        ]);
+
+        assert_eq!(ring.active_access_key().kid, active.kid);
+        assert!(ring.access_key(&active.kid).is_some());
+        assert!(ring.access_key(&retiring.kid).is_some());
+        assert!(ring.access_key(&expired.kid).is_none());
+        assert_eq!(ring.jwks().keys.len(), 2);
+    }
+
+    #[actix_web::test]
+    async fn pending_keys_are_published_but_not_used_for_signing() {
+        let active = AccessSigningKey::generate().unwrap();
+        let pending = AccessSigningKey::generate().unwrap();
+
+        let ring = ring_with_access_keys(vec![
+            row(
+                KeyPurpose::Access,
+                &active.kid,
+                active.pem(),
+                KeyStatus::Active,
+                None,
+            ),
+            row(
+                KeyPurpose::Access,
+                &pending.kid,
+                pending.pem(),
+                KeyStatus::Pending,
+                None,
+            ),
+        ]);
+
+        assert_eq!(ring.active_access_key().kid, active.kid);
+        assert!(ring.jwks().find(&pending.kid).is_some());
+    }
+
+    #[actix_web::test]
+    async fn refresh_tokens_without_key_id_use_the_active_key() {
+        let ring = KeyRing::new(
+            AccessSigningKey::generate().unwrap(),
+            RefreshSigningKey::new("refresh", "secret"),
+        );
+
+        assert_eq!(ring.refresh_key(None).unwrap().kid, "refresh");
+        assert!(ring.refresh_key(Some("unknown")).is_none());
+    }
+
+    #[actix_web::test]
+    async fn key_ring_requires_an_active_key_for_each_purpose() {
+        let pending = AccessSigningKey::generate().unwrap();
+
+        let result = KeyRing::from_rows(vec![
+            row(
+                KeyPurpose::Access,
+                &pending.kid,
+                pending.pem(),
+                KeyStatus::Pending,
+                None,
+            ),
+            row(
+                KeyPurpose::Refresh,
+                "refresh",
+                "secret",
+                KeyStatus::Active,
+                None,
+            ),
+        ]);
+
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,249 @@
+use crate::auth::device::DeviceInfo;
+use crate::auth::identity::{Identity, IdentityConfig};
+use crate::auth::provider::{AuthProviderInsert, AuthProviderType, IntoAuthProviderInsert};
+use crate::auth::{create_new_user, generate_sign_in_success_response, Token};
+use crate::db::{DbConnection, DbError};
+use crate::schema;
+use crate::user::{User, UserWithAuthProviders};
+use actix_web::{error, HttpResponse};
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use diesel::result::DatabaseErrorKind;
+use diesel_async::RunQueryDsl;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+const PENDING_LINK_AUDIENCE: &str = "pending_link";
+
+/// How long a user has to decide between linking and creating an account.
+const PENDING_LINK_EXPIRES_IN_MINUTES: i64 = 10;
+
+/// A provider that was signed in with but not yet attached to a user, because
+/// its email matches existing users.
+///
+/// The claims are carried by the client in a short-lived token signed with the
+/// refresh token key, so the provider does not have to be verified again when
+/// the user decides.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PendingLinkClaims {
+    aud: String,
+    iat: u64,
+    exp: u64,
+    /// The users that the provider may be linked to.
+    pub user_ids: Vec<Uuid>,
+    /// The provider to attach, without a user.
+    pub provider: AuthProviderInsert,
+}
+
+impl PendingLinkClaims {
+    pub fn new(provider: AuthProviderInsert, user_ids: Vec<Uuid>) -> Self {
+        let now = Utc::now();
+        PendingLinkClaims {
+            aud: PENDING_LINK_AUDIENCE.to_string(),
+            iat: now.timestamp() as u64,
+            exp: (now + Duration::minutes(PENDING_LINK_EXPIRES_IN_MINUTES)).timestamp() as u64,
+            user_ids,
+            provider,
+        }
+    }
+
+    pub fn generate_token(&self, config: &IdentityConfig) -> Token {
+        Token {
+            value: config.encode_client_claims(self),
+            expires_at: chrono::DateTime::from_timestamp(self.exp as i64, 0)
+                .expect("Expiry should be a valid timestamp"),
+        }
+    }
+
+    pub fn decode(config: &IdentityConfig, token: &str) -> Result<Self, error::Error> {
+        config.decode_client_claims(token, PENDING_LINK_AUDIENCE)
+    }
+}
+
+/// The order to give a provider that is added to a user after their existing
+/// providers.
+pub async fn next_provider_order(conn: &mut DbConnection, user_id: &Uuid) -> Result<i16, DbError> {
+    let max_order: Option<i16> = schema::auth_provider::table
+        .filter(schema::auth_provider::user_id.eq(user_id))
+        .select(diesel::dsl::max(schema::auth_provider::order))
+        .first(conn)
+        .await?;
+    Ok(max_order.map_or(0, |order| order + 1))
+}
+
+/// The user that a provider is attached to, if any.
+pub async fn find_provider_owner(
+    conn: &mut DbConnection,
+    provider_type: &AuthProviderType,
+    provider_id: &str,
+) -> Result<Option<Uuid>, DbError> {
+    schema::auth_provider::table
+        .filter(schema::auth_provider::provider_type.eq(provider_type))
+        .filter(schema::auth_provider::provider_id.eq(provider_id))
+        .select(schema::auth_provider::user_id)
+        .first(conn)
+        .await
+        .optional()
+}
+
+/// Attaches a provider to an existing user after their existing providers.
+///
+/// Fails with a conflict if the provider already belongs to another user.
+/// Linking a provider that already belongs to the user changes nothing.
+pub async fn link_provider<I: IntoAuthProviderInsert>(
+    conn: &mut DbConnection,
+    user_id: &Uuid,
+    provider_info: &I,
+) -> actix_web::Result<UserWithAuthProviders> {
+    let Some(user): Option<User> = schema::user::table
+        .find(user_id)
+        .first(conn)
+        .await
+        .optional()
+        .map_err(error::ErrorInternalServerError)?
+    else {
+        return Err(error::ErrorNotFound(format!(
+            "No user found with id {user_id}"
+        )));
+    };
+
+    let provider_insert = provider_info.into_provider_insert(&user);
+    let owner = find_provider_owner(
+        conn,
+        &provider_insert.provider_type,
+        &provider_insert.provider_id,
+    )
+    .await
+    .map_err(error::ErrorInternalServerError)?;
+
+    match owner {
+        Some(owner) if owner != user.id => {
+            return Err(error::ErrorConflict(
+                "The provider is already linked to another user",
+            ));
+        }
+        Some(_) => {}
+        None => {
+            let order = next_provider_order(conn, &user.id)
+                .await
+                .map_err(error::ErrorInternalServerError)?;
+            diesel::insert_into(schema::auth_provider::table)
+                .values(AuthProviderInsert {
+                    order,
+                    ..provider_insert
+                })
+                .execute(conn)
+                .await
+                .map_err(|err| match err {
+                    DbError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+                        error::ErrorConflict("The provider is already linked to another user")
+                    }
+                    err => error::ErrorInternalServerError(err),
+                })?;
+        }
+    }
+
+    let providers = user
+        .get_providers(conn)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+    Ok(UserWithAuthProviders { user, providers })
+}
+
+/// Attaches the provider of a pending link token to the caller and signs them
+/// in.
+///
+/// The caller proves that they own one of the matched users by signing in with
+/// one of that user's providers first.
+pub async fn link_pending(
+    conn: &mut DbConnection,
+    identity_config: &IdentityConfig,
+    identity: &Identity,
+    link_token: &str,
+    device: &DeviceInfo,
+) -> actix_web::Result<HttpResponse> {
+    let claims = PendingLinkClaims::decode(identity_config, link_token)?;
+    if !claims.user_ids.contains(&identity.user_id) {
+        return Err(error::ErrorForbidden(
+            "The provider cannot be linked to this user",
+        ));
+    }
+
+    let user_with_providers = link_provider(conn, &identity.user_id, &claims.provider).await?;
+    generate_sign_in_success_response(
+        conn,
+        user_with_providers,
+        &claims.provider.provider_type,
+        identity_config,
+        device,
+    )
+    .await
+}
+
+/// Creates a separate user for the provider of a pending link token instead
+/// of linking it, and signs them in.
+pub async fn create_pending(
+    conn: &mut DbConnection,
+    identity_config: &IdentityConfig,
+    link_token: &str,
+    device: &DeviceInfo,
+) -> actix_web::Result<HttpResponse> {
+    let claims = PendingLinkClaims::decode(identity_config, link_token)?;
+
+    if find_provider_owner(
+        conn,
+        &claims.provider.provider_type,
+        &claims.provider.provider_id,
+    )
+    .await
+    .map_err(error::ErrorInternalServerError)?
+    .is_some()
+    {
+        return Err(error::ErrorConflict(
+            "The provider is already linked to a user",
+        ));
+    }
+
+    let new_user = create_new_user(conn, &claims.provider)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    generate_sign_in_success_response(
+        conn,
+        new_user,
+        &claims.provider.provider_type,
+        identity_config,
+        device,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::refresh::session::{RefreshResult, RefreshSession};
+    use crate::{config, db};
+
+    use super::*;
+
+    #[actix_web::test]
+    async fn link_token_is_not_a_refresh_token() {
+        let pool = db::initialize_db_pool(&config::DB_URL).await;
+        let mut conn = pool
+            .get()
+            .await
+            .expect("Failed to get a database connection");
+
+        let link_token = PendingLinkClaims::new(AuthProviderInsert::default(), vec![])
+            .generate_token(&config::IDENTITY_CONFIG)
+            .value;
+        let result = RefreshSession::refresh(
+            &mut conn,
+            &config::IDENTITY_CONFIG,
+            &link_token,
+            &DeviceInfo::default(),
+        )
+        .await
+        .expect("Failed to refresh session");
+        assert_eq!(result, RefreshResult::TokenDecodeFailure);
+    }
+}
@@ -1,34 +1,187 @@
+pub mod device;
+pub mod event;
 pub mod game_center;
+pub mod guest;
 pub mod identity;
+pub mod keys;
+pub mod link;
 pub mod oauth2;
+pub mod oidc;
+pub mod password;
 pub mod play_games;
 pub mod provider;
 pub mod refresh;
+pub mod revocation;
+pub mod sessions;
+pub mod sign_in_error;
 pub mod steam;
 pub mod token;
 
+use crate::auth::device::DeviceInfo;
+use crate::auth::event::{AuthEventInsert, AuthEventKind, AuthEventOutcome};
 use crate::auth::identity::{Identity, IdentityConfig};
-use crate::auth::provider::{AuthProvider, IntoAuthProviderInsert};
+use crate::auth::provider::{AuthProvider, AuthProviderType, IntoAuthProviderInsert};
 use crate::auth::refresh::session::RefreshSession;
+use crate::auth::revocation::RevocationKind;
+use crate::config::RateLimitConfig;
+use crate::db::DbPool;
+use crate::rate_limit::{CredentialKey, RateLimitRule};
 use crate::schema;
 use crate::user::{User, UserInsert};
 use crate::{db::DbConnection, user::UserWithAuthProviders};
-use actix_web::{cookie, error, post, web, HttpResponse, Responder};
+use actix_web::{cookie, error, post, web, HttpResponse};
 use chrono::{DateTime, Utc};
 use diesel_async::RunQueryDsl;
 use serde::Serialize;
 
 pub fn config_service(cfg: &mut web::ServiceConfig) {
     cfg.service(sign_out)
+        .service(sign_out_everywhere)
         .service(refresh::refresh)
+        .service(web::scope("/sessions").configure(sessions::config_service))
+        .service(web::scope("/revocations").configure(revocation::config_service))
         .service(web::scope("/oauth2").configure(oauth2::config_service))
+        .service(web::scope("/oidc").configure(oidc::config_service))
+        .service(web::scope("/password").configure(password::config_service))
+        .service(web::scope("/guest").configure(guest::config_service))
         .service(web::scope("/game-center").configure(game_center::config_service))
         .service(web::scope("/play-games").configure(play_games::config_service))
         .service(web::scope("/steam").configure(steam::config_service));
 }
 
+/// The rate limits of the `/auth/` routes. Sign-in attempts with the same
+/// credential are limited across IPs, to protect the upstream providers and
+/// to slow down password guessing.
+pub fn rate_limit_rules(config: &RateLimitConfig) -> Vec<RateLimitRule> {
+    vec![
+        RateLimitRule::new(
+            "password",
+            &["/auth/password/sign-in/", "/auth/password/link/"],
+        )
+        .per_ip(config.sign_in.per_ip)
+        .per_credential(
+            CredentialKey::JsonField("email"),
+            config.sign_in.per_credential,
+        ),
+        RateLimitRule::new(
+            "mail",
+            &[
+                "/auth/password/sign-up/",
+                "/auth/password/verify-email/request/",
+                "/auth/password/reset-password/request/",
+            ],
+        )
+        .per_ip(config.mail.per_ip)
+        .per_credential(
+            CredentialKey::JsonField("email"),
+            config.mail.per_credential,
+        ),
+        RateLimitRule::new(
+            "sign_in",
+            &[
+                "/auth/{provider}/sign-in/",
+                "/auth/{provider}/link/",
+                "/auth/oidc/{issuer}/sign-in/",
+                "/auth/oidc/{issuer}/link/",
+                "/auth/oauth2/callback/",
+            ],
+        )
+        .per_ip(config.sign_in.per_ip)
+        .per_credential(CredentialKey::Body, config.sign_in.per_credential),
+        RateLimitRule::new("refresh", &["/auth/refresh/"])
+            .per_ip(config.refresh.per_ip)
+            .per_credential(
+                CredentialKey::JsonField("refresh_token"),
+                config.refresh.per_credential,
+            ),
+    ]
+}
+
+/// Signs out of the caller's session so that its refresh and access tokens can
+/// no longer be used.
 #[post("/sign-out/")]
-async fn sign_out(_: identity::Identity) -> impl Responder {
+async fn sign_out(
+    identity: Identity,
+    pool: web::Data<DbPool>,
+    identity_config: web::Data<IdentityConfig>,
+    device: DeviceInfo,
+) -> actix_web::Result<HttpResponse> {
+    let mut conn = pool.get().await.map_err(error::ErrorInternalServerError)?;
+
+    if let Some(session_id) = identity.session_id {
+        RefreshSession::invalidate(&mut conn, &session_id)
+            .await
+            .map_err(error::ErrorInternalServerError)?;
+        revocation::revoke(
+            &mut conn,
+            &identity_config,
+            RevocationKind::Session,
+            &session_id,
+        )
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+    } else if let Some(token_id) = identity.token_id {
+        revocation::revoke(
+            &mut conn,
+            &identity_config,
+            RevocationKind::Token,
+            &token_id,
+        )
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+    }
+
+    let event = AuthEventInsert::new(
+        identity.user_id,
+        AuthEventKind::SignOut,
+        AuthEventOutcome::Success,
+        &device,
+    );
+    match identity.session_id {
+        Some(session_id) => event.session_id(session_id),
+        None => event,
+    }
+    .record(&mut conn)
+    .await
+    .map_err(error::ErrorInternalServerError)?;
+
+    Ok(sign_out_response())
+}
+
+/// Signs out of every session of the caller, on all of their devices.
+#[post("/sign-out/everywhere/")]
+async fn sign_out_everywhere(
+    identity: Identity,
+    pool: web::Data<DbPool>,
+    identity_config: web::Data<IdentityConfig>,
+    device: DeviceInfo,
+) -> actix_web::Result<HttpResponse> {
+    let mut conn = pool.get().await.map_err(error::ErrorInternalServerError)?;
+    RefreshSession::invalidate_all(&mut conn, &identity.user_id)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+    revocation::revoke(
+        &mut conn,
+        &identity_config,
+        RevocationKind::User,
+        &identity.user_id,
+    )
+    .await
+    .map_err(error::ErrorInternalServerError)?;
+    AuthEventInsert::new(
+        identity.user_id,
+        AuthEventKind::SignOutEverywhere,
+        AuthEventOutcome::Success,
+        &device,
+    )
+    .record(&mut conn)
+    .await
+    .map_err(error::ErrorInternalServerError)?;
+
+    Ok(sign_out_response())
+}
+
+fn sign_out_response() -> HttpResponse {
     let clear_access = cookie::Cookie::build("access_token", "")
         .path("/")
         .max_age(cookie::time::Duration::seconds(-1))
@@ -52,7 +205,19 @@ async fn sign_out(_: identity::Identity) -> impl Responder {
 #[serde(tag = "type", content = "payload", rename_all = "snake_case")]
 pub enum SignInResult {
     Success(SignInSuccess),
-    PendingLinkOrCreate(Vec<UserWithAuthProviders>),
+    PendingLinkOrCreate(PendingLinkOrCreate),
+}
+
+/// The provider that was signed in with matches existing users by email.
+///
+/// The client either links the provider to one of `users` at
+/// `/auth/oauth2/link/` or creates a new user at `/auth/oauth2/create/`, with
+/// `link_token`.
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(test, derive(serde::Deserialize, PartialEq))]
+pub struct PendingLinkOrCreate {
+    pub link_token: Token,
+    pub users: Vec<UserWithAuthProviders>,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -70,44 +235,93 @@ pub struct Token {
     pub expires_at: DateTime<Utc>,
 }
 
-pub async fn generate_sign_in_success_response(
+pub async fn generate_sign_in_success(
     conn: &mut DbConnection,
     user_with_providers: UserWithAuthProviders,
+    provider_type: &AuthProviderType,
     identity_config: &IdentityConfig,
-) -> actix_web::Result<HttpResponse> {
-    let identity = Identity {
-        user_id: user_with_providers.user.id,
-    };
-    let access_token = identity.generate_token(identity_config);
-    let sign_in_cookie = cookie::Cookie::build("access_token", access_token.value.to_owned())
-        .path("/")
-        .max_age(cookie::time::Duration::seconds(
-            identity_config.expires_in.num_seconds(),
-        ))
-        .http_only(true)
-        .finish();
-
+    device: &DeviceInfo,
+) -> actix_web::Result<SignInSuccess> {
     let refresh_session =
-        RefreshSession::create(conn, &identity_config, &user_with_providers.user.id)
+        RefreshSession::create(conn, identity_config, &user_with_providers.user.id, device)
             .await
             .map_err(error::ErrorInternalServerError)?;
-    let refresh_token = refresh_session.generate_token(&identity_config);
-    let refresh_cookie = cookie::Cookie::build("refresh_token", refresh_token.value.to_owned())
-        .path("/")
-        .max_age(cookie::time::Duration::seconds(
-            identity_config.refresh_expires_in.num_seconds(),
-        ))
-        .http_only(true)
-        .finish();
+    AuthEventInsert::new(
+        user_with_providers.user.id,
+        AuthEventKind::SignIn,
+        AuthEventOutcome::Success,
+        device,
+    )
+    .provider_type(provider_type)
+    .session_id(refresh_session.id)
+    .record(conn)
+    .await
+    .map_err(error::ErrorInternalServerError)?;
+
+    Ok(SignInSuccess {
+        access_token: Identity::from_session(&refresh_session).generate_token(identity_config),
+        refresh_token: refresh_session.generate_token(identity_config),
+        user: user_with_providers,
+    })
+}
+
+/// The `access_token` and `refresh_token` cookies of a sign-in.
+pub fn sign_in_cookies(
+    success: &SignInSuccess,
+    identity_config: &IdentityConfig,
+) -> [cookie::Cookie<'static>; 2] {
+    let sign_in_cookie =
+        cookie::Cookie::build("access_token", success.access_token.value.to_owned())
+            .path("/")
+            .max_age(cookie::time::Duration::seconds(
+                identity_config.expires_in.num_seconds(),
+            ))
+            .http_only(true)
+            .finish();
+
+    let refresh_cookie =
+        cookie::Cookie::build("refresh_token", success.refresh_token.value.to_owned())
+            .path("/")
+            .max_age(cookie::time::Duration::seconds(
+                identity_config.refresh_expires_in.num_seconds(),
+            ))
+            .http_only(true)
+            .finish();
 
-    Ok(HttpResponse::Ok()
-        .cookie(sign_in_cookie)
-        .cookie(refresh_cookie)
-        .json(SignInResult::Success(SignInSuccess {
-            access_token,
-            refresh_token,
-            user: user_with_providers,
-        })))
+    [sign_in_cookie, refresh_cookie]
+}
+
+/// Responds with a sign-in result, and sets the sign-in cookies if it
+/// succeeded.
+pub fn sign_in_response(result: SignInResult, identity_config: &IdentityConfig) -> HttpResponse {
+    let mut response = HttpResponse::Ok();
+    if let SignInResult::Success(success) = &result {
+        for cookie in sign_in_cookies(success, identity_config) {
+            response.cookie(cookie);
+        }
+    }
+    response.json(result)
+}
+
+pub async fn generate_sign_in_success_response(
+    conn: &mut DbConnection,
+    user_with_providers: UserWithAuthProviders,
+    provider_type: &AuthProviderType,
+    identity_config: &IdentityConfig,
+    device: &DeviceInfo,
+) -> actix_web::Result<HttpResponse> {
+    let success = generate_sign_in_success(
+        conn,
+        user_with_providers,
+        provider_type,
+        identity_config,
+        device,
+    )
+    .await?;
+    Ok(sign_in_response(
+        SignInResult::Success(success),
+        identity_config,
+    ))
 }
 
 pub async fn create_new_user<I>(
@@ -132,3 +346,157 @@ where
         providers: vec![provider],
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::refresh::session::RefreshResult;
+    use crate::{config, db};
+    use actix_web::{http::header::AUTHORIZATION, test, App};
+    use uuid::Uuid;
+
+    use super::*;
+
+    async fn create_sessions(pool: &DbPool, count: usize) -> Vec<RefreshSession> {
+        let user = User {
+            id: Uuid::new_v4(),
+            name: None,
+            discriminator: None,
+        };
+
+        let mut conn = pool
+            .get()
+            .await
+            .expect("Failed to get a database connection");
+
+        diesel::insert_into(schema::user::table)
+            .values(&user)
+            .execute(&mut conn)
+            .await
+            .expect("Failed to insert user");
+
+        let mut sessions = vec![];
+        for _ in 0..count {
+            sessions.push(
+                RefreshSession::create(
+                    &mut conn,
+                    &config::IDENTITY_CONFIG,
+                    &user.id,
+                    &DeviceInfo::default(),
+                )
+                .await
+                .expect("Failed to create session"),
+            );
+        }
+        sessions
+    }
+
+    async fn refresh(pool: &DbPool, session: &RefreshSession) -> RefreshResult {
+        let mut conn = pool
+            .get()
+            .await
+            .expect("Failed to get a database connection");
+
+        RefreshSession::refresh(
+            &mut conn,
+            &config::IDENTITY_CONFIG,
+            &session.generate_token(&config::IDENTITY_CONFIG).value,
+            &DeviceInfo::default(),
+        )
+        .await
+        .expect("Failed to refresh session")
+    }
+
+    #[actix_web::test]
+    async fn sign_out_invalidates_only_the_current_session() {
+        let pool = db::initialize_db_pool(&config::DB_URL).await;
+        let sessions = create_sessions(&pool, 2).await;
+        let identity_config = web::Data::new(config::IDENTITY_CONFIG.clone());
+
+        let token = Identity::from_session(&sessions[0])
+            .generate_token(&identity_config)
+            .value;
+
+        let pool = web::Data::new(pool);
+        let app = test::init_service(
+            App::new()
+                .app_data(pool.clone())
+                .app_data(identity_config.clone())
+                .service(sign_out),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .insert_header((AUTHORIZATION, format!("Bearer {token}")))
+            .uri("/sign-out/")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        assert!(Identity::from_token(&identity_config, &token).is_err());
+
+        assert_eq!(
+            refresh(&pool, &sessions[0]).await,
+            RefreshResult::SessionInvalidated
+        );
+        assert!(matches!(
+            refresh(&pool, &sessions[1]).await,
+            RefreshResult::Success(_)
+        ));
+    }
+
+    #[actix_web::test]
+    async fn sign_out_everywhere_invalidates_every_session() {
+        let pool = db::initialize_db_pool(&config::DB_URL).await;
+        let sessions = create_sessions(&pool, 2).await;
+        let identity_config = web::Data::new(config::IDENTITY_CONFIG.clone());
+
+        let token = Identity::from_session(&sessions[0])
+            .generate_token(&identity_config)
+            .value;
+
+        let pool = web::Data::new(pool);
+        let app = test::init_service(
+            App::new()
+                .app_data(pool.clone())
+                .app_data(identity_config.clone())
+                .service(sign_out_everywhere),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .insert_header((AUTHORIZATION, format!("Bearer {token}")))
+            .uri("/sign-out/everywhere/")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        assert!(Identity::from_token(&identity_config, &token).is_err());
+
+        for session in &sessions {
+            assert_eq!(
+                refresh(&pool, session).await,
+                RefreshResult::SessionInvalidated
+            );
+        }
+    }
+
+    #[actix_web::test]
+    async fn each_auth_route_has_its_rate_limit() {
+        let rules = rate_limit_rules(&config::RATE_LIMIT_CONFIG);
+        let rule_name = |path: &str| {
+            rules
+                .iter()
+                .find(|rule| rule.routes.is_match(path))
+                .map(|rule| rule.name)
+        };
+
+        assert_eq!(rule_name("/auth/password/sign-in/"), Some("password"));
+        assert_eq!(
+            rule_name("/auth/password/reset-password/request/"),
+            Some("mail")
+        );
+        assert_eq!(rule_name("/auth/steam/sign-in/"), Some("sign_in"));
+        assert_eq!(rule_name("/auth/guest/sign-in/"), Some("sign_in"));
+        assert_eq!(rule_name("/auth/oidc/apple/link/"), Some("sign_in"));
+        assert_eq!(rule_name("/auth/refresh/"), Some("refresh"));
+        assert_eq!(rule_name("/auth/sessions/"), None);
+    }
+}
@@ -0,0 +1,386 @@
+use crate::auth::device::DeviceInfo;
+use crate::auth::identity::IdentityConfig;
+use crate::auth::oauth2::exchange_code::OAuthCodeExchangeService;
+use crate::auth::oauth2::google_user_info_api::{GoogleUserInfo, GoogleUserInfoService};
+use crate::auth::oauth2::sign_in::sign_in_with_user_info;
+use crate::auth::sign_in_error::SignInError;
+use crate::auth::token::generate_secret;
+use crate::auth::{sign_in_cookies, SignInResult};
+use crate::config::OAUTH_CLIENT_SECRETS;
+use crate::db::DbPool;
+use actix_web::http::header::LOCATION;
+use actix_web::{cookie, error, get, web, HttpRequest, HttpResponse};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL_SAFE_NO_PAD;
+use base64::prelude::*;
+use chrono::{Duration, Utc};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+const AUTHORIZATION_URL: &str = "https://accounts.google.com/o/oauth2/v2/auth";
+
+const SCOPES: &str = "openid email profile";
+
+const FLOW_AUDIENCE: &str = "oauth2_flow";
+
+/// The cookie that carries a flow from `/authorize/` to `/callback/`.
+const FLOW_COOKIE: &str = "oauth2_flow";
+
+/// How long a user has to sign in with Google.
+const FLOW_EXPIRES_IN_MINUTES: i64 = 10;
+
+/// A sign-in started at `/authorize/`, carried by the browser in a cookie
+/// signed with the refresh token key.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct FlowClaims {
+    aud: String,
+    iat: u64,
+    exp: u64,
+    state: String,
+    code_verifier: String,
+    /// Where the browser is sent once signed in.
+    return_to: String,
+}
+
+impl FlowClaims {
+    fn new(return_to: String) -> Self {
+        let now = Utc::now();
+        FlowClaims {
+            aud: FLOW_AUDIENCE.to_string(),
+            iat: now.timestamp() as u64,
+            exp: (now + Duration::minutes(FLOW_EXPIRES_IN_MINUTES)).timestamp() as u64,
+            state: generate_secret(),
+            code_verifier: generate_secret(),
+            return_to,
+        }
+    }
+
+    /// https://datatracker.ietf.org/doc/html/rfc7636#section-4.2
+    fn code_challenge(&self) -> String {
+        BASE64_URL_SAFE_NO_PAD.encode(openssl::sha::sha256(self.code_verifier.as_bytes()))
+    }
+}
+
+/// Only the web client's origins may be returned to, so that the flow cannot
+/// send a signed-in browser to an arbitrary site.
+fn parse_return_url(return_to: &str, return_origins: &[String]) -> Option<Url> {
+    let url = Url::parse(return_to).ok()?;
+    let origin = url.origin().ascii_serialization();
+    return_origins
+        .iter()
+        .any(|allowed| allowed.trim_end_matches('/') == origin)
+        .then_some(url)
+}
+
+fn flow_cookie(value: String, max_age: cookie::time::Duration) -> cookie::Cookie<'static> {
+    cookie::Cookie::build(FLOW_COOKIE, value)
+        .path("/")
+        .max_age(max_age)
+        .http_only(true)
+        .same_site(cookie::SameSite::Lax)
+        .finish()
+}
+
+#[derive(Deserialize)]
+struct AuthorizeQuery {
+    return_to: String,
+}
+
+/// Starts signing in with Google by sending the browser to Google's consent
+/// screen.
+#[get("/authorize/")]
+async fn authorize(
+    query: web::Query<AuthorizeQuery>,
+    identity_config: web::Data<IdentityConfig>,
+    code_exchange_service: web::Data<dyn OAuthCodeExchangeService>,
+) -> actix_web::Result<HttpResponse> {
+    let config = code_exchange_service.config();
+    if parse_return_url(&query.return_to, &config.return_origins).is_none() {
+        return Err(error::ErrorBadRequest(
+            "The return URL is not an allowed origin",
+        ));
+    }
+
+    let flow = FlowClaims::new(query.into_inner().return_to);
+    let mut url = Url::parse(AUTHORIZATION_URL).expect("The authorization URL should be valid");
+    url.query_pairs_mut()
+        .append_pair("client_id", &OAUTH_CLIENT_SECRETS.id)
+        .append_pair("redirect_uri", &config.redirect_url)
+        .append_pair("response_type", "code")
+        .append_pair("scope", SCOPES)
+        .append_pair("state", &flow.state)
+        .append_pair("code_challenge", &flow.code_challenge())
+        .append_pair("code_challenge_method", "S256");
+
+    Ok(HttpResponse::Found()
+        .insert_header((LOCATION, url.as_str()))
+        .cookie(flow_cookie(
+            identity_config.encode_client_claims(&flow),
+            cookie::time::Duration::minutes(FLOW_EXPIRES_IN_MINUTES),
+        ))
+        .finish())
+}
+
+#[derive(Deserialize)]
+struct CallbackQuery {
+    state: String,
+    code: Option<String>,
+    error: Option<String>,
+}
+
+async fn get_user_info(
+    query: &CallbackQuery,
+    flow: &FlowClaims,
+    code_exchange_service: &dyn OAuthCodeExchangeService,
+    google_user_info_service: &dyn GoogleUserInfoService,
+) -> Result<GoogleUserInfo, SignInError> {
+    let Some(code) = &query.code else {
+        return Err(SignInError::InvalidCredential(
+            "No authorization code received".to_string(),
+        ));
+    };
+    let access_token = code_exchange_service
+        .exchange_code(code, &flow.code_verifier)
+        .await?;
+    google_user_info_service.get_info(&access_token).await
+}
+
+/// Completes signing in with Google, and sends the browser back to the web
+/// client.
+///
+/// The sign-in cookies are set if the user signed in. Otherwise, the result is
+/// added to the return URL's fragment as either `error` or `link_token`.
+#[get("/callback/")]
+async fn callback(
+    req: HttpRequest,
+    query: web::Query<CallbackQuery>,
+    pool: web::Data<DbPool>,
+    identity_config: web::Data<IdentityConfig>,
+    device: DeviceInfo,
+    code_exchange_service: web::Data<dyn OAuthCodeExchangeService>,
+    google_user_info_service: web::Data<dyn GoogleUserInfoService>,
+) -> actix_web::Result<HttpResponse> {
+    let Some(cookie) = req.cookie(FLOW_COOKIE) else {
+        return Err(error::ErrorBadRequest("No sign-in in progress"));
+    };
+    let flow: FlowClaims = identity_config.decode_client_claims(cookie.value(), FLOW_AUDIENCE)?;
+    if flow.state != query.state {
+        return Err(error::ErrorBadRequest(
+            "The state does not match the sign-in in progress",
+        ));
+    }
+    let Some(mut return_url) = parse_return_url(
+        &flow.return_to,
+        &code_exchange_service.config().return_origins,
+    ) else {
+        return Err(error::ErrorBadRequest(
+            "The return URL is not an allowed origin",
+        ));
+    };
+
+    let mut response = HttpResponse::SeeOther();
+    response.cookie(flow_cookie(
+        "".to_string(),
+        cookie::time::Duration::seconds(-1),
+    ));
+
+    let user_info = match &query.error {
+        Some(err) => Err(err.clone()),
+        None => get_user_info(
+            &query,
+            &flow,
+            code_exchange_service.get_ref(),
+            google_user_info_service.get_ref(),
+        )
+        .await
+        .map_err(|err| err.code().to_string()),
+    };
+    let user_info = match user_info {
+        Ok(user_info) => user_info,
+        Err(code) => {
+            return_url.set_fragment(Some(&format!("error={code}")));
+            return Ok(response
+                .insert_header((LOCATION, return_url.as_str()))
+                .finish());
+        }
+    };
+
+    let mut conn = pool.get().await.map_err(error::ErrorInternalServerError)?;
+    match sign_in_with_user_info(&mut conn, &identity_config, &device, &user_info).await? {
+        SignInResult::Success(success) => {
+            for cookie in sign_in_cookies(&success, &identity_config) {
+                response.cookie(cookie);
+            }
+        }
+        SignInResult::PendingLinkOrCreate(pending) => {
+            return_url.set_fragment(Some(&format!("link_token={}", pending.link_token.value)));
+        }
+    }
+    Ok(response
+        .insert_header((LOCATION, return_url.as_str()))
+        .finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::oauth2::exchange_code::OAuthCodeExchangeService;
+    use crate::config::OAuthFlowConfig;
+    use crate::{config, db};
+    use actix_web::http::StatusCode;
+    use actix_web::{test, App};
+    use std::sync::Arc;
+
+    use super::*;
+
+    struct MockOAuthCodeExchangeService {
+        config: OAuthFlowConfig,
+    }
+
+    #[async_trait::async_trait]
+    impl OAuthCodeExchangeService for MockOAuthCodeExchangeService {
+        fn config(&self) -> &OAuthFlowConfig {
+            &self.config
+        }
+
+        /// Only exchanges codes for verifiers that match the challenge that
+        /// the code was issued for.
+        async fn exchange_code(
+            &self,
+            code: &str,
+            code_verifier: &str,
+        ) -> Result<String, SignInError> {
+            let challenge =
+                BASE64_URL_SAFE_NO_PAD.encode(openssl::sha::sha256(code_verifier.as_bytes()));
+            if code == challenge {
+                Ok("google-access-token".to_string())
+            } else {
+                Err(SignInError::InvalidCredential(
+                    "The code verifier does not match".to_string(),
+                ))
+            }
+        }
+    }
+
+    struct MockGoogleUserInfoService;
+
+    #[async_trait::async_trait]
+    impl GoogleUserInfoService for MockGoogleUserInfoService {
+        async fn get_info(&self, token: &str) -> Result<GoogleUserInfo, SignInError> {
+            assert_eq!(token, "google-access-token");
+            Ok(GoogleUserInfo {
+                id: uuid::Uuid::new_v4().to_string(),
+                email: None,
+                verified_email: false,
+                name: Some("Bryan".to_string()),
+                family_name: None,
+                given_name: Some("Bryan".to_string()),
+                locale: None,
+                picture: None,
+            })
+        }
+    }
+
+    macro_rules! init_app {
+        () => {
+            test::init_service(
+                App::new()
+                    .app_data(web::Data::new(
+                        db::initialize_db_pool(&config::DB_URL).await,
+                    ))
+                    .app_data(web::Data::new(config::IDENTITY_CONFIG.clone()))
+                    .app_data(web::Data::from(Arc::new(MockOAuthCodeExchangeService {
+                        config: OAuthFlowConfig {
+                            redirect_url: "https://auth.example.com/auth/oauth2/callback/"
+                                .to_string(),
+                            return_origins: vec!["https://game.example.com".to_string()],
+                        },
+                    })
+                        as Arc<dyn OAuthCodeExchangeService>))
+                    .app_data(web::Data::from(
+                        Arc::new(MockGoogleUserInfoService) as Arc<dyn GoogleUserInfoService>
+                    ))
+                    .service(authorize)
+                    .service(callback),
+            )
+            .await
+        };
+    }
+
+    fn authorize_uri(return_to: &str) -> String {
+        let mut url = Url::parse("http://localhost/authorize/").unwrap();
+        url.query_pairs_mut().append_pair("return_to", return_to);
+        format!("/authorize/?{}", url.query().unwrap())
+    }
+
+    #[actix_web::test]
+    async fn sign_in_completes_in_the_browser() {
+        let app = init_app!();
+
+        let req = test::TestRequest::get()
+            .uri(&authorize_uri("https://game.example.com/play/"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::FOUND);
+        let flow_cookie = resp
+            .response()
+            .cookies()
+            .find(|c| c.name() == FLOW_COOKIE)
+            .expect("Expected a flow cookie")
+            .into_owned();
+        let location = Url::parse(resp.headers().get(LOCATION).unwrap().to_str().unwrap()).unwrap();
+        let params: std::collections::HashMap<_, _> = location.query_pairs().collect();
+        assert_eq!(params["code_challenge_method"], "S256");
+
+        // Google sends the code back with the state. The mock exchanges codes
+        // that equal the challenge.
+        let callback_uri = |state: &str| {
+            let mut url = Url::parse("http://localhost/callback/").unwrap();
+            url.query_pairs_mut()
+                .append_pair("state", state)
+                .append_pair("code", &params["code_challenge"]);
+            format!("/callback/?{}", url.query().unwrap())
+        };
+
+        let req = test::TestRequest::get()
+            .uri(&callback_uri("another-state"))
+            .cookie(flow_cookie.clone())
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let req = test::TestRequest::get()
+            .uri(&callback_uri(&params["state"]))
+            .cookie(flow_cookie)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+        assert_eq!(
+            resp.headers().get(LOCATION).unwrap(),
+            "https://game.example.com/play/"
+        );
+        let cookies: Vec<_> = resp
+            .response()
+            .cookies()
+            .map(|c| (c.name().to_string(), c.value().is_empty()))
+            .collect();
+        assert!(cookies.contains(&("access_token".to_string(), false)));
+        assert!(cookies.contains(&("refresh_token".to_string(), false)));
+        assert!(cookies.contains(&(FLOW_COOKIE.to_string(), true)));
+    }
+
+    #[actix_web::test]
+    async fn only_allowed_origins_are_returned_to() {
+        let app = init_app!();
+
+        for return_to in [
+            "https://attacker.example.com/",
+            "https://game.example.com.attacker.com/",
+            "not a url",
+        ] {
+            let req = test::TestRequest::get()
+                .uri(&authorize_uri(return_to))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST, "{return_to}");
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use crate::auth::sign_in_error::SignInError;
+use crate::config::{OAuthFlowConfig, OAUTH_CLIENT_SECRETS, OAUTH_FLOW_CONFIG};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use std::time::Duration;
+
+const OAUTH_TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
+
+#[derive(Deserialize)]
+struct TokenPayload {
+    access_token: String,
+}
+
+#[async_trait::async_trait]
+pub trait OAuthCodeExchangeService: Sync {
+    fn config(&self) -> &OAuthFlowConfig;
+
+    /// Exchanges an authorization code for an access token, proving with the
+    /// PKCE code verifier that this server started the flow.
+    async fn exchange_code(&self, code: &str, code_verifier: &str) -> Result<String, SignInError> {
+        let params = [
+            ("client_id", OAUTH_CLIENT_SECRETS.id.as_str()),
+            ("client_secret", OAUTH_CLIENT_SECRETS.secret.as_str()),
+            ("code", code),
+            ("code_verifier", code_verifier),
+            ("grant_type", "authorization_code"),
+            ("redirect_uri", self.config().redirect_url.as_str()),
+        ];
+
+        let client = reqwest::Client::new();
+        let resp = client
+            .post(OAUTH_TOKEN_URL)
+            .timeout(Duration::from_secs(5))
+            .form(&params)
+            .send()
+            .await
+            .map_err(SignInError::upstream_unavailable)?;
+
+        match resp.status() {
+            StatusCode::OK => {
+                let token: TokenPayload = resp
+                    .json()
+                    .await
+                    .map_err(SignInError::upstream_unavailable)?;
+                Ok(token.access_token)
+            }
+            StatusCode::BAD_REQUEST | StatusCode::UNAUTHORIZED => Err(
+                SignInError::InvalidCredential("Failed to exchange code".to_string()),
+            ),
+            status => Err(SignInError::upstream_unavailable(status)),
+        }
+    }
+}
+
+pub struct RealOAuthCodeExchangeService;
+
+impl OAuthCodeExchangeService for RealOAuthCodeExchangeService {
+    fn config(&self) -> &OAuthFlowConfig {
+        &OAUTH_FLOW_CONFIG
+    }
+}
@@ -1,8 +1,8 @@
 use crate::auth::provider::{
     AuthProviderChangeset, AuthProviderInsert, AuthProviderType, IntoAuthProviderInsert,
 };
+use crate::auth::sign_in_error::SignInError;
 use crate::user::{User, UserInsert};
-use actix_web::error;
 use reqwest::StatusCode;
 use serde::Deserialize;
 use std::time::Duration;
@@ -23,22 +23,22 @@ pub struct GoogleUserInfo {
 impl From<&GoogleUserInfo> for AuthProviderChangeset {
     fn from(value: &GoogleUserInfo) -> Self {
         AuthProviderChangeset {
-            order: 0,
             email: value.email.clone(),
             email_verified: value.verified_email,
             display_name: value.name.clone(),
             user_name: value.given_name.clone(),
             picture_url: value.picture.clone(),
             locale: value.locale.clone(),
+            family_shared_owner_id: None,
+            vac_banned: false,
+            publisher_banned: false,
         }
     }
 }
 
 impl From<&GoogleUserInfo> for UserInsert {
-    fn from(value: &GoogleUserInfo) -> Self {
-        UserInsert {
-            name: value.name.clone(),
-        }
+    fn from(_value: &GoogleUserInfo) -> Self {
+        UserInsert::default()
     }
 }
 
@@ -55,15 +55,18 @@ impl IntoAuthProviderInsert for GoogleUserInfo {
             user_name: self.given_name.clone(),
             picture_url: self.picture.clone(),
             locale: self.locale.clone(),
+            family_shared_owner_id: None,
+            vac_banned: false,
+            publisher_banned: false,
         }
     }
 }
 
-const USER_INFO_REQUEST_URL: &'static str = "https://www.googleapis.com/userinfo/v2/me";
+const USER_INFO_REQUEST_URL: &str = "https://www.googleapis.com/userinfo/v2/me";
 
 #[async_trait::async_trait]
 pub trait GoogleUserInfoService: Sync {
-    async fn get_info(&self, token: &str) -> Result<GoogleUserInfo, error::Error> {
+    async fn get_info(&self, token: &str) -> Result<GoogleUserInfo, SignInError> {
         let client = reqwest::Client::new();
         let resp = client
             .get(USER_INFO_REQUEST_URL)
@@ -71,17 +74,17 @@ pub trait GoogleUserInfoService: Sync {
             .bearer_auth(token)
             .send()
             .await
-            .map_err(error::ErrorInternalServerError)?;
+            .map_err(SignInError::upstream_unavailable)?;
 
         match resp.status() {
             StatusCode::OK => resp
                 .json::<GoogleUserInfo>()
                 .await
-                .map_err(error::ErrorInternalServerError),
-            StatusCode::UNAUTHORIZED => Err(error::ErrorUnauthorized(resp.status())),
-            _ => Err(error::ErrorInternalServerError(
-                "Failed to get user information",
+                .map_err(SignInError::upstream_unavailable),
+            StatusCode::UNAUTHORIZED => Err(SignInError::InvalidCredential(
+                "Failed to get user information".to_string(),
             )),
+            status => Err(SignInError::upstream_unavailable(status)),
         }
     }
 }
@@ -0,0 +1,283 @@
+use crate::auth::device::DeviceInfo;
+use crate::auth::identity::{Identity, IdentityConfig};
+use crate::auth::link::{create_pending, link_pending, link_provider};
+use crate::auth::oauth2::google_user_info_api::GoogleUserInfoService;
+use crate::db::DbPool;
+use actix_web::{error, post, web, HttpResponse};
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(serde::Serialize))]
+#[serde(untagged)]
+enum LinkRequestBody {
+    /// Confirms a link that was left pending by sign-in.
+    Pending { link_token: String },
+    /// Links another Google account to the caller.
+    Credential { access_token: String },
+}
+
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(serde::Serialize))]
+struct CreateRequestBody {
+    link_token: String,
+}
+
+/// Links a provider to the caller, either one that was left pending by sign-in
+/// or another Google account.
+#[post("/link/")]
+async fn link(
+    body: web::Json<LinkRequestBody>,
+    identity: Identity,
+    pool: web::Data<DbPool>,
+    identity_config: web::Data<IdentityConfig>,
+    device: DeviceInfo,
+    google_user_info_service: web::Data<dyn GoogleUserInfoService>,
+) -> actix_web::Result<HttpResponse> {
+    match body.into_inner() {
+        LinkRequestBody::Pending { link_token } => {
+            let mut conn = pool.get().await.map_err(error::ErrorInternalServerError)?;
+            link_pending(&mut conn, &identity_config, &identity, &link_token, &device).await
+        }
+        LinkRequestBody::Credential { access_token } => {
+            let user_info = google_user_info_service.get_info(&access_token).await?;
+
+            let mut conn = pool.get().await.map_err(error::ErrorInternalServerError)?;
+            let user_with_providers =
+                link_provider(&mut conn, &identity.user_id, &user_info).await?;
+            Ok(HttpResponse::Ok().json(user_with_providers))
+        }
+    }
+}
+
+/// Creates a separate user for a provider that was left pending by sign-in
+/// instead of linking it.
+#[post("/create/")]
+async fn create(
+    body: web::Json<CreateRequestBody>,
+    pool: web::Data<DbPool>,
+    identity_config: web::Data<IdentityConfig>,
+    device: DeviceInfo,
+) -> actix_web::Result<HttpResponse> {
+    let mut conn = pool.get().await.map_err(error::ErrorInternalServerError)?;
+    create_pending(&mut conn, &identity_config, &body.link_token, &device).await
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::link::PendingLinkClaims;
+    use crate::auth::oauth2::google_user_info_api::GoogleUserInfo;
+    use crate::auth::provider::{AuthProviderInsert, AuthProviderType};
+    use crate::auth::sign_in_error::SignInError;
+    use crate::auth::SignInResult;
+    use crate::schema;
+    use crate::user::{User, UserWithAuthProviders};
+    use crate::{config, db};
+    use actix_web::{http::header::AUTHORIZATION, http::StatusCode, test, App};
+    use diesel_async::RunQueryDsl;
+    use std::sync::Arc;
+    use uuid::Uuid;
+
+    use super::*;
+
+    struct MockGoogleUserInfoService;
+
+    #[async_trait::async_trait]
+    impl GoogleUserInfoService for MockGoogleUserInfoService {
+        async fn get_info(&self, token: &str) -> Result<GoogleUserInfo, SignInError> {
+            Ok(GoogleUserInfo {
+                id: token.to_string(),
+                email: None,
+                verified_email: false,
+                name: Some("Bryan".to_string()),
+                family_name: None,
+                given_name: Some("Bryan".to_string()),
+                locale: None,
+                picture: None,
+            })
+        }
+    }
+
+    async fn insert_user(pool: &DbPool, email: &str) -> User {
+        let user = User {
+            id: Uuid::new_v4(),
+            name: None,
+            discriminator: None,
+        };
+
+        let mut conn = pool
+            .get()
+            .await
+            .expect("Failed to get a database connection");
+
+        diesel::insert_into(schema::user::table)
+            .values(&user)
+            .execute(&mut conn)
+            .await
+            .expect("Failed to insert user");
+        diesel::insert_into(schema::auth_provider::table)
+            .values(AuthProviderInsert {
+                user_id: user.id,
+                provider_type: AuthProviderType::Steam,
+                provider_id: Uuid::new_v4().to_string(),
+                email: Some(email.to_string()),
+                ..AuthProviderInsert::default()
+            })
+            .execute(&mut conn)
+            .await
+            .expect("Failed to insert provider");
+        user
+    }
+
+    fn pending_provider(email: &str) -> AuthProviderInsert {
+        AuthProviderInsert {
+            provider_id: Uuid::new_v4().to_string(),
+            email: Some(email.to_string()),
+            display_name: Some("Bryan".to_string()),
+            ..AuthProviderInsert::default()
+        }
+    }
+
+    macro_rules! init_app {
+        ($pool:expr) => {
+            test::init_service(
+                App::new()
+                    .app_data(web::Data::new($pool))
+                    .app_data(web::Data::new(config::IDENTITY_CONFIG.clone()))
+                    .app_data(web::Data::from(
+                        Arc::new(MockGoogleUserInfoService) as Arc<dyn GoogleUserInfoService>
+                    ))
+                    .service(link)
+                    .service(create),
+            )
+            .await
+        };
+    }
+
+    fn bearer(user: &User) -> (actix_web::http::header::HeaderName, String) {
+        let token = Identity::from_user(user)
+            .generate_token(&config::IDENTITY_CONFIG)
+            .value;
+        (AUTHORIZATION, format!("Bearer {token}"))
+    }
+
+    #[actix_web::test]
+    async fn pending_provider_can_be_linked_to_a_matched_user() {
+        let email = "link@example.com";
+        let pool = db::initialize_db_pool(&config::DB_URL).await;
+        let user = insert_user(&pool, email).await;
+        let link_token = PendingLinkClaims::new(pending_provider(email), vec![user.id])
+            .generate_token(&config::IDENTITY_CONFIG)
+            .value;
+        let app = init_app!(pool);
+
+        let req = test::TestRequest::post()
+            .insert_header(bearer(&user))
+            .uri("/link/")
+            .set_json(LinkRequestBody::Pending {
+                link_token: link_token.clone(),
+            })
+            .to_request();
+        let body: SignInResult = test::call_and_read_body_json(&app, req).await;
+        let SignInResult::Success(success) = body else {
+            panic!("Expected a successful sign in")
+        };
+        assert_eq!(success.user.user.id, user.id);
+        assert_eq!(success.user.providers.len(), 2);
+        assert_eq!(success.user.providers[1].order, 1);
+        assert_eq!(
+            success.user.providers[1].provider_type,
+            AuthProviderType::OAuth2
+        );
+    }
+
+    #[actix_web::test]
+    async fn pending_provider_cannot_be_linked_to_an_unmatched_user() {
+        let email = "unmatched@example.com";
+        let pool = db::initialize_db_pool(&config::DB_URL).await;
+        let matched_user = insert_user(&pool, email).await;
+        let other_user = insert_user(&pool, "other@example.com").await;
+        let link_token = PendingLinkClaims::new(pending_provider(email), vec![matched_user.id])
+            .generate_token(&config::IDENTITY_CONFIG)
+            .value;
+        let app = init_app!(pool);
+
+        let req = test::TestRequest::post()
+            .insert_header(bearer(&other_user))
+            .uri("/link/")
+            .set_json(LinkRequestBody::Pending { link_token })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[actix_web::test]
+    async fn pending_provider_can_create_a_new_user() {
+        let email = "create@example.com";
+        let pool = db::initialize_db_pool(&config::DB_URL).await;
+        let matched_user = insert_user(&pool, email).await;
+        let link_token = PendingLinkClaims::new(pending_provider(email), vec![matched_user.id])
+            .generate_token(&config::IDENTITY_CONFIG)
+            .value;
+        let app = init_app!(pool);
+
+        let req = test::TestRequest::post()
+            .uri("/create/")
+            .set_json(CreateRequestBody { link_token })
+            .to_request();
+        let body: SignInResult = test::call_and_read_body_json(&app, req).await;
+        let SignInResult::Success(success) = body else {
+            panic!("Expected a successful sign in")
+        };
+        assert_ne!(success.user.user.id, matched_user.id);
+        assert_eq!(success.user.user.name, None);
+        assert_eq!(success.user.providers.len(), 1);
+        assert_eq!(
+            success.user.providers[0].display_name,
+            Some("Bryan".to_string())
+        );
+    }
+
+    #[actix_web::test]
+    async fn signed_in_user_can_link_another_google_account() {
+        let pool = db::initialize_db_pool(&config::DB_URL).await;
+        let user = insert_user(&pool, "signed-in@example.com").await;
+        let app = init_app!(pool);
+
+        let req = test::TestRequest::post()
+            .insert_header(bearer(&user))
+            .uri("/link/")
+            .set_json(LinkRequestBody::Credential {
+                access_token: "google-0001".to_string(),
+            })
+            .to_request();
+        let body: UserWithAuthProviders = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body.user.id, user.id);
+        assert_eq!(body.providers.len(), 2);
+        assert_eq!(body.providers[1].provider_id, "google-0001");
+        assert_eq!(body.providers[1].order, 1);
+    }
+
+    #[actix_web::test]
+    async fn provider_of_another_user_cannot_be_linked() {
+        let pool = db::initialize_db_pool(&config::DB_URL).await;
+        let user = insert_user(&pool, "first@example.com").await;
+        let other_user = insert_user(&pool, "second@example.com").await;
+        let app = init_app!(pool);
+
+        let link_request = |user: &User| {
+            test::TestRequest::post()
+                .insert_header(bearer(user))
+                .uri("/link/")
+                .set_json(LinkRequestBody::Credential {
+                    access_token: "google-0002".to_string(),
+                })
+                .to_request()
+        };
+
+        let resp = test::call_service(&app, link_request(&user)).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let resp = test::call_service(&app, link_request(&other_user)).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+    }
+}
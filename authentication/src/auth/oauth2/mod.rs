@@ -1,6 +1,10 @@
+mod authorize;
+mod exchange_code;
 pub mod google_user_info_api;
+mod link;
 mod sign_in;
 
+use self::exchange_code::{OAuthCodeExchangeService, RealOAuthCodeExchangeService};
 use self::google_user_info_api::{GoogleUserInfoService, RealGoogleUserInfoService};
 use actix_web::web;
 use std::sync::Arc;
@@ -8,6 +12,14 @@ use std::sync::Arc;
 pub fn config_service(cfg: &mut web::ServiceConfig) {
     let google_user_info_service =
         web::Data::from(Arc::new(RealGoogleUserInfoService) as Arc<dyn GoogleUserInfoService>);
+    let code_exchange_service = web::Data::from(
+        Arc::new(RealOAuthCodeExchangeService) as Arc<dyn OAuthCodeExchangeService>
+    );
     cfg.app_data(google_user_info_service)
-        .service(sign_in::sign_in);
+        .app_data(code_exchange_service)
+        .service(sign_in::sign_in)
+        .service(link::link)
+        .service(link::create)
+        .service(authorize::authorize)
+        .service(authorize::callback);
 }
@@ -1,9 +1,15 @@
+use crate::auth::device::DeviceInfo;
 use crate::auth::identity::IdentityConfig;
-use crate::auth::oauth2::google_user_info_api::GoogleUserInfoService;
-use crate::auth::provider::{AuthProvider, AuthProviderChangeset, AuthProviderType};
+use crate::auth::link::PendingLinkClaims;
+use crate::auth::oauth2::google_user_info_api::{GoogleUserInfo, GoogleUserInfoService};
+use crate::auth::provider::{
+    AuthProvider, AuthProviderChangeset, AuthProviderType, IntoAuthProviderInsert,
+};
 use crate::auth::token::BearerToken;
-use crate::auth::{create_new_user, generate_sign_in_success_response, SignInResult};
-use crate::db::DbPool;
+use crate::auth::{
+    create_new_user, generate_sign_in_success, sign_in_response, PendingLinkOrCreate, SignInResult,
+};
+use crate::db::{DbConnection, DbPool};
 use crate::schema;
 use crate::user::{User, UserWithAuthProviders};
 use actix_web::{error, post, web, HttpResponse};
@@ -16,18 +22,31 @@ async fn sign_in(
     token: BearerToken,
     pool: web::Data<DbPool>,
     identity_config: web::Data<IdentityConfig>,
+    device: DeviceInfo,
     google_user_info_service: web::Data<dyn GoogleUserInfoService>,
 ) -> actix_web::Result<HttpResponse> {
     let user_info = google_user_info_service.get_info(&token).await?;
 
     let mut conn = pool.get().await.map_err(error::ErrorInternalServerError)?;
 
-    let provider_changeset: AuthProviderChangeset = (&user_info).into();
+    let result = sign_in_with_user_info(&mut conn, &identity_config, &device, &user_info).await?;
+    Ok(sign_in_response(result, &identity_config))
+}
+
+/// Signs in with a verified Google user, or asks the client to link or create
+/// a user if the user's email matches existing users.
+pub async fn sign_in_with_user_info(
+    conn: &mut DbConnection,
+    identity_config: &IdentityConfig,
+    device: &DeviceInfo,
+    user_info: &GoogleUserInfo,
+) -> actix_web::Result<SignInResult> {
+    let provider_changeset: AuthProviderChangeset = user_info.into();
     let matching_provider: Option<AuthProvider> = diesel::update(schema::auth_provider::table)
         .filter(schema::auth_provider::provider_id.eq(&user_info.id))
         .filter(schema::auth_provider::provider_type.eq(AuthProviderType::OAuth2))
         .set(&provider_changeset)
-        .get_result(&mut conn)
+        .get_result(conn)
         .await
         .optional()
         .map_err(error::ErrorInternalServerError)?;
@@ -35,48 +54,70 @@ async fn sign_in(
     if let Some(matching_provider) = matching_provider {
         let user: User = schema::user::table
             .filter(schema::user::id.eq(&matching_provider.user_id))
-            .first(&mut conn)
+            .first(conn)
             .await
             .map_err(error::ErrorInternalServerError)?;
 
         let providers = user
-            .get_providers(&mut conn)
+            .get_providers(conn)
             .await
             .map_err(error::ErrorInternalServerError)?;
 
-        return generate_sign_in_success_response(
-            &mut conn,
-            UserWithAuthProviders { user, providers },
-            &identity_config,
-        )
-        .await;
+        return Ok(SignInResult::Success(
+            generate_sign_in_success(
+                conn,
+                UserWithAuthProviders { user, providers },
+                &AuthProviderType::OAuth2,
+                identity_config,
+                device,
+            )
+            .await?,
+        ));
     }
 
     let Some(email) = &user_info.email else {
-        let new_user = create_new_user(&mut conn, &user_info)
+        let new_user = create_new_user(conn, user_info)
             .await
             .map_err(error::ErrorInternalServerError)?;
-        return generate_sign_in_success_response(&mut conn, new_user, &identity_config).await;
+        return Ok(SignInResult::Success(
+            generate_sign_in_success(
+                conn,
+                new_user,
+                &AuthProviderType::OAuth2,
+                identity_config,
+                device,
+            )
+            .await?,
+        ));
     };
 
     let same_email_providers: Vec<AuthProvider> = schema::auth_provider::table
         .filter(schema::auth_provider::email.eq(&email))
-        .get_results(&mut conn)
+        .get_results(conn)
         .await
         .map_err(error::ErrorInternalServerError)?;
 
     if same_email_providers.is_empty() {
-        let new_user = create_new_user(&mut conn, &user_info)
+        let new_user = create_new_user(conn, user_info)
             .await
             .map_err(error::ErrorInternalServerError)?;
-        return generate_sign_in_success_response(&mut conn, new_user, &identity_config).await;
+        return Ok(SignInResult::Success(
+            generate_sign_in_success(
+                conn,
+                new_user,
+                &AuthProviderType::OAuth2,
+                identity_config,
+                device,
+            )
+            .await?,
+        ));
     }
 
     let user_ids: Vec<Uuid> = same_email_providers.iter().map(|p| p.user_id).collect();
 
     let users: Vec<User> = schema::user::table
         .filter(schema::user::id.eq_any(user_ids))
-        .get_results(&mut conn)
+        .get_results(conn)
         .await
         .map_err(error::ErrorInternalServerError)?;
 
@@ -87,13 +128,29 @@ async fn sign_in(
         .map(|(providers, user)| UserWithAuthProviders { user, providers })
         .collect();
 
-    Ok(HttpResponse::Ok().json(SignInResult::PendingLinkOrCreate(users_with_providers)))
+    // The provider gets its user once it is linked or a new user is created.
+    let provider = user_info.into_provider_insert(&User {
+        id: Uuid::nil(),
+        name: None,
+        discriminator: None,
+    });
+    let link_token = PendingLinkClaims::new(
+        provider,
+        users_with_providers.iter().map(|u| u.user.id).collect(),
+    )
+    .generate_token(identity_config);
+
+    Ok(SignInResult::PendingLinkOrCreate(PendingLinkOrCreate {
+        link_token,
+        users: users_with_providers,
+    }))
 }
 
 #[cfg(test)]
 mod tests {
     use crate::auth::oauth2::google_user_info_api::GoogleUserInfo;
     use crate::auth::provider::{AuthProviderInsert, IntoAuthProviderInsert};
+    use crate::auth::sign_in_error::SignInError;
     use crate::{config, db};
     use actix_web::{http::header::AUTHORIZATION, test, web, App};
     use reqwest::StatusCode;
@@ -108,7 +165,7 @@ mod tests {
 
         #[async_trait::async_trait]
         impl GoogleUserInfoService for NeverGoogleUserInfoService {
-            async fn get_info(&self, _token: &str) -> Result<GoogleUserInfo, error::Error> {
+            async fn get_info(&self, _token: &str) -> Result<GoogleUserInfo, SignInError> {
                 panic!("Unreachable code")
             }
         }
@@ -161,7 +218,7 @@ mod tests {
 
         #[async_trait::async_trait]
         impl GoogleUserInfoService for MockGoogleUserInfoService {
-            async fn get_info(&self, _token: &str) -> Result<GoogleUserInfo, error::Error> {
+            async fn get_info(&self, _token: &str) -> Result<GoogleUserInfo, SignInError> {
                 Ok(USER_INFO.clone())
             }
         }
@@ -176,6 +233,7 @@ mod tests {
             let existing_user = User {
                 id: Uuid::new_v4(),
                 name: Some(EXISTING_NAME.clone()),
+                discriminator: None,
             };
             diesel::insert_into(schema::user::table)
                 .values(&existing_user)
@@ -224,9 +282,10 @@ mod tests {
 
         let body: SignInResult = test::read_body_json(resp).await;
         assert!(matches!(body, SignInResult::PendingLinkOrCreate(_)));
-        let SignInResult::PendingLinkOrCreate(matched_users) = body else {
+        let SignInResult::PendingLinkOrCreate(pending) = body else {
             panic!()
         };
+        let matched_users = pending.users;
 
         assert_eq!(matched_users.len(), 1);
         let matched_user = matched_users[0].clone();
@@ -255,7 +314,7 @@ mod tests {
 
         #[async_trait::async_trait]
         impl GoogleUserInfoService for MockGoogleUserInfoService {
-            async fn get_info(&self, _token: &str) -> Result<GoogleUserInfo, error::Error> {
+            async fn get_info(&self, _token: &str) -> Result<GoogleUserInfo, SignInError> {
                 Ok(USER_INFO.clone())
             }
         }
@@ -321,13 +380,14 @@ mod tests {
         let existing_user = User {
             id: Uuid::new_v4(),
             name: Some("Adam".to_string()),
+            discriminator: None,
         };
 
         struct MockGoogleUserInfoService;
 
         #[async_trait::async_trait]
         impl GoogleUserInfoService for MockGoogleUserInfoService {
-            async fn get_info(&self, _token: &str) -> Result<GoogleUserInfo, error::Error> {
+            async fn get_info(&self, _token: &str) -> Result<GoogleUserInfo, SignInError> {
                 Ok(USER_INFO.clone())
             }
         }
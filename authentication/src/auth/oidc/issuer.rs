@@ -0,0 +1,430 @@
+use crate::auth::oidc::key_cache::KeyCache;
+use crate::auth::provider::{
+    AuthProviderChangeset, AuthProviderInsert, AuthProviderType, IntoAuthProviderInsert,
+};
+use crate::auth::sign_in_error::SignInError;
+use crate::config::{OidcIssuerConfig, OIDC_ISSUERS};
+use crate::user::{User, UserInsert};
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::time::Duration;
+
+/// The algorithms that ID tokens may be signed with. Symmetric algorithms are
+/// excluded, since the issuer's keys are public.
+const ALLOWED_ALGORITHMS: [Algorithm; 9] = [
+    Algorithm::RS256,
+    Algorithm::RS384,
+    Algorithm::RS512,
+    Algorithm::PS256,
+    Algorithm::PS384,
+    Algorithm::PS512,
+    Algorithm::ES256,
+    Algorithm::ES384,
+    Algorithm::EdDSA,
+];
+
+/// https://openid.net/specs/openid-connect-discovery-1_0.html#ProviderMetadata
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiscoveryDocument {
+    pub issuer: String,
+    pub jwks_uri: String,
+}
+
+/// The standard claims of an ID token that are stored on a provider.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OidcClaims {
+    pub sub: String,
+    pub email: Option<String>,
+    /// Apple sends this as a string.
+    #[serde(default, deserialize_with = "deserialize_bool_or_string")]
+    pub email_verified: bool,
+    pub name: Option<String>,
+    pub preferred_username: Option<String>,
+    pub picture: Option<String>,
+    pub locale: Option<String>,
+}
+
+fn deserialize_bool_or_string<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<bool, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum BoolOrString {
+        Bool(bool),
+        String(String),
+    }
+
+    Ok(match BoolOrString::deserialize(deserializer)? {
+        BoolOrString::Bool(value) => value,
+        BoolOrString::String(value) => value == "true",
+    })
+}
+
+/// A user verified by an issuer.
+#[derive(Debug, Clone)]
+pub struct OidcUser {
+    pub issuer_name: String,
+    pub claims: OidcClaims,
+}
+
+impl OidcUser {
+    pub fn provider_type(&self) -> AuthProviderType {
+        AuthProviderType::Oidc(self.issuer_name.clone())
+    }
+}
+
+impl From<&OidcUser> for AuthProviderChangeset {
+    fn from(value: &OidcUser) -> Self {
+        AuthProviderChangeset {
+            email: value.claims.email.clone(),
+            email_verified: value.claims.email_verified,
+            display_name: value.claims.name.clone(),
+            user_name: value.claims.preferred_username.clone(),
+            picture_url: value.claims.picture.clone(),
+            locale: value.claims.locale.clone(),
+            family_shared_owner_id: None,
+            vac_banned: false,
+            publisher_banned: false,
+        }
+    }
+}
+
+impl From<&OidcUser> for UserInsert {
+    fn from(_value: &OidcUser) -> Self {
+        UserInsert::default()
+    }
+}
+
+impl IntoAuthProviderInsert for OidcUser {
+    fn into_provider_insert(&self, user: &User) -> AuthProviderInsert {
+        AuthProviderInsert {
+            user_id: user.id,
+            order: 0,
+            provider_type: self.provider_type(),
+            provider_id: self.claims.sub.clone(),
+            email: self.claims.email.clone(),
+            email_verified: self.claims.email_verified,
+            display_name: self.claims.name.clone(),
+            user_name: self.claims.preferred_username.clone(),
+            picture_url: self.claims.picture.clone(),
+            locale: self.claims.locale.clone(),
+            family_shared_owner_id: None,
+            vac_banned: false,
+            publisher_banned: false,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+pub trait OidcIssuerService: Sync {
+    fn issuers(&self) -> &[OidcIssuerConfig];
+
+    /// Gets the signing keys of an issuer. With `refresh`, cached keys should
+    /// be downloaded again, since the issuer may have rotated its keys.
+    async fn get_keys(
+        &self,
+        issuer: &OidcIssuerConfig,
+        refresh: bool,
+    ) -> Result<JwkSet, SignInError>;
+
+    fn find_issuer(&self, name: &str) -> Option<&OidcIssuerConfig> {
+        self.issuers().iter().find(|issuer| issuer.name == name)
+    }
+
+    /// Verifies an ID token against the issuer's keys, issuer identifier and
+    /// client ids.
+    async fn verify(
+        &self,
+        issuer: &OidcIssuerConfig,
+        id_token: &str,
+    ) -> Result<OidcUser, SignInError> {
+        let header = jsonwebtoken::decode_header(id_token)
+            .map_err(|err| SignInError::InvalidCredential(err.to_string()))?;
+        let Some(kid) = header.kid else {
+            return Err(SignInError::InvalidCredential(
+                "The ID token has no key id".to_string(),
+            ));
+        };
+
+        let mut keys = self.get_keys(issuer, false).await?;
+        if keys.find(&kid).is_none() {
+            keys = self.get_keys(issuer, true).await?;
+        }
+        let Some(jwk) = keys.find(&kid) else {
+            return Err(SignInError::InvalidCredential(
+                "The ID token is signed by an unknown key".to_string(),
+            ));
+        };
+
+        if !ALLOWED_ALGORITHMS.contains(&header.alg) {
+            return Err(SignInError::InvalidCredential(
+                "The ID token is signed with an unsupported algorithm".to_string(),
+            ));
+        }
+        if let Some(key_algorithm) = jwk.common.key_algorithm {
+            if key_algorithm.to_string().parse::<Algorithm>().ok() != Some(header.alg) {
+                return Err(SignInError::InvalidCredential(
+                    "The ID token is signed with a different algorithm than its key".to_string(),
+                ));
+            }
+        }
+        let key = DecodingKey::from_jwk(jwk).map_err(SignInError::upstream_unavailable)?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_audience(&issuer.client_ids);
+        validation.set_issuer(&[&issuer.issuer]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+
+        let claims = jsonwebtoken::decode::<OidcClaims>(id_token, &key, &validation)
+            .map_err(|err| SignInError::InvalidCredential(err.to_string()))?
+            .claims;
+        Ok(OidcUser {
+            issuer_name: issuer.name.clone(),
+            claims,
+        })
+    }
+}
+
+/// Downloads the signing keys of an issuer through its discovery document.
+pub async fn download_keys(issuer: &OidcIssuerConfig) -> Result<JwkSet, SignInError> {
+    let client = reqwest::Client::new();
+    let discovery: DiscoveryDocument = client
+        .get(format!(
+            "{}/.well-known/openid-configuration",
+            issuer.issuer.trim_end_matches('/')
+        ))
+        .timeout(Duration::from_secs(5))
+        .send()
+        .await
+        .and_then(|resp| resp.error_for_status())
+        .map_err(SignInError::upstream_unavailable)?
+        .json()
+        .await
+        .map_err(SignInError::upstream_unavailable)?;
+    if discovery.issuer != issuer.issuer {
+        return Err(SignInError::UpstreamUnavailable(format!(
+            "The discovery document of {} is for a different issuer",
+            issuer.name
+        )));
+    }
+
+    client
+        .get(&discovery.jwks_uri)
+        .timeout(Duration::from_secs(5))
+        .send()
+        .await
+        .and_then(|resp| resp.error_for_status())
+        .map_err(SignInError::upstream_unavailable)?
+        .json()
+        .await
+        .map_err(SignInError::upstream_unavailable)
+}
+
+lazy_static::lazy_static! {
+    static ref KEY_CACHE: KeyCache = KeyCache::default();
+}
+
+pub struct RealOidcIssuerService;
+
+#[async_trait::async_trait]
+impl OidcIssuerService for RealOidcIssuerService {
+    fn issuers(&self) -> &[OidcIssuerConfig] {
+        &OIDC_ISSUERS
+    }
+
+    async fn get_keys(
+        &self,
+        issuer: &OidcIssuerConfig,
+        refresh: bool,
+    ) -> Result<JwkSet, SignInError> {
+        KEY_CACHE
+            .get(&issuer.name, refresh, || download_keys(issuer))
+            .await
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::auth::keys::access::AccessSigningKey;
+    use actix_web::{web, App, HttpResponse, HttpServer};
+    use chrono::Utc;
+    use jsonwebtoken::Header;
+
+    use super::*;
+
+    #[derive(Serialize)]
+    pub struct IdTokenClaims<C: Serialize> {
+        pub iss: String,
+        pub aud: String,
+        pub exp: i64,
+        #[serde(flatten)]
+        pub claims: C,
+    }
+
+    pub fn sign_id_token<C: Serialize>(
+        key: &AccessSigningKey,
+        claims: &IdTokenClaims<C>,
+    ) -> String {
+        let mut header = Header::new(key.algorithm);
+        header.kid = Some(key.kid.clone());
+        jsonwebtoken::encode(&header, claims, key.encoding_key()).expect("Failed to sign ID token")
+    }
+
+    pub fn test_claims(sub: &str) -> OidcClaims {
+        OidcClaims {
+            sub: sub.to_string(),
+            email: Some("bryan@example.com".to_string()),
+            email_verified: true,
+            name: Some("Bryan".to_string()),
+            preferred_username: Some("bryanmylee".to_string()),
+            picture: None,
+            locale: None,
+        }
+    }
+
+    /// Serves a discovery document and a key set on a local port.
+    async fn start_issuer(keys: JwkSet) -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("Failed to bind issuer");
+        let issuer = format!("http://{}", listener.local_addr().unwrap());
+        let discovery = DiscoveryDocument {
+            issuer: issuer.clone(),
+            jwks_uri: format!("{issuer}/jwks/"),
+        };
+        let server = HttpServer::new(move || {
+            let discovery = discovery.clone();
+            let keys = keys.clone();
+            App::new()
+                .route(
+                    "/.well-known/openid-configuration",
+                    web::get().to(move || {
+                        let discovery = discovery.clone();
+                        async move { HttpResponse::Ok().json(discovery) }
+                    }),
+                )
+                .route(
+                    "/jwks/",
+                    web::get().to(move || {
+                        let keys = keys.clone();
+                        async move { HttpResponse::Ok().json(keys) }
+                    }),
+                )
+        })
+        .workers(1)
+        .listen(listener)
+        .expect("Failed to listen")
+        .run();
+        actix_web::rt::spawn(server);
+        issuer
+    }
+
+    struct LocalOidcIssuerService {
+        issuers: Vec<OidcIssuerConfig>,
+        keys: KeyCache,
+    }
+
+    #[async_trait::async_trait]
+    impl OidcIssuerService for LocalOidcIssuerService {
+        fn issuers(&self) -> &[OidcIssuerConfig] {
+            &self.issuers
+        }
+
+        async fn get_keys(
+            &self,
+            issuer: &OidcIssuerConfig,
+            refresh: bool,
+        ) -> Result<JwkSet, SignInError> {
+            self.keys
+                .get(&issuer.name, refresh, || download_keys(issuer))
+                .await
+        }
+    }
+
+    #[actix_web::test]
+    async fn id_tokens_are_verified_against_the_issuer() {
+        let key = AccessSigningKey::generate().unwrap();
+        let issuer_url = start_issuer(JwkSet {
+            keys: vec![key.jwk().clone()],
+        })
+        .await;
+        let service = LocalOidcIssuerService {
+            issuers: vec![OidcIssuerConfig {
+                name: "local".to_string(),
+                issuer: issuer_url.clone(),
+                client_ids: vec!["game".to_string()],
+            }],
+            keys: KeyCache::default(),
+        };
+        let issuer = service.find_issuer("local").unwrap();
+        let valid = || IdTokenClaims {
+            iss: issuer_url.clone(),
+            aud: "game".to_string(),
+            exp: Utc::now().timestamp() + 600,
+            claims: test_claims("0001"),
+        };
+
+        let user = service
+            .verify(issuer, &sign_id_token(&key, &valid()))
+            .await
+            .unwrap();
+        assert_eq!(
+            user.provider_type(),
+            AuthProviderType::Oidc("local".to_string())
+        );
+        assert_eq!(user.claims.sub, "0001");
+        assert!(user.claims.email_verified);
+
+        let invalid_tokens = [
+            IdTokenClaims {
+                aud: "other-game".to_string(),
+                ..valid()
+            },
+            IdTokenClaims {
+                iss: "https://attacker.example.com".to_string(),
+                ..valid()
+            },
+            IdTokenClaims {
+                exp: Utc::now().timestamp() - 600,
+                ..valid()
+            },
+        ];
+        for claims in invalid_tokens {
+            let result = service.verify(issuer, &sign_id_token(&key, &claims)).await;
+            assert!(matches!(result, Err(SignInError::InvalidCredential(_))));
+        }
+
+        let unknown_key = AccessSigningKey::generate().unwrap();
+        let result = service
+            .verify(issuer, &sign_id_token(&unknown_key, &valid()))
+            .await;
+        assert!(matches!(result, Err(SignInError::InvalidCredential(_))));
+    }
+
+    #[test]
+    fn email_verified_may_be_a_string() {
+        #[derive(Deserialize)]
+        struct EmailVerified {
+            #[serde(deserialize_with = "deserialize_bool_or_string")]
+            email_verified: bool,
+        }
+
+        let key = AccessSigningKey::generate().unwrap();
+        let token = sign_id_token(
+            &key,
+            &IdTokenClaims {
+                iss: "https://appleid.apple.com".to_string(),
+                aud: "game".to_string(),
+                exp: Utc::now().timestamp() + 600,
+                claims: [("email_verified", "true")]
+                    .into_iter()
+                    .collect::<std::collections::HashMap<_, _>>(),
+            },
+        );
+        let mut validation = Validation::new(key.algorithm);
+        validation.set_audience(&["game"]);
+        let claims = jsonwebtoken::decode::<EmailVerified>(&token, key.decoding_key(), &validation)
+            .unwrap()
+            .claims;
+        assert!(claims.email_verified);
+    }
+}
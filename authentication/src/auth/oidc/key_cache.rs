@@ -0,0 +1,105 @@
+use crate::auth::sign_in_error::SignInError;
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::jwk::JwkSet;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::RwLock;
+
+/// How long an issuer's keys are cached for.
+const MAX_AGE_SECS: i64 = 60 * 60;
+
+/// How often an issuer's keys may be downloaded again for a token signed by an
+/// unknown key, so that made-up key ids cannot flood the issuer with requests.
+const MIN_REFRESH_INTERVAL_SECS: i64 = 60;
+
+struct CacheEntry {
+    keys: JwkSet,
+    fetched_at: DateTime<Utc>,
+}
+
+/// The signing keys of each issuer, by issuer name.
+#[derive(Default)]
+pub struct KeyCache {
+    entries: RwLock<HashMap<String, CacheEntry>>,
+}
+
+impl KeyCache {
+    /// Gets the keys of an issuer, downloading them if they are not cached or
+    /// too old. With `refresh`, cached keys are downloaded again unless they
+    /// were just downloaded, for when an issuer has rotated its keys.
+    pub async fn get<F, Fut>(
+        &self,
+        issuer_name: &str,
+        refresh: bool,
+        fetch: F,
+    ) -> Result<JwkSet, SignInError>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<JwkSet, SignInError>>,
+    {
+        {
+            let entries = self
+                .entries
+                .read()
+                .expect("Failed to get read lock on key cache");
+            if let Some(entry) = entries.get(issuer_name) {
+                let age = Utc::now() - entry.fetched_at;
+                let max_age = if refresh {
+                    Duration::seconds(MIN_REFRESH_INTERVAL_SECS)
+                } else {
+                    Duration::seconds(MAX_AGE_SECS)
+                };
+                if age < max_age {
+                    return Ok(entry.keys.clone());
+                }
+            }
+        }
+
+        let keys = fetch().await?;
+        self.entries
+            .write()
+            .expect("Failed to get write lock on key cache")
+            .insert(
+                issuer_name.to_string(),
+                CacheEntry {
+                    keys: keys.clone(),
+                    fetched_at: Utc::now(),
+                },
+            );
+        Ok(keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[actix_web::test]
+    async fn keys_are_not_refreshed_too_often() {
+        let cache = KeyCache::default();
+        let fetches = AtomicUsize::new(0);
+        let fetch = || async {
+            fetches.fetch_add(1, Ordering::SeqCst);
+            Ok(JwkSet { keys: vec![] })
+        };
+
+        cache.get("apple", false, fetch).await.unwrap();
+        cache.get("apple", false, fetch).await.unwrap();
+        cache.get("apple", true, fetch).await.unwrap();
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+
+        cache
+            .entries
+            .write()
+            .unwrap()
+            .get_mut("apple")
+            .unwrap()
+            .fetched_at -= Duration::seconds(MIN_REFRESH_INTERVAL_SECS);
+        cache.get("apple", false, fetch).await.unwrap();
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+        cache.get("apple", true, fetch).await.unwrap();
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+    }
+}
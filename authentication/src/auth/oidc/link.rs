@@ -0,0 +1,26 @@
+use crate::auth::identity::Identity;
+use crate::auth::link::link_provider;
+use crate::auth::oidc::issuer::OidcIssuerService;
+use crate::db::DbPool;
+use actix_web::{error, post, web, HttpResponse};
+
+/// Links a user of an OpenID Connect issuer to the caller.
+#[post("/{issuer}/link/")]
+async fn link(
+    issuer_name: web::Path<String>,
+    id_token: String,
+    identity: Identity,
+    pool: web::Data<DbPool>,
+    oidc_issuer_service: web::Data<dyn OidcIssuerService>,
+) -> actix_web::Result<HttpResponse> {
+    let Some(issuer) = oidc_issuer_service.find_issuer(&issuer_name) else {
+        return Err(error::ErrorNotFound(format!(
+            "No issuer found with name {issuer_name}"
+        )));
+    };
+    let oidc_user = oidc_issuer_service.verify(issuer, &id_token).await?;
+
+    let mut conn = pool.get().await.map_err(error::ErrorInternalServerError)?;
+    let user_with_providers = link_provider(&mut conn, &identity.user_id, &oidc_user).await?;
+    Ok(HttpResponse::Ok().json(user_with_providers))
+}
@@ -0,0 +1,16 @@
+pub mod issuer;
+mod key_cache;
+mod link;
+mod sign_in;
+
+use self::issuer::{OidcIssuerService, RealOidcIssuerService};
+use actix_web::web;
+use std::sync::Arc;
+
+pub fn config_service(cfg: &mut web::ServiceConfig) {
+    let oidc_issuer_service =
+        web::Data::from(Arc::new(RealOidcIssuerService) as Arc<dyn OidcIssuerService>);
+    cfg.app_data(oidc_issuer_service)
+        .service(sign_in::sign_in)
+        .service(link::link);
+}
@@ -0,0 +1,179 @@
+use crate::auth::device::DeviceInfo;
+use crate::auth::identity::IdentityConfig;
+use crate::auth::oidc::issuer::OidcIssuerService;
+use crate::auth::provider::{AuthProvider, AuthProviderChangeset};
+use crate::auth::{create_new_user, generate_sign_in_success_response};
+use crate::db::DbPool;
+use crate::schema;
+use crate::user::{User, UserWithAuthProviders};
+use actix_web::{error, post, web, HttpResponse};
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+
+/// Signs in with an ID token of a configured OpenID Connect issuer.
+#[post("/{issuer}/sign-in/")]
+async fn sign_in(
+    issuer_name: web::Path<String>,
+    id_token: String,
+    pool: web::Data<DbPool>,
+    identity_config: web::Data<IdentityConfig>,
+    device: DeviceInfo,
+    oidc_issuer_service: web::Data<dyn OidcIssuerService>,
+) -> actix_web::Result<HttpResponse> {
+    let Some(issuer) = oidc_issuer_service.find_issuer(&issuer_name) else {
+        return Err(error::ErrorNotFound(format!(
+            "No issuer found with name {issuer_name}"
+        )));
+    };
+    let oidc_user = oidc_issuer_service.verify(issuer, &id_token).await?;
+
+    let mut conn = pool.get().await.map_err(error::ErrorInternalServerError)?;
+
+    let provider_changeset: AuthProviderChangeset = (&oidc_user).into();
+    let matching_provider: Option<AuthProvider> = diesel::update(schema::auth_provider::table)
+        .filter(schema::auth_provider::provider_id.eq(&oidc_user.claims.sub))
+        .filter(schema::auth_provider::provider_type.eq(oidc_user.provider_type()))
+        .set(&provider_changeset)
+        .get_result(&mut conn)
+        .await
+        .optional()
+        .map_err(error::ErrorInternalServerError)?;
+
+    if let Some(matching_provider) = matching_provider {
+        let user: User = schema::user::table
+            .filter(schema::user::id.eq(&matching_provider.user_id))
+            .first(&mut conn)
+            .await
+            .map_err(error::ErrorInternalServerError)?;
+
+        let providers = user
+            .get_providers(&mut conn)
+            .await
+            .map_err(error::ErrorInternalServerError)?;
+
+        return generate_sign_in_success_response(
+            &mut conn,
+            UserWithAuthProviders { user, providers },
+            &oidc_user.provider_type(),
+            &identity_config,
+            &device,
+        )
+        .await;
+    }
+
+    let new_user = create_new_user(&mut conn, &oidc_user)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    generate_sign_in_success_response(
+        &mut conn,
+        new_user,
+        &oidc_user.provider_type(),
+        &identity_config,
+        &device,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::keys::access::AccessSigningKey;
+    use crate::auth::oidc::issuer::tests::{sign_id_token, test_claims, IdTokenClaims};
+    use crate::auth::provider::AuthProviderType;
+    use crate::auth::sign_in_error::SignInError;
+    use crate::auth::SignInResult;
+    use crate::config::OidcIssuerConfig;
+    use crate::{config, db};
+    use actix_web::{http::StatusCode, test, App};
+    use chrono::Utc;
+    use jsonwebtoken::jwk::JwkSet;
+    use std::sync::Arc;
+
+    use super::*;
+
+    struct MockOidcIssuerService {
+        issuers: Vec<OidcIssuerConfig>,
+        keys: JwkSet,
+    }
+
+    #[async_trait::async_trait]
+    impl OidcIssuerService for MockOidcIssuerService {
+        fn issuers(&self) -> &[OidcIssuerConfig] {
+            &self.issuers
+        }
+
+        async fn get_keys(
+            &self,
+            _issuer: &OidcIssuerConfig,
+            _refresh: bool,
+        ) -> Result<JwkSet, SignInError> {
+            Ok(self.keys.clone())
+        }
+    }
+
+    #[actix_web::test]
+    async fn each_issuer_has_its_own_provider_type() {
+        let key = AccessSigningKey::generate().unwrap();
+        let issuers = ["apple", "discord"].map(|name| OidcIssuerConfig {
+            name: name.to_string(),
+            issuer: format!("https://{name}.example.com"),
+            client_ids: vec!["game".to_string()],
+        });
+        let oidc_issuer_service = web::Data::from(Arc::new(MockOidcIssuerService {
+            issuers: issuers.to_vec(),
+            keys: JwkSet {
+                keys: vec![key.jwk().clone()],
+            },
+        }) as Arc<dyn OidcIssuerService>);
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(
+                    db::initialize_db_pool(&config::DB_URL).await,
+                ))
+                .app_data(web::Data::new(config::IDENTITY_CONFIG.clone()))
+                .app_data(oidc_issuer_service)
+                .service(sign_in),
+        )
+        .await;
+
+        let mut user_ids = vec![];
+        for issuer in ["apple", "discord", "apple"] {
+            let id_token = sign_id_token(
+                &key,
+                &IdTokenClaims {
+                    iss: format!("https://{issuer}.example.com"),
+                    aud: "game".to_string(),
+                    exp: Utc::now().timestamp() + 600,
+                    claims: test_claims("0001"),
+                },
+            );
+            let req = test::TestRequest::post()
+                .uri(&format!("/{issuer}/sign-in/"))
+                .set_payload(id_token)
+                .to_request();
+            let SignInResult::Success(success) = test::call_and_read_body_json(&app, req).await
+            else {
+                panic!("Expected the sign-in to succeed");
+            };
+            assert_eq!(
+                success.user.providers[0].provider_type,
+                AuthProviderType::Oidc(issuer.to_string())
+            );
+            assert_eq!(
+                success.user.providers[0].user_name.as_deref(),
+                Some("bryanmylee")
+            );
+            user_ids.push(success.user.user.id);
+        }
+        assert_ne!(user_ids[0], user_ids[1]);
+        assert_eq!(user_ids[0], user_ids[2]);
+
+        let req = test::TestRequest::post()
+            .uri("/microsoft/sign-in/")
+            .set_payload("token")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,120 @@
+use crate::auth::identity::IdentityConfig;
+use crate::auth::provider::AuthProvider;
+use crate::config::PASSWORD_CONFIG;
+use crate::mail::{Mail, Mailer};
+use actix_web::error;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL_SAFE_NO_PAD;
+use base64::prelude::*;
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+const VERIFY_EMAIL_AUDIENCE: &str = "verify_email";
+const VERIFY_EMAIL_EXPIRES_IN_HOURS: i64 = 24;
+
+const RESET_PASSWORD_AUDIENCE: &str = "reset_password";
+const RESET_PASSWORD_EXPIRES_IN_MINUTES: i64 = 60;
+
+/// A token sent by mail to prove that the user can read the provider's email.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct EmailTokenClaims {
+    aud: String,
+    iat: u64,
+    exp: u64,
+    /// The password provider.
+    pub sub: Uuid,
+    /// The email that the token was sent to.
+    pub email: String,
+    /// For password resets, a fingerprint of the password hash being reset,
+    /// so that the token stops working once it is used.
+    pub pwd: Option<String>,
+}
+
+impl EmailTokenClaims {
+    fn new(audience: &str, expires_in: Duration, provider: &AuthProvider) -> Self {
+        let now = Utc::now();
+        EmailTokenClaims {
+            aud: audience.to_string(),
+            iat: now.timestamp() as u64,
+            exp: (now + expires_in).timestamp() as u64,
+            sub: provider.id,
+            email: provider.provider_id.clone(),
+            pwd: None,
+        }
+    }
+
+    pub fn decode_verify_email(config: &IdentityConfig, token: &str) -> actix_web::Result<Self> {
+        config.decode_client_claims(token, VERIFY_EMAIL_AUDIENCE)
+    }
+
+    pub fn decode_reset_password(config: &IdentityConfig, token: &str) -> actix_web::Result<Self> {
+        config.decode_client_claims(token, RESET_PASSWORD_AUDIENCE)
+    }
+}
+
+pub fn password_fingerprint(password_hash: &str) -> String {
+    BASE64_URL_SAFE_NO_PAD.encode(&openssl::sha::sha256(password_hash.as_bytes())[..16])
+}
+
+/// The link to a page that takes the token, or the token itself if no page is
+/// configured.
+fn token_link(url: &Option<String>, token: &str) -> String {
+    match url {
+        Some(url) => format!("{url}?token={token}"),
+        None => token.to_string(),
+    }
+}
+
+pub async fn send_verification_email(
+    mailer: &dyn Mailer,
+    identity_config: &IdentityConfig,
+    provider: &AuthProvider,
+) -> actix_web::Result<()> {
+    let claims = EmailTokenClaims::new(
+        VERIFY_EMAIL_AUDIENCE,
+        Duration::hours(VERIFY_EMAIL_EXPIRES_IN_HOURS),
+        provider,
+    );
+    let token = identity_config.encode_client_claims(&claims);
+    let link = token_link(&PASSWORD_CONFIG.verify_email_url, &token);
+    let mail = Mail {
+        to: claims.email,
+        subject: "Verify your email".to_string(),
+        body: format!(
+            "Verify your email with:\n\n{link}\n\nThis expires in {VERIFY_EMAIL_EXPIRES_IN_HOURS} hours."
+        ),
+    };
+    mailer
+        .send(&mail)
+        .await
+        .map_err(error::ErrorInternalServerError)
+}
+
+pub async fn send_password_reset_email(
+    mailer: &dyn Mailer,
+    identity_config: &IdentityConfig,
+    provider: &AuthProvider,
+    password_hash: &str,
+) -> actix_web::Result<()> {
+    let claims = EmailTokenClaims {
+        pwd: Some(password_fingerprint(password_hash)),
+        ..EmailTokenClaims::new(
+            RESET_PASSWORD_AUDIENCE,
+            Duration::minutes(RESET_PASSWORD_EXPIRES_IN_MINUTES),
+            provider,
+        )
+    };
+    let token = identity_config.encode_client_claims(&claims);
+    let link = token_link(&PASSWORD_CONFIG.reset_password_url, &token);
+    let mail = Mail {
+        to: claims.email,
+        subject: "Reset your password".to_string(),
+        body: format!(
+            "Reset your password with:\n\n{link}\n\nThis expires in {RESET_PASSWORD_EXPIRES_IN_MINUTES} minutes. If you did not ask to reset your password, you can ignore this email."
+        ),
+    };
+    mailer
+        .send(&mail)
+        .await
+        .map_err(error::ErrorInternalServerError)
+}
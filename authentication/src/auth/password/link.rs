@@ -0,0 +1,56 @@
+use crate::auth::identity::{Identity, IdentityConfig};
+use crate::auth::link::link_provider;
+use crate::auth::password::email_token::send_verification_email;
+use crate::auth::password::{
+    find_credential, hash_password, set_password_hash, Credentials, PasswordUser,
+};
+use crate::auth::provider::AuthProviderType;
+use crate::db::DbPool;
+use crate::mail::Mailer;
+use actix_web::{error, post, web, HttpResponse};
+
+/// Adds an email and password to the caller, e.g. so that a guest can sign in
+/// on other devices.
+#[post("/link/")]
+async fn link(
+    credentials: web::Json<Credentials>,
+    identity: Identity,
+    pool: web::Data<DbPool>,
+    identity_config: web::Data<IdentityConfig>,
+    mailer: web::Data<dyn Mailer>,
+) -> actix_web::Result<HttpResponse> {
+    let Credentials { email, password } = credentials.into_inner();
+    let password_user = PasswordUser::parse(&email)?;
+    let password_hash = hash_password(password).await?;
+
+    let mut conn = pool.get().await.map_err(error::ErrorInternalServerError)?;
+    // Changing the password of a linked email goes through a password reset.
+    if find_credential(&mut conn, &password_user.email)
+        .await
+        .map_err(error::ErrorInternalServerError)?
+        .is_some()
+    {
+        return Err(error::ErrorConflict(
+            "An account with this email already exists",
+        ));
+    }
+
+    let user_with_providers = link_provider(&mut conn, &identity.user_id, &password_user).await?;
+    let Some(provider) = user_with_providers.providers.iter().find(|provider| {
+        provider.provider_type == AuthProviderType::Password
+            && provider.provider_id == password_user.email
+    }) else {
+        return Err(error::ErrorInternalServerError(
+            "The linked provider is missing",
+        ));
+    };
+    set_password_hash(&mut conn, &provider.id, &password_hash)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    if let Err(err) = send_verification_email(mailer.get_ref(), &identity_config, provider).await {
+        println!("Failed to send a verification email: {err}");
+    }
+
+    Ok(HttpResponse::Ok().json(user_with_providers))
+}
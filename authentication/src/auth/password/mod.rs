@@ -0,0 +1,230 @@
+mod email_token;
+mod link;
+mod reset_password;
+mod sign_in;
+mod sign_up;
+mod verify_email;
+
+use crate::auth::provider::{
+    AuthProvider, AuthProviderInsert, AuthProviderType, IntoAuthProviderInsert,
+};
+use crate::config::MAIL_CONFIG;
+use crate::db::{DbConnection, DbError};
+use crate::schema;
+use crate::user::{User, UserInsert};
+use actix_web::{error, web};
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use chrono::Utc;
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use serde::Deserialize;
+use uuid::Uuid;
+
+const MIN_PASSWORD_LENGTH: usize = 8;
+const MAX_PASSWORD_LENGTH: usize = 128;
+const MAX_EMAIL_LENGTH: usize = 254;
+
+pub fn config_service(cfg: &mut web::ServiceConfig) {
+    let mailer = web::Data::from(crate::mail::configured_mailer(&MAIL_CONFIG));
+    cfg.app_data(mailer)
+        .service(sign_up::sign_up)
+        .service(sign_in::sign_in)
+        .service(link::link)
+        .service(verify_email::verify_email)
+        .service(verify_email::request_verification)
+        .service(reset_password::reset_password)
+        .service(reset_password::request_reset);
+}
+
+#[derive(Debug, Deserialize)]
+#[cfg_attr(test, derive(serde::Serialize))]
+pub struct Credentials {
+    email: String,
+    password: String,
+}
+
+/// A password provider, identified by its lowercased email.
+pub struct PasswordUser {
+    pub email: String,
+}
+
+impl PasswordUser {
+    pub fn parse(email: &str) -> actix_web::Result<Self> {
+        let email = email.trim().to_lowercase();
+        let is_valid = email.len() <= MAX_EMAIL_LENGTH
+            && !email.contains(char::is_whitespace)
+            && email.split_once('@').is_some_and(|(local, domain)| {
+                !local.is_empty() && domain.contains('.') && !domain.contains('@')
+            });
+        if !is_valid {
+            return Err(error::ErrorBadRequest("The email is invalid"));
+        }
+        Ok(PasswordUser { email })
+    }
+}
+
+impl From<&PasswordUser> for UserInsert {
+    fn from(_value: &PasswordUser) -> Self {
+        UserInsert::default()
+    }
+}
+
+impl IntoAuthProviderInsert for PasswordUser {
+    fn into_provider_insert(&self, user: &User) -> AuthProviderInsert {
+        AuthProviderInsert {
+            user_id: user.id,
+            provider_type: AuthProviderType::Password,
+            provider_id: self.email.clone(),
+            email: Some(self.email.clone()),
+            ..Default::default()
+        }
+    }
+}
+
+fn validate_password(password: &str) -> actix_web::Result<()> {
+    let length = password.chars().count();
+    if !(MIN_PASSWORD_LENGTH..=MAX_PASSWORD_LENGTH).contains(&length) {
+        return Err(error::ErrorBadRequest(format!(
+            "The password should have {MIN_PASSWORD_LENGTH} to {MAX_PASSWORD_LENGTH} characters"
+        )));
+    }
+    Ok(())
+}
+
+/// Hashes a password with Argon2 on the blocking thread pool.
+pub async fn hash_password(password: String) -> actix_web::Result<String> {
+    validate_password(&password)?;
+    web::block(move || {
+        let mut salt = [0; 16];
+        openssl::rand::rand_bytes(&mut salt).expect("Failed to generate random bytes");
+        let salt = SaltString::encode_b64(&salt)?;
+        Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+    })
+    .await
+    .map_err(error::ErrorInternalServerError)?
+    .map_err(error::ErrorInternalServerError)
+}
+
+/// Checks a password against its hash on the blocking thread pool.
+pub async fn verify_password(password: String, password_hash: String) -> actix_web::Result<bool> {
+    web::block(move || {
+        let password_hash = PasswordHash::new(&password_hash)?;
+        Ok::<_, argon2::password_hash::Error>(
+            Argon2::default()
+                .verify_password(password.as_bytes(), &password_hash)
+                .is_ok(),
+        )
+    })
+    .await
+    .map_err(error::ErrorInternalServerError)?
+    .map_err(error::ErrorInternalServerError)
+}
+
+/// Finds the password provider of an email with its password hash.
+pub async fn find_credential(
+    conn: &mut DbConnection,
+    email: &str,
+) -> Result<Option<(AuthProvider, String)>, DbError> {
+    schema::auth_provider::table
+        .inner_join(schema::password_credential::table)
+        .filter(schema::auth_provider::provider_type.eq(AuthProviderType::Password))
+        .filter(schema::auth_provider::provider_id.eq(email))
+        .select((
+            AuthProvider::as_select(),
+            schema::password_credential::password_hash,
+        ))
+        .first(conn)
+        .await
+        .optional()
+}
+
+/// Sets the password hash of a password provider.
+pub async fn set_password_hash(
+    conn: &mut DbConnection,
+    auth_provider_id: &Uuid,
+    password_hash: &str,
+) -> Result<(), DbError> {
+    let updated_at = Utc::now();
+    diesel::insert_into(schema::password_credential::table)
+        .values((
+            schema::password_credential::auth_provider_id.eq(auth_provider_id),
+            schema::password_credential::password_hash.eq(password_hash),
+            schema::password_credential::updated_at.eq(updated_at),
+        ))
+        .on_conflict(schema::password_credential::auth_provider_id)
+        .do_update()
+        .set((
+            schema::password_credential::password_hash.eq(password_hash),
+            schema::password_credential::updated_at.eq(updated_at),
+        ))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::mail::{Mail, MailError, Mailer};
+    use std::sync::Mutex;
+
+    use super::*;
+
+    /// Keeps outgoing mail so that tests can follow the links in it.
+    #[derive(Default)]
+    pub struct RecordingMailer {
+        pub mails: Mutex<Vec<Mail>>,
+    }
+
+    impl RecordingMailer {
+        /// The token sent in the last mail.
+        pub fn last_token(&self) -> String {
+            let mails = self.mails.lock().unwrap();
+            let mail = mails.last().expect("Expected a mail to be sent");
+            mail.body
+                .lines()
+                .find(|line| line.starts_with("ey"))
+                .expect("Expected the mail to contain a token")
+                .to_string()
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl Mailer for RecordingMailer {
+        async fn send(&self, mail: &Mail) -> Result<(), MailError> {
+            self.mails.lock().unwrap().push(mail.clone());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn emails_are_normalized() {
+        assert_eq!(
+            PasswordUser::parse(" Bryan@Example.com ").unwrap().email,
+            "bryan@example.com"
+        );
+        for email in [
+            "bryan",
+            "@example.com",
+            "bryan@localhost",
+            "a b@example.com",
+        ] {
+            assert!(PasswordUser::parse(email).is_err(), "{email}");
+        }
+    }
+
+    #[actix_web::test]
+    async fn passwords_are_verified_against_their_hash() {
+        let hash = hash_password("correct horse".to_string()).await.unwrap();
+        assert!(hash.starts_with("$argon2id$"));
+        assert!(verify_password("correct horse".to_string(), hash.clone())
+            .await
+            .unwrap());
+        assert!(!verify_password("battery staple".to_string(), hash)
+            .await
+            .unwrap());
+        assert!(hash_password("short".to_string()).await.is_err());
+    }
+}
@@ -0,0 +1,208 @@
+use crate::auth::device::DeviceInfo;
+use crate::auth::event::{AuthEventInsert, AuthEventKind, AuthEventOutcome};
+use crate::auth::identity::IdentityConfig;
+use crate::auth::password::email_token::{
+    password_fingerprint, send_password_reset_email, EmailTokenClaims,
+};
+use crate::auth::password::{find_credential, hash_password, set_password_hash, PasswordUser};
+use crate::auth::provider::AuthProviderType;
+use crate::auth::refresh::session::RefreshSession;
+use crate::auth::revocation::{self, RevocationKind};
+use crate::db::DbPool;
+use crate::mail::Mailer;
+use crate::schema;
+use actix_web::{error, post, web, HttpResponse};
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+#[cfg_attr(test, derive(serde::Serialize))]
+struct RequestResetBody {
+    email: String,
+}
+
+/// Mails a password reset link if the email belongs to a user.
+///
+/// Always succeeds, so that the route cannot be used to find out which emails
+/// have an account.
+#[post("/reset-password/request/")]
+async fn request_reset(
+    body: web::Json<RequestResetBody>,
+    pool: web::Data<DbPool>,
+    identity_config: web::Data<IdentityConfig>,
+    mailer: web::Data<dyn Mailer>,
+) -> actix_web::Result<HttpResponse> {
+    let password_user = PasswordUser::parse(&body.email)?;
+
+    let mut conn = pool.get().await.map_err(error::ErrorInternalServerError)?;
+    let credential = find_credential(&mut conn, &password_user.email)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+    if let Some((provider, password_hash)) = credential {
+        send_password_reset_email(
+            mailer.get_ref(),
+            &identity_config,
+            &provider,
+            &password_hash,
+        )
+        .await?;
+    }
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[derive(Debug, Deserialize)]
+#[cfg_attr(test, derive(serde::Serialize))]
+struct ResetPasswordBody {
+    token: String,
+    password: String,
+}
+
+/// Sets a new password with a token that was mailed to the email, and signs
+/// the user out everywhere.
+#[post("/reset-password/")]
+async fn reset_password(
+    body: web::Json<ResetPasswordBody>,
+    pool: web::Data<DbPool>,
+    identity_config: web::Data<IdentityConfig>,
+    device: DeviceInfo,
+) -> actix_web::Result<HttpResponse> {
+    let ResetPasswordBody { token, password } = body.into_inner();
+    let claims = EmailTokenClaims::decode_reset_password(&identity_config, &token)?;
+    let password_hash = hash_password(password).await?;
+
+    let mut conn = pool.get().await.map_err(error::ErrorInternalServerError)?;
+    let credential = find_credential(&mut conn, &claims.email)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+    let Some((provider, current_password_hash)) = credential else {
+        return Err(error::ErrorNotFound("The email is no longer linked"));
+    };
+    if provider.id != claims.sub
+        || claims.pwd.as_deref() != Some(&password_fingerprint(&current_password_hash))
+    {
+        return Err(error::ErrorBadRequest("The token has already been used"));
+    }
+
+    set_password_hash(&mut conn, &provider.id, &password_hash)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+    // Following the link proves that the user can read the email.
+    diesel::update(schema::auth_provider::table.find(provider.id))
+        .set(schema::auth_provider::email_verified.eq(true))
+        .execute(&mut conn)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    RefreshSession::invalidate_all(&mut conn, &provider.user_id)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+    revocation::revoke(
+        &mut conn,
+        &identity_config,
+        RevocationKind::User,
+        &provider.user_id,
+    )
+    .await
+    .map_err(error::ErrorInternalServerError)?;
+    AuthEventInsert::new(
+        provider.user_id,
+        AuthEventKind::PasswordReset,
+        AuthEventOutcome::Success,
+        &device,
+    )
+    .provider_type(&AuthProviderType::Password)
+    .record(&mut conn)
+    .await
+    .map_err(error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::password::sign_in::sign_in;
+    use crate::auth::password::sign_up::sign_up;
+    use crate::auth::password::tests::RecordingMailer;
+    use crate::auth::password::Credentials;
+    use crate::{config, db};
+    use actix_web::{http::StatusCode, test, App};
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[actix_web::test]
+    async fn passwords_are_reset_once_with_the_mailed_token() {
+        let mailer = Arc::new(RecordingMailer::default());
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(
+                    db::initialize_db_pool(&config::DB_URL).await,
+                ))
+                .app_data(web::Data::new(config::IDENTITY_CONFIG.clone()))
+                .app_data(web::Data::from(mailer.clone() as Arc<dyn Mailer>))
+                .service(sign_up)
+                .service(sign_in)
+                .service(request_reset)
+                .service(reset_password),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/sign-up/")
+            .set_json(Credentials {
+                email: "bryan@example.com".to_string(),
+                password: "correct horse".to_string(),
+            })
+            .to_request();
+        test::call_service(&app, req).await;
+
+        let req = test::TestRequest::post()
+            .uri("/reset-password/request/")
+            .set_json(RequestResetBody {
+                email: "adam@example.com".to_string(),
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(mailer.mails.lock().unwrap().len(), 1);
+
+        let req = test::TestRequest::post()
+            .uri("/reset-password/request/")
+            .set_json(RequestResetBody {
+                email: "bryan@example.com".to_string(),
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let token = mailer.last_token();
+
+        for expected_status in [StatusCode::OK, StatusCode::BAD_REQUEST] {
+            let req = test::TestRequest::post()
+                .uri("/reset-password/")
+                .set_json(ResetPasswordBody {
+                    token: token.clone(),
+                    password: "battery staple".to_string(),
+                })
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), expected_status);
+        }
+
+        for (password, expected_status) in [
+            ("correct horse", StatusCode::UNAUTHORIZED),
+            ("battery staple", StatusCode::OK),
+        ] {
+            let req = test::TestRequest::post()
+                .uri("/sign-in/")
+                .set_json(Credentials {
+                    email: "bryan@example.com".to_string(),
+                    password: password.to_string(),
+                })
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), expected_status);
+        }
+    }
+}
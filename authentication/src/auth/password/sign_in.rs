@@ -0,0 +1,146 @@
+use crate::auth::device::DeviceInfo;
+use crate::auth::event::{AuthEventInsert, AuthEventKind, AuthEventOutcome};
+use crate::auth::generate_sign_in_success_response;
+use crate::auth::identity::IdentityConfig;
+use crate::auth::password::{find_credential, verify_password, Credentials, PasswordUser};
+use crate::auth::provider::AuthProviderType;
+use crate::auth::sign_in_error::SignInError;
+use crate::db::DbPool;
+use crate::schema;
+use crate::user::{User, UserWithAuthProviders};
+use actix_web::{error, post, web, HttpResponse};
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+
+/// Signs in with an email and password.
+#[post("/sign-in/")]
+async fn sign_in(
+    credentials: web::Json<Credentials>,
+    pool: web::Data<DbPool>,
+    identity_config: web::Data<IdentityConfig>,
+    device: DeviceInfo,
+) -> actix_web::Result<HttpResponse> {
+    let Credentials { email, password } = credentials.into_inner();
+    let password_user = PasswordUser::parse(&email)?;
+
+    let mut conn = pool.get().await.map_err(error::ErrorInternalServerError)?;
+    let credential = find_credential(&mut conn, &password_user.email)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+    let Some((provider, password_hash)) = credential else {
+        return Err(SignInError::InvalidCredential("Wrong email or password".to_string()).into());
+    };
+    if !verify_password(password, password_hash).await? {
+        AuthEventInsert::new(
+            provider.user_id,
+            AuthEventKind::SignIn,
+            AuthEventOutcome::WrongPassword,
+            &device,
+        )
+        .provider_type(&AuthProviderType::Password)
+        .record(&mut conn)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+        return Err(SignInError::InvalidCredential("Wrong email or password".to_string()).into());
+    }
+
+    let user: User = schema::user::table
+        .filter(schema::user::id.eq(&provider.user_id))
+        .first(&mut conn)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    let providers = user
+        .get_providers(&mut conn)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    generate_sign_in_success_response(
+        &mut conn,
+        UserWithAuthProviders { user, providers },
+        &AuthProviderType::Password,
+        &identity_config,
+        &device,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::password::sign_up::sign_up;
+    use crate::auth::password::tests::RecordingMailer;
+    use crate::auth::provider::AuthProviderType;
+    use crate::auth::SignInResult;
+    use crate::mail::Mailer;
+    use crate::{config, db};
+    use actix_web::{http::StatusCode, test, App};
+    use std::sync::Arc;
+
+    use super::*;
+
+    fn credentials(email: &str, password: &str) -> Credentials {
+        Credentials {
+            email: email.to_string(),
+            password: password.to_string(),
+        }
+    }
+
+    #[actix_web::test]
+    async fn users_sign_in_with_the_password_they_signed_up_with() {
+        let mailer = Arc::new(RecordingMailer::default());
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(
+                    db::initialize_db_pool(&config::DB_URL).await,
+                ))
+                .app_data(web::Data::new(config::IDENTITY_CONFIG.clone()))
+                .app_data(web::Data::from(mailer.clone() as Arc<dyn Mailer>))
+                .service(sign_up)
+                .service(sign_in),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/sign-up/")
+            .set_json(credentials("Bryan@Example.com", "correct horse"))
+            .to_request();
+        let SignInResult::Success(signed_up) = test::call_and_read_body_json(&app, req).await
+        else {
+            panic!("Expected the sign-up to succeed");
+        };
+        let provider = &signed_up.user.providers[0];
+        assert_eq!(provider.provider_type, AuthProviderType::Password);
+        assert_eq!(provider.provider_id, "bryan@example.com");
+        assert!(!provider.email_verified);
+        assert_eq!(mailer.mails.lock().unwrap()[0].to, "bryan@example.com");
+
+        let req = test::TestRequest::post()
+            .uri("/sign-up/")
+            .set_json(credentials("bryan@example.com", "battery staple"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+
+        let req = test::TestRequest::post()
+            .uri("/sign-in/")
+            .set_json(credentials("bryan@example.com", "correct horse"))
+            .to_request();
+        let SignInResult::Success(signed_in) = test::call_and_read_body_json(&app, req).await
+        else {
+            panic!("Expected the sign-in to succeed");
+        };
+        assert_eq!(signed_in.user.user.id, signed_up.user.user.id);
+
+        for (email, password) in [
+            ("bryan@example.com", "battery staple"),
+            ("adam@example.com", "correct horse"),
+        ] {
+            let req = test::TestRequest::post()
+                .uri("/sign-in/")
+                .set_json(credentials(email, password))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use crate::auth::device::DeviceInfo;
+use crate::auth::generate_sign_in_success_response;
+use crate::auth::identity::IdentityConfig;
+use crate::auth::password::email_token::send_verification_email;
+use crate::auth::password::{hash_password, set_password_hash, Credentials, PasswordUser};
+use crate::auth::provider::{AuthProvider, AuthProviderType, IntoAuthProviderInsert};
+use crate::db::{DbError, DbPool};
+use crate::mail::Mailer;
+use crate::schema;
+use crate::user::{User, UserInsert, UserWithAuthProviders};
+use actix_web::{error, post, web, HttpResponse};
+use diesel::result::DatabaseErrorKind;
+use diesel_async::scoped_futures::ScopedFutureExt;
+use diesel_async::{AsyncConnection, RunQueryDsl};
+
+/// Creates a user with an email and password, and mails them a link to verify
+/// their email.
+#[post("/sign-up/")]
+async fn sign_up(
+    credentials: web::Json<Credentials>,
+    pool: web::Data<DbPool>,
+    identity_config: web::Data<IdentityConfig>,
+    device: DeviceInfo,
+    mailer: web::Data<dyn Mailer>,
+) -> actix_web::Result<HttpResponse> {
+    let Credentials { email, password } = credentials.into_inner();
+    let password_user = PasswordUser::parse(&email)?;
+    let password_hash = hash_password(password).await?;
+
+    let mut conn = pool.get().await.map_err(error::ErrorInternalServerError)?;
+    let new_user = conn
+        .transaction::<_, DbError, _>(|conn| {
+            async move {
+                let user: User = diesel::insert_into(schema::user::table)
+                    .values(UserInsert::from(&password_user))
+                    .get_result(conn)
+                    .await?;
+                let provider: AuthProvider = diesel::insert_into(schema::auth_provider::table)
+                    .values(password_user.into_provider_insert(&user))
+                    .get_result(conn)
+                    .await?;
+                set_password_hash(conn, &provider.id, &password_hash).await?;
+                Ok(UserWithAuthProviders {
+                    user,
+                    providers: vec![provider],
+                })
+            }
+            .scope_boxed()
+        })
+        .await
+        .map_err(|err| match err {
+            DbError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+                error::ErrorConflict("An account with this email already exists")
+            }
+            err => error::ErrorInternalServerError(err),
+        })?;
+
+    // The user can ask for another mail, so a failed mail does not fail the
+    // sign-up.
+    if let Err(err) =
+        send_verification_email(mailer.get_ref(), &identity_config, &new_user.providers[0]).await
+    {
+        println!("Failed to send a verification email: {err}");
+    }
+
+    generate_sign_in_success_response(
+        &mut conn,
+        new_user,
+        &AuthProviderType::Password,
+        &identity_config,
+        &device,
+    )
+    .await
+}
@@ -0,0 +1,136 @@
+use crate::auth::identity::{Identity, IdentityConfig};
+use crate::auth::password::email_token::{send_verification_email, EmailTokenClaims};
+use crate::auth::provider::{AuthProvider, AuthProviderType};
+use crate::db::DbPool;
+use crate::mail::Mailer;
+use crate::schema;
+use actix_web::{error, post, web, HttpResponse};
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+#[cfg_attr(test, derive(serde::Serialize))]
+struct VerifyEmailBody {
+    token: String,
+}
+
+/// Marks the email of a password provider as verified with a token that was
+/// mailed to it.
+#[post("/verify-email/")]
+async fn verify_email(
+    body: web::Json<VerifyEmailBody>,
+    pool: web::Data<DbPool>,
+    identity_config: web::Data<IdentityConfig>,
+) -> actix_web::Result<HttpResponse> {
+    let claims = EmailTokenClaims::decode_verify_email(&identity_config, &body.token)?;
+
+    let mut conn = pool.get().await.map_err(error::ErrorInternalServerError)?;
+    let updated = diesel::update(schema::auth_provider::table.find(claims.sub))
+        .filter(schema::auth_provider::provider_type.eq(AuthProviderType::Password))
+        .filter(schema::auth_provider::provider_id.eq(&claims.email))
+        .set(schema::auth_provider::email_verified.eq(true))
+        .execute(&mut conn)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+    if updated == 0 {
+        return Err(error::ErrorNotFound("The email is no longer linked"));
+    }
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Mails another verification link to each unverified email of the caller.
+#[post("/verify-email/request/")]
+async fn request_verification(
+    identity: Identity,
+    pool: web::Data<DbPool>,
+    identity_config: web::Data<IdentityConfig>,
+    mailer: web::Data<dyn Mailer>,
+) -> actix_web::Result<HttpResponse> {
+    let mut conn = pool.get().await.map_err(error::ErrorInternalServerError)?;
+    let providers: Vec<AuthProvider> = schema::auth_provider::table
+        .filter(schema::auth_provider::user_id.eq(identity.user_id))
+        .filter(schema::auth_provider::provider_type.eq(AuthProviderType::Password))
+        .filter(schema::auth_provider::email_verified.eq(false))
+        .get_results(&mut conn)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    for provider in &providers {
+        send_verification_email(mailer.get_ref(), &identity_config, provider).await?;
+    }
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::password::sign_up::sign_up;
+    use crate::auth::password::tests::RecordingMailer;
+    use crate::auth::password::Credentials;
+    use crate::auth::SignInResult;
+    use crate::{config, db};
+    use actix_web::http::header::AUTHORIZATION;
+    use actix_web::{http::StatusCode, test, App};
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[actix_web::test]
+    async fn emails_are_verified_with_the_mailed_token() {
+        let pool = db::initialize_db_pool(&config::DB_URL).await;
+        let mailer = Arc::new(RecordingMailer::default());
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(config::IDENTITY_CONFIG.clone()))
+                .app_data(web::Data::from(mailer.clone() as Arc<dyn Mailer>))
+                .service(sign_up)
+                .service(verify_email)
+                .service(request_verification),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/sign-up/")
+            .set_json(Credentials {
+                email: "bryan@example.com".to_string(),
+                password: "correct horse".to_string(),
+            })
+            .to_request();
+        let SignInResult::Success(signed_up) = test::call_and_read_body_json(&app, req).await
+        else {
+            panic!("Expected the sign-up to succeed");
+        };
+
+        let req = test::TestRequest::post()
+            .uri("/verify-email/request/")
+            .insert_header((
+                AUTHORIZATION,
+                format!("Bearer {}", signed_up.access_token.value),
+            ))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(mailer.mails.lock().unwrap().len(), 2);
+
+        let req = test::TestRequest::post()
+            .uri("/verify-email/")
+            .set_json(VerifyEmailBody {
+                token: mailer.last_token(),
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let mut conn = pool.get().await.unwrap();
+        let email_verified: bool = schema::auth_provider::table
+            .find(signed_up.user.providers[0].id)
+            .select(schema::auth_provider::email_verified)
+            .first(&mut conn)
+            .await
+            .unwrap();
+        assert!(email_verified);
+    }
+}
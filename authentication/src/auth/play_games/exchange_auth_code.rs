@@ -1,8 +1,10 @@
+use crate::auth::sign_in_error::SignInError;
 use crate::config::OAUTH_CLIENT_SECRETS;
+use reqwest::StatusCode;
 use serde::Deserialize;
 use std::time::Duration;
 
-const OAUTH_TOKEN_URL: &'static str = "https://oauth2.googleapis.com/token";
+const OAUTH_TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
 
 #[derive(Deserialize)]
 #[allow(dead_code)]
@@ -15,10 +17,7 @@ struct TokenPayload {
 
 #[async_trait::async_trait]
 pub trait PlayGamesExchangeAuthCodeService: Sync {
-    async fn get_access_token(
-        &self,
-        auth_code: &str,
-    ) -> Result<String, Box<dyn std::error::Error>> {
+    async fn get_access_token(&self, auth_code: &str) -> Result<String, SignInError> {
         let params = [
             ("client_id", &OAUTH_CLIENT_SECRETS.id),
             ("client_secret", &OAUTH_CLIENT_SECRETS.secret),
@@ -32,11 +31,22 @@ pub trait PlayGamesExchangeAuthCodeService: Sync {
             .timeout(Duration::from_secs(5))
             .form(&params)
             .send()
-            .await?;
-
-        let token: TokenPayload = resp.json().await?;
-
-        Ok(token.access_token)
+            .await
+            .map_err(SignInError::upstream_unavailable)?;
+
+        match resp.status() {
+            StatusCode::OK => {
+                let token: TokenPayload = resp
+                    .json()
+                    .await
+                    .map_err(SignInError::upstream_unavailable)?;
+                Ok(token.access_token)
+            }
+            StatusCode::BAD_REQUEST | StatusCode::UNAUTHORIZED => Err(
+                SignInError::InvalidCredential("Failed to exchange auth code".to_string()),
+            ),
+            status => Err(SignInError::upstream_unavailable(status)),
+        }
     }
 }
 
@@ -0,0 +1,27 @@
+use crate::auth::identity::Identity;
+use crate::auth::link::link_provider;
+use crate::auth::play_games::{
+    exchange_auth_code::PlayGamesExchangeAuthCodeService, play_games_api::players::PlayersService,
+};
+use crate::db::DbPool;
+use actix_web::{error, post, web, HttpResponse};
+
+/// Links a Google Play Games player to the caller.
+#[post("/link/")]
+async fn link(
+    auth_code: String,
+    identity: Identity,
+    pool: web::Data<DbPool>,
+    exchange_auth_code_service: web::Data<dyn PlayGamesExchangeAuthCodeService>,
+    players_service: web::Data<dyn PlayersService>,
+) -> actix_web::Result<HttpResponse> {
+    let access_token = exchange_auth_code_service
+        .get_access_token(&auth_code)
+        .await?;
+
+    let player = players_service.me(&access_token).await?;
+
+    let mut conn = pool.get().await.map_err(error::ErrorInternalServerError)?;
+    let user_with_providers = link_provider(&mut conn, &identity.user_id, &player).await?;
+    Ok(HttpResponse::Ok().json(user_with_providers))
+}
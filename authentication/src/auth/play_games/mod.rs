@@ -1,4 +1,5 @@
 mod exchange_auth_code;
+mod link;
 mod play_games_api;
 mod sign_in;
 
@@ -16,5 +17,6 @@ pub fn config_service(cfg: &mut web::ServiceConfig) {
     let players_service = web::Data::from(Arc::new(RealPlayersService) as Arc<dyn PlayersService>);
     cfg.app_data(exchange_auth_code_service)
         .app_data(players_service)
-        .service(sign_in::sign_in);
+        .service(sign_in::sign_in)
+        .service(link::link);
 }
@@ -3,23 +3,38 @@ use crate::{
     auth::provider::{
         AuthProviderChangeset, AuthProviderInsert, AuthProviderType, IntoAuthProviderInsert,
     },
+    auth::sign_in_error::SignInError,
     user::{User, UserInsert},
 };
+use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
 
 #[async_trait::async_trait]
 pub trait PlayersService: Sync {
-    async fn me(&self, access_token: &str) -> Result<Player, Box<dyn std::error::Error>> {
+    async fn me(&self, access_token: &str) -> Result<Player, SignInError> {
         let client = reqwest::Client::new();
         let resp = client
             .get(format!("{URL}/games/v1/players/me"))
             .timeout(Duration::from_secs(5))
             .bearer_auth(access_token)
             .send()
-            .await?;
-        let player: Player = resp.json().await?;
-        Ok(player)
+            .await
+            .map_err(SignInError::upstream_unavailable)?;
+
+        match resp.status() {
+            StatusCode::OK => resp
+                .json::<Player>()
+                .await
+                .map_err(SignInError::upstream_unavailable),
+            StatusCode::UNAUTHORIZED => Err(SignInError::InvalidCredential(
+                "Failed to get player".to_string(),
+            )),
+            StatusCode::FORBIDDEN | StatusCode::NOT_FOUND => Err(SignInError::ProfileUnavailable(
+                "No player found".to_string(),
+            )),
+            status => Err(SignInError::upstream_unavailable(status)),
+        }
     }
 }
 
@@ -48,22 +63,22 @@ pub struct Player {
 impl From<&Player> for AuthProviderChangeset {
     fn from(value: &Player) -> Self {
         AuthProviderChangeset {
-            order: 0,
             email: None,
             email_verified: false,
             display_name: value.display_name.clone(),
             user_name: value.display_name.clone(),
             picture_url: value.avatar_image_url.clone(),
             locale: None,
+            family_shared_owner_id: None,
+            vac_banned: false,
+            publisher_banned: false,
         }
     }
 }
 
 impl From<&Player> for UserInsert {
-    fn from(value: &Player) -> Self {
-        UserInsert {
-            name: value.display_name.clone(),
-        }
+    fn from(_value: &Player) -> Self {
+        UserInsert::default()
     }
 }
 
@@ -80,6 +95,9 @@ impl IntoAuthProviderInsert for Player {
             user_name: self.display_name.clone(),
             picture_url: self.avatar_image_url.clone(),
             locale: None,
+            family_shared_owner_id: None,
+            vac_banned: false,
+            publisher_banned: false,
         }
     }
 }
@@ -1,3 +1,4 @@
+use crate::auth::device::DeviceInfo;
 use crate::auth::identity::IdentityConfig;
 use crate::auth::play_games::{
     exchange_auth_code::PlayGamesExchangeAuthCodeService, play_games_api::players::PlayersService,
@@ -16,6 +17,7 @@ async fn sign_in(
     auth_code: String,
     pool: web::Data<DbPool>,
     identity_config: web::Data<IdentityConfig>,
+    device: DeviceInfo,
     exchange_auth_code_service: web::Data<dyn PlayGamesExchangeAuthCodeService>,
     players_service: web::Data<dyn PlayersService>,
 ) -> actix_web::Result<HttpResponse> {
@@ -52,7 +54,9 @@ async fn sign_in(
         return generate_sign_in_success_response(
             &mut conn,
             UserWithAuthProviders { user, providers },
+            &AuthProviderType::GooglePlayGames,
             &identity_config,
+            &device,
         )
         .await;
     }
@@ -61,5 +65,12 @@ async fn sign_in(
         .await
         .map_err(error::ErrorInternalServerError)?;
 
-    generate_sign_in_success_response(&mut conn, new_user, &identity_config).await
+    generate_sign_in_success_response(
+        &mut conn,
+        new_user,
+        &AuthProviderType::GooglePlayGames,
+        &identity_config,
+        &device,
+    )
+    .await
 }
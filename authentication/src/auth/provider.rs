@@ -1,13 +1,17 @@
-use crate::{diesel_insertable, schema, user::User};
+use crate::user::{User, UserInsert};
+use crate::{diesel_insertable, schema};
 use diesel::{
     deserialize::{FromSql, FromSqlRow},
     expression::AsExpression,
     pg::Pg,
     prelude::*,
-    serialize::ToSql,
+    serialize::{IsNull, ToSql},
     sql_types,
 };
 use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
 use uuid::Uuid;
 
 diesel_insertable! {
@@ -27,6 +31,9 @@ diesel_insertable! {
         pub user_name: Option<String>,
         pub picture_url: Option<String>,
         pub locale: Option<String>,
+        pub family_shared_owner_id: Option<String>,
+        pub vac_banned: bool,
+        pub publisher_banned: bool,
     }
 }
 
@@ -43,6 +50,9 @@ impl Default for AuthProviderInsert {
             display_name: None,
             picture_url: None,
             locale: None,
+            family_shared_owner_id: None,
+            vac_banned: false,
+            publisher_banned: false,
         }
     }
 }
@@ -51,58 +61,142 @@ pub trait IntoAuthProviderInsert {
     fn into_provider_insert(&self, user: &User) -> AuthProviderInsert;
 }
 
+impl IntoAuthProviderInsert for AuthProviderInsert {
+    fn into_provider_insert(&self, user: &User) -> AuthProviderInsert {
+        AuthProviderInsert {
+            user_id: user.id,
+            ..self.clone()
+        }
+    }
+}
+
+impl From<&AuthProviderInsert> for UserInsert {
+    fn from(_value: &AuthProviderInsert) -> Self {
+        UserInsert::default()
+    }
+}
+
 #[derive(AsChangeset)]
 #[diesel(table_name = schema::auth_provider)]
 pub struct AuthProviderChangeset {
-    pub order: i16,
     pub email: Option<String>,
     pub email_verified: bool,
     pub display_name: Option<String>,
     pub user_name: Option<String>,
     pub picture_url: Option<String>,
     pub locale: Option<String>,
+    /// The Steam id of the owner of the game, if the player borrowed it
+    /// through Steam Family Sharing. `Some(None)` clears it, and `None` keeps
+    /// it for providers other than Steam.
+    pub family_shared_owner_id: Option<Option<String>>,
+    pub vac_banned: bool,
+    pub publisher_banned: bool,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
 #[diesel(sql_type = sql_types::Text)]
+#[serde(into = "String", try_from = "String")]
 pub enum AuthProviderType {
-    #[serde(rename = "oauth2")]
     OAuth2,
-    #[serde(rename = "steam")]
     Steam,
-    #[serde(rename = "game_center")]
     AppleGameCenter,
-    #[serde(rename = "play_games")]
     GooglePlayGames,
+    /// An email and password, identified by the lowercased email.
+    Password,
+    /// A device-bound account that has not signed in with a platform yet.
+    Guest,
+    /// An OpenID Connect issuer by its configured name, stored as `oidc:{name}`.
+    Oidc(String),
 }
 
-impl FromSql<sql_types::Text, Pg> for AuthProviderType {
-    fn from_sql(
-        bytes: <Pg as diesel::backend::Backend>::RawValue<'_>,
-    ) -> diesel::deserialize::Result<Self> {
-        match String::from_sql(bytes)?.as_ref() {
+const OIDC_PROVIDER_TYPE_PREFIX: &str = "oidc:";
+
+impl fmt::Display for AuthProviderType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthProviderType::OAuth2 => write!(f, "oauth2"),
+            AuthProviderType::Steam => write!(f, "steam"),
+            AuthProviderType::AppleGameCenter => write!(f, "game_center"),
+            AuthProviderType::GooglePlayGames => write!(f, "play_games"),
+            AuthProviderType::Password => write!(f, "password"),
+            AuthProviderType::Guest => write!(f, "guest"),
+            AuthProviderType::Oidc(name) => write!(f, "{OIDC_PROVIDER_TYPE_PREFIX}{name}"),
+        }
+    }
+}
+
+impl FromStr for AuthProviderType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
             "oauth2" => Ok(AuthProviderType::OAuth2),
             "steam" => Ok(AuthProviderType::Steam),
             "game_center" => Ok(AuthProviderType::AppleGameCenter),
             "play_games" => Ok(AuthProviderType::GooglePlayGames),
-            _ => Err("Unknown `ProviderType` received".into()),
+            "password" => Ok(AuthProviderType::Password),
+            "guest" => Ok(AuthProviderType::Guest),
+            _ => match s.strip_prefix(OIDC_PROVIDER_TYPE_PREFIX) {
+                Some(name) if !name.is_empty() => Ok(AuthProviderType::Oidc(name.to_string())),
+                _ => Err(format!("Unknown `ProviderType` {s}")),
+            },
         }
     }
 }
 
+impl From<AuthProviderType> for String {
+    fn from(value: AuthProviderType) -> Self {
+        value.to_string()
+    }
+}
+
+impl TryFrom<String> for AuthProviderType {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl FromSql<sql_types::Text, Pg> for AuthProviderType {
+    fn from_sql(
+        bytes: <Pg as diesel::backend::Backend>::RawValue<'_>,
+    ) -> diesel::deserialize::Result<Self> {
+        Ok(String::from_sql(bytes)?.parse()?)
+    }
+}
+
 impl ToSql<sql_types::Text, Pg> for AuthProviderType {
     fn to_sql<'b>(
         &'b self,
         out: &mut diesel::serialize::Output<'b, '_, Pg>,
     ) -> diesel::serialize::Result {
-        <str as ToSql<sql_types::Text, Pg>>::to_sql(
-            match self {
-                AuthProviderType::OAuth2 => "oauth2",
-                AuthProviderType::Steam => "steam",
-                AuthProviderType::AppleGameCenter => "game_center",
-                AuthProviderType::GooglePlayGames => "play_games",
-            },
-            out,
-        )
+        out.write_all(self.to_string().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn provider_types_round_trip_through_text() {
+        for provider_type in [
+            AuthProviderType::OAuth2,
+            AuthProviderType::Steam,
+            AuthProviderType::AppleGameCenter,
+            AuthProviderType::GooglePlayGames,
+            AuthProviderType::Password,
+            AuthProviderType::Guest,
+            AuthProviderType::Oidc("apple".to_string()),
+        ] {
+            assert_eq!(provider_type.to_string().parse(), Ok(provider_type));
+        }
+        assert_eq!(
+            AuthProviderType::Oidc("discord".to_string()).to_string(),
+            "oidc:discord"
+        );
+        assert!("oidc:".parse::<AuthProviderType>().is_err());
     }
 }
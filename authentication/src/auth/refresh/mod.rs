@@ -1,6 +1,7 @@
 use actix_web::{error, post, web, HttpResponse};
 use serde::Deserialize;
 
+use crate::auth::device::DeviceInfo;
 use crate::auth::identity::IdentityConfig;
 use crate::auth::refresh::session::{RefreshResult, RefreshSession};
 use crate::db::DbPool;
@@ -17,9 +18,10 @@ async fn refresh(
     body: web::Json<RefreshRequestBody>,
     pool: web::Data<DbPool>,
     identity_config: web::Data<IdentityConfig>,
+    device: DeviceInfo,
 ) -> actix_web::Result<HttpResponse> {
     let mut conn = pool.get().await.map_err(error::ErrorInternalServerError)?;
-    let result = RefreshSession::refresh(&mut conn, &identity_config, &body.refresh_token)
+    let result = RefreshSession::refresh(&mut conn, &identity_config, &body.refresh_token, &device)
         .await
         .map_err(error::ErrorInternalServerError)?;
 
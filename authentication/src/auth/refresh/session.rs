@@ -1,3 +1,5 @@
+use crate::auth::device::DeviceInfo;
+use crate::auth::event::{AuthEventInsert, AuthEventKind, AuthEventOutcome};
 use crate::auth::identity::{Identity, IdentityConfig};
 use crate::auth::Token;
 use crate::db::{DbConnection, DbError};
@@ -5,9 +7,8 @@ use crate::{diesel_insertable, schema};
 use actix_web::error;
 use chrono::{DateTime, Utc};
 use diesel::{pg::Pg, prelude::*};
-use diesel_async::RunQueryDsl;
-use jsonwebtoken::DecodingKey;
-use jsonwebtoken::EncodingKey;
+use diesel_async::scoped_futures::ScopedFutureExt;
+use diesel_async::{AsyncConnection, RunQueryDsl};
 use jsonwebtoken::Header;
 use jsonwebtoken::Validation;
 use serde::{Deserialize, Serialize};
@@ -25,37 +26,90 @@ diesel_insertable! {
         pub expires_at: DateTime<Utc>,
         pub count: i64,
         pub invalidated: bool,
+        pub device_id: Option<String>,
+        pub device_label: Option<String>,
+        pub platform: Option<String>,
+        pub user_agent: Option<String>,
+        pub created_at: DateTime<Utc>,
     }
 }
 
+/// The most sessions without a device id that a user keeps, besides their
+/// oldest one, which tokens issued before sessions were kept per device
+/// fall back to.
+const MAX_SESSIONS_WITHOUT_DEVICE: i64 = 10;
+
 impl RefreshSession {
+    /// Starts a new session for `user_id` on `device`.
+    ///
+    /// A user has one session per device, so signing in again on a device
+    /// with a known device id deletes that device's previous session.
     pub async fn create(
         conn: &mut DbConnection,
         config: &IdentityConfig,
         user_id: &Uuid,
+        device: &DeviceInfo,
     ) -> Result<Self, DbError> {
         let issued_at = Utc::now();
         let expires_at = issued_at + config.refresh_expires_in;
-        let token_insert = RefreshSessionInsert {
-            user_id: user_id.clone(),
+        let session_insert = RefreshSessionInsert {
+            user_id: *user_id,
             issued_at,
             expires_at,
             count: 0,
             invalidated: false,
+            device_id: device.device_id.clone(),
+            device_label: device.device_label.clone(),
+            platform: device.platform.clone(),
+            user_agent: device.user_agent.clone(),
+            created_at: issued_at,
         };
-        let token: RefreshSession = diesel::insert_into(schema::refresh_session::table)
-            .values(token_insert)
-            .on_conflict(schema::refresh_session::user_id)
-            .do_update()
-            .set((
-                schema::refresh_session::issued_at.eq(issued_at),
-                schema::refresh_session::expires_at.eq(expires_at),
-                schema::refresh_session::count.eq(schema::refresh_session::count + 1),
-                schema::refresh_session::invalidated.eq(false),
-            ))
-            .get_result(conn)
-            .await?;
-        Ok(token)
+        conn.transaction::<_, DbError, _>(|conn| {
+            async move {
+                let user_sessions = schema::refresh_session::table
+                    .filter(schema::refresh_session::user_id.eq(session_insert.user_id));
+                match &session_insert.device_id {
+                    Some(device_id) => {
+                        diesel::delete(
+                            user_sessions.filter(schema::refresh_session::device_id.eq(device_id)),
+                        )
+                        .execute(conn)
+                        .await?;
+                    }
+                    None => {
+                        let user_sessions =
+                            user_sessions.filter(schema::refresh_session::device_id.is_null());
+                        let oldest: Vec<Uuid> = user_sessions
+                            .select(schema::refresh_session::id)
+                            .order(schema::refresh_session::created_at.asc())
+                            .limit(1)
+                            .load(conn)
+                            .await?;
+                        // Make room for the new session among the newest ones.
+                        let newest: Vec<Uuid> = user_sessions
+                            .select(schema::refresh_session::id)
+                            .order(schema::refresh_session::created_at.desc())
+                            .limit(MAX_SESSIONS_WITHOUT_DEVICE - 1)
+                            .load(conn)
+                            .await?;
+                        diesel::delete(
+                            user_sessions
+                                .filter(schema::refresh_session::id.ne_all(oldest))
+                                .filter(schema::refresh_session::id.ne_all(newest)),
+                        )
+                        .execute(conn)
+                        .await?;
+                    }
+                }
+
+                diesel::insert_into(schema::refresh_session::table)
+                    .values(&session_insert)
+                    .get_result(conn)
+                    .await
+            }
+            .scope_boxed()
+        })
+        .await
     }
 
     pub fn generate_token(&self, config: &IdentityConfig) -> Token {
@@ -89,49 +143,109 @@ impl RefreshSession {
         conn: &mut DbConnection,
         config: &IdentityConfig,
         refresh_token: &str,
+        device: &DeviceInfo,
     ) -> Result<RefreshResult, DbError> {
         let Ok(claims) = RefreshTokenClaims::decode(config, refresh_token) else {
             return Ok(RefreshResult::TokenDecodeFailure);
         };
 
-        let Some(session): Option<RefreshSession> = schema::refresh_session::table
+        let session_query = schema::refresh_session::table
             .filter(schema::refresh_session::user_id.eq(&claims.sub))
-            .first(conn)
-            .await
-            .optional()?
+            .into_boxed();
+        let session_query = match claims.sid {
+            Some(sid) => session_query.filter(schema::refresh_session::id.eq(sid)),
+            // Tokens issued before sessions were kept per device belong to
+            // the session that the user had then, which has no device id.
+            None => session_query
+                .filter(schema::refresh_session::device_id.is_null())
+                .order(schema::refresh_session::created_at.asc()),
+        };
+        let Some(session): Option<RefreshSession> = session_query.first(conn).await.optional()?
         else {
             return Ok(RefreshResult::SessionNotFound);
         };
 
         if claims.cnt < session.count {
-            return RefreshSession::invalidate_session(conn, claims.sub)
-                .await
-                .and(Ok(RefreshResult::TokenAlreadyUsed));
+            RefreshSession::invalidate(conn, &session.id).await?;
+            session
+                .record_refresh(conn, AuthEventOutcome::TokenAlreadyUsed, device)
+                .await?;
+            return Ok(RefreshResult::TokenAlreadyUsed);
         }
 
         if session.expires_at < Utc::now() {
-            return RefreshSession::invalidate_session(conn, claims.sub)
-                .await
-                .and(Ok(RefreshResult::SessionExpired));
+            RefreshSession::invalidate(conn, &session.id).await?;
+            session
+                .record_refresh(conn, AuthEventOutcome::SessionExpired, device)
+                .await?;
+            return Ok(RefreshResult::SessionExpired);
         }
 
         if session.invalidated {
+            session
+                .record_refresh(conn, AuthEventOutcome::SessionInvalidated, device)
+                .await?;
             return Ok(RefreshResult::SessionInvalidated);
         }
 
-        let access_token = Identity::from_user_id(&session.user_id).generate_token(config);
-        let refresh_session = RefreshSession::create(conn, config, &claims.sub).await?;
-        let refresh_token = refresh_session.generate_token(config);
+        let issued_at = Utc::now();
+        let expires_at = issued_at + config.refresh_expires_in;
+        // Only rotate if no concurrent refresh has used the same token.
+        let Some(session): Option<RefreshSession> =
+            diesel::update(schema::refresh_session::table.find(session.id))
+                .filter(schema::refresh_session::count.eq(session.count))
+                .set((
+                    schema::refresh_session::issued_at.eq(issued_at),
+                    schema::refresh_session::expires_at.eq(expires_at),
+                    schema::refresh_session::count.eq(schema::refresh_session::count + 1),
+                ))
+                .get_result(conn)
+                .await
+                .optional()?
+        else {
+            RefreshSession::invalidate(conn, &session.id).await?;
+            session
+                .record_refresh(conn, AuthEventOutcome::TokenAlreadyUsed, device)
+                .await?;
+            return Ok(RefreshResult::TokenAlreadyUsed);
+        };
+        session
+            .record_refresh(conn, AuthEventOutcome::Success, device)
+            .await?;
 
-        return Ok(RefreshResult::Success(RefreshSuccess {
+        let access_token = Identity::from_session(&session).generate_token(config);
+        let refresh_token = session.generate_token(config);
+
+        Ok(RefreshResult::Success(RefreshSuccess {
             access_token,
             refresh_token,
-        }));
+        }))
+    }
+
+    async fn record_refresh(
+        &self,
+        conn: &mut DbConnection,
+        outcome: AuthEventOutcome,
+        device: &DeviceInfo,
+    ) -> Result<(), DbError> {
+        AuthEventInsert::new(self.user_id, AuthEventKind::Refresh, outcome, device)
+            .session_id(self.id)
+            .record(conn)
+            .await
     }
 
-    async fn invalidate_session(conn: &mut DbConnection, user_id: Uuid) -> Result<(), DbError> {
+    pub async fn invalidate(conn: &mut DbConnection, session_id: &Uuid) -> Result<(), DbError> {
+        diesel::update(schema::refresh_session::table.find(session_id))
+            .set(schema::refresh_session::invalidated.eq(true))
+            .execute(conn)
+            .await
+            .map(|_| ())
+    }
+
+    /// Invalidates every session of a user.
+    pub async fn invalidate_all(conn: &mut DbConnection, user_id: &Uuid) -> Result<(), DbError> {
         diesel::update(schema::refresh_session::table)
-            .filter(schema::refresh_session::user_id.eq(&user_id))
+            .filter(schema::refresh_session::user_id.eq(user_id))
             .set(schema::refresh_session::invalidated.eq(true))
             .execute(conn)
             .await
@@ -142,6 +256,9 @@ impl RefreshSession {
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct RefreshTokenClaims {
     pub sub: Uuid,
+    /// The session of the token, which tokens issued before sessions were
+    /// kept per device do not have.
+    pub sid: Option<Uuid>,
     pub iat: u64,
     pub exp: u64,
     pub cnt: i64,
@@ -149,22 +266,25 @@ pub struct RefreshTokenClaims {
 
 impl RefreshTokenClaims {
     pub fn encode(&self, config: &IdentityConfig) -> String {
-        jsonwebtoken::encode(
-            &Header::default(),
-            &self,
-            &EncodingKey::from_secret(config.refresh_secret.as_ref()),
-        )
-        .expect("Failed to generate refresh token")
+        let keys = config.read_keys();
+        let signing_key = keys.active_refresh_key();
+        let header = Header {
+            kid: Some(signing_key.kid.clone()),
+            ..Header::default()
+        };
+        jsonwebtoken::encode(&header, self, &signing_key.encoding_key())
+            .expect("Failed to generate refresh token")
     }
 
     pub fn decode(config: &IdentityConfig, token: &str) -> Result<Self, error::Error> {
+        let header = jsonwebtoken::decode_header(token).map_err(error::ErrorBadRequest)?;
+        let keys = config.read_keys();
+        let Some(signing_key) = keys.refresh_key(header.kid.as_deref()) else {
+            return Err(error::ErrorBadRequest("Unknown signing key"));
+        };
         let mut validation = Validation::default();
         validation.validate_exp = false;
-        match jsonwebtoken::decode::<Self>(
-            token,
-            &DecodingKey::from_secret(config.refresh_secret.as_ref()),
-            &validation,
-        ) {
+        match jsonwebtoken::decode::<Self>(token, &signing_key.decoding_key(), &validation) {
             Ok(payload) => Ok(payload.claims),
             Err(err) => Err(error::ErrorBadRequest(err)),
         }
@@ -175,6 +295,7 @@ impl From<&RefreshSession> for RefreshTokenClaims {
     fn from(value: &RefreshSession) -> Self {
         Self {
             sub: value.user_id,
+            sid: Some(value.id),
             iat: value.issued_at.timestamp() as u64,
             exp: value.expires_at.timestamp() as u64,
             cnt: value.count,
@@ -197,6 +318,15 @@ mod tests {
             let user = User {
                 id: Uuid::new_v4(),
                 name: None,
+                discriminator: None,
+            };
+
+            let device = DeviceInfo {
+                device_id: Some("device-1".to_string()),
+                device_label: Some("Adam's phone".to_string()),
+                platform: Some("android".to_string()),
+                user_agent: Some("Godot/4.2".to_string()),
+                ip: None,
             };
 
             let pool = db::initialize_db_pool(&config::DB_URL).await;
@@ -211,9 +341,10 @@ mod tests {
                 .await
                 .expect("Failed to insert user");
 
-            let new_session = RefreshSession::create(&mut conn, &config::IDENTITY_CONFIG, &user.id)
-                .await
-                .expect("Failed to create new token");
+            let new_session =
+                RefreshSession::create(&mut conn, &config::IDENTITY_CONFIG, &user.id, &device)
+                    .await
+                    .expect("Failed to create new token");
 
             assert_eq!(new_session.user_id, user.id);
             assert!(
@@ -225,7 +356,11 @@ mod tests {
                     .contains(&new_session.expires_at)
             );
             assert_eq!(new_session.count, 0);
-            assert_eq!(new_session.invalidated, false);
+            assert!(!new_session.invalidated);
+            assert_eq!(new_session.device_id, device.device_id);
+            assert_eq!(new_session.device_label, device.device_label);
+            assert_eq!(new_session.platform, device.platform);
+            assert_eq!(new_session.user_agent, device.user_agent);
 
             let stored_session: RefreshSession = schema::refresh_session::table
                 .find(new_session.id)
@@ -236,10 +371,11 @@ mod tests {
         }
 
         #[actix_web::test]
-        async fn create_on_existing_session_updates_fields() {
+        async fn create_on_existing_device_session_replaces_it() {
             let user = User {
                 id: Uuid::new_v4(),
                 name: None,
+                discriminator: None,
             };
 
             let old_session = {
@@ -253,6 +389,11 @@ mod tests {
                     expires_at,
                     count: 5,
                     invalidated: true,
+                    device_id: Some("device-1".to_string()),
+                    device_label: Some("Old label".to_string()),
+                    platform: None,
+                    user_agent: None,
+                    created_at: issued_at,
                 }
             };
 
@@ -274,9 +415,15 @@ mod tests {
                 .await
                 .expect("Failed to insert old token");
 
-            let new_session = RefreshSession::create(&mut conn, &config::IDENTITY_CONFIG, &user.id)
-                .await
-                .expect("Failed to create new token");
+            let device = DeviceInfo {
+                device_id: Some("device-1".to_string()),
+                device_label: Some("New label".to_string()),
+                ..DeviceInfo::default()
+            };
+            let new_session =
+                RefreshSession::create(&mut conn, &config::IDENTITY_CONFIG, &user.id, &device)
+                    .await
+                    .expect("Failed to create new token");
 
             assert_eq!(new_session.user_id, user.id);
             assert!(
@@ -287,8 +434,10 @@ mod tests {
                 (Utc::now() + Duration::days(6)..Utc::now() + Duration::days(8))
                     .contains(&new_session.expires_at)
             );
-            assert_eq!(new_session.count, 6);
-            assert_eq!(new_session.invalidated, false);
+            assert_ne!(new_session.id, old_session.id);
+            assert_eq!(new_session.count, 0);
+            assert!(!new_session.invalidated);
+            assert_eq!(new_session.device_label, device.device_label);
 
             let stored_session: RefreshSession = schema::refresh_session::table
                 .find(new_session.id)
@@ -296,6 +445,112 @@ mod tests {
                 .await
                 .expect("Failed to find new session");
             assert_eq!(stored_session, new_session);
+
+            let old_refresh = RefreshSession::refresh(
+                &mut conn,
+                &config::IDENTITY_CONFIG,
+                &old_session.generate_token(&config::IDENTITY_CONFIG).value,
+                &DeviceInfo::default(),
+            )
+            .await
+            .expect("Failed to refresh session");
+            assert_eq!(old_refresh, RefreshResult::SessionNotFound);
+        }
+
+        #[actix_web::test]
+        async fn create_without_device_keeps_the_oldest_and_newest_sessions() {
+            let user = User {
+                id: Uuid::new_v4(),
+                name: None,
+                discriminator: None,
+            };
+
+            let pool = db::initialize_db_pool(&config::DB_URL).await;
+            let mut conn = pool
+                .get()
+                .await
+                .expect("Failed to get a database connection");
+
+            diesel::insert_into(schema::user::table)
+                .values(&user)
+                .execute(&mut conn)
+                .await
+                .expect("Failed to insert user");
+
+            let mut sessions = Vec::new();
+            for _ in 0..MAX_SESSIONS_WITHOUT_DEVICE + 3 {
+                let session = RefreshSession::create(
+                    &mut conn,
+                    &config::IDENTITY_CONFIG,
+                    &user.id,
+                    &DeviceInfo::default(),
+                )
+                .await
+                .expect("Failed to create session");
+                sessions.push(session.id);
+            }
+
+            let mut stored_sessions: Vec<Uuid> = schema::refresh_session::table
+                .filter(schema::refresh_session::user_id.eq(user.id))
+                .select(schema::refresh_session::id)
+                .load(&mut conn)
+                .await
+                .expect("Failed to load sessions");
+            stored_sessions.sort();
+            let mut expected_sessions = sessions.split_off(3);
+            expected_sessions.push(sessions[0]);
+            expected_sessions.sort();
+            assert_eq!(stored_sessions, expected_sessions);
+        }
+
+        #[actix_web::test]
+        async fn create_on_another_device_keeps_existing_session() {
+            let user = User {
+                id: Uuid::new_v4(),
+                name: None,
+                discriminator: None,
+            };
+
+            let pool = db::initialize_db_pool(&config::DB_URL).await;
+            let mut conn = pool
+                .get()
+                .await
+                .expect("Failed to get a database connection");
+
+            diesel::insert_into(schema::user::table)
+                .values(&user)
+                .execute(&mut conn)
+                .await
+                .expect("Failed to insert user");
+
+            let phone = DeviceInfo {
+                device_id: Some("phone".to_string()),
+                ..DeviceInfo::default()
+            };
+            let desktop = DeviceInfo {
+                device_id: Some("desktop".to_string()),
+                ..DeviceInfo::default()
+            };
+
+            let phone_session =
+                RefreshSession::create(&mut conn, &config::IDENTITY_CONFIG, &user.id, &phone)
+                    .await
+                    .expect("Failed to create phone session");
+            let desktop_session =
+                RefreshSession::create(&mut conn, &config::IDENTITY_CONFIG, &user.id, &desktop)
+                    .await
+                    .expect("Failed to create desktop session");
+            assert_ne!(phone_session.id, desktop_session.id);
+
+            let phone_refresh = RefreshSession::refresh(
+                &mut conn,
+                &config::IDENTITY_CONFIG,
+                &phone_session.generate_token(&config::IDENTITY_CONFIG).value,
+                &DeviceInfo::default(),
+            )
+            .await
+            .expect("Failed to refresh session");
+            assert!(matches!(phone_refresh, RefreshResult::Success(_)));
         }
     }
 
@@ -307,6 +562,7 @@ mod tests {
             let user = User {
                 id: Uuid::new_v4(),
                 name: None,
+                discriminator: None,
             };
 
             let session = {
@@ -320,6 +576,11 @@ mod tests {
                     expires_at,
                     count: 5,
                     invalidated: false,
+                    device_id: None,
+                    device_label: None,
+                    platform: None,
+                    user_agent: None,
+                    created_at: issued_at,
                 }
             };
 
@@ -347,10 +608,14 @@ mod tests {
                 session.generate_token(identity_config)
             };
 
-            let refresh_result =
-                RefreshSession::refresh(&mut conn, &identity_config, &refresh_token.value)
-                    .await
-                    .expect("Failed to refresh session");
+            let refresh_result = RefreshSession::refresh(
+                &mut conn,
+                identity_config,
+                &refresh_token.value,
+                &DeviceInfo::default(),
+            )
+            .await
+            .expect("Failed to refresh session");
 
             assert!(matches!(refresh_result, RefreshResult::Success(_)));
             let RefreshResult::Success(success) = refresh_result else {
@@ -359,11 +624,78 @@ mod tests {
             assert_ne!(success.refresh_token, refresh_token);
         }
 
+        #[actix_web::test]
+        async fn refresh_without_session_id_uses_the_legacy_session() {
+            let user = User {
+                id: Uuid::new_v4(),
+                name: None,
+                discriminator: None,
+            };
+
+            let identity_config = &config::IDENTITY_CONFIG;
+
+            let pool = db::initialize_db_pool(&config::DB_URL).await;
+            let mut conn = pool
+                .get()
+                .await
+                .expect("Failed to get a database connection");
+
+            diesel::insert_into(schema::user::table)
+                .values(&user)
+                .execute(&mut conn)
+                .await
+                .expect("Failed to insert user");
+
+            let legacy_session = RefreshSession::create(
+                &mut conn,
+                identity_config,
+                &user.id,
+                &DeviceInfo::default(),
+            )
+            .await
+            .expect("Failed to create legacy session");
+            RefreshSession::create(
+                &mut conn,
+                identity_config,
+                &user.id,
+                &DeviceInfo {
+                    device_id: Some("phone".to_string()),
+                    ..DeviceInfo::default()
+                },
+            )
+            .await
+            .expect("Failed to create phone session");
+
+            let legacy_token = RefreshTokenClaims {
+                sid: None,
+                ..RefreshTokenClaims::from(&legacy_session)
+            }
+            .encode(identity_config);
+
+            let refresh_result = RefreshSession::refresh(
+                &mut conn,
+                identity_config,
+                &legacy_token,
+                &DeviceInfo::default(),
+            )
+            .await
+            .expect("Failed to refresh session");
+            assert!(matches!(refresh_result, RefreshResult::Success(_)));
+
+            let stored_session: RefreshSession = schema::refresh_session::table
+                .find(legacy_session.id)
+                .first(&mut conn)
+                .await
+                .expect("Failed to find session");
+            assert_eq!(stored_session.count, legacy_session.count + 1);
+        }
+
         #[actix_web::test]
         async fn refresh_with_non_existent_token_errors() {
             let user = User {
                 id: Uuid::new_v4(),
                 name: None,
+                discriminator: None,
             };
 
             let session = {
@@ -377,6 +709,11 @@ mod tests {
                     expires_at,
                     count: 5,
                     invalidated: false,
+                    device_id: None,
+                    device_label: None,
+                    platform: None,
+                    user_agent: None,
+                    created_at: issued_at,
                 }
             };
 
@@ -398,10 +735,14 @@ mod tests {
                 session.generate_token(identity_config)
             };
 
-            let refresh_result =
-                RefreshSession::refresh(&mut conn, &identity_config, &refresh_token.value)
-                    .await
-                    .expect("Failed to refresh session");
+            let refresh_result = RefreshSession::refresh(
+                &mut conn,
+                identity_config,
+                &refresh_token.value,
+                &DeviceInfo::default(),
+            )
+            .await
+            .expect("Failed to refresh session");
 
             assert_eq!(refresh_result, RefreshResult::SessionNotFound);
         }
@@ -411,6 +752,7 @@ mod tests {
             let user = User {
                 id: Uuid::new_v4(),
                 name: None,
+                discriminator: None,
             };
 
             let session = {
@@ -424,6 +766,11 @@ mod tests {
                     expires_at,
                     count: 5,
                     invalidated: false,
+                    device_id: None,
+                    device_label: None,
+                    platform: None,
+                    user_agent: None,
+                    created_at: issued_at,
                 }
             };
 
@@ -451,14 +798,23 @@ mod tests {
                 session.generate_token(identity_config)
             };
 
-            let _ = RefreshSession::refresh(&mut conn, &identity_config, &refresh_token.value)
-                .await
-                .expect("Failed to refresh session");
-
-            let refresh_result =
-                RefreshSession::refresh(&mut conn, &identity_config, &refresh_token.value)
-                    .await
-                    .expect("Failed to refresh session");
+            let _ = RefreshSession::refresh(
+                &mut conn,
+                identity_config,
+                &refresh_token.value,
+                &DeviceInfo::default(),
+            )
+            .await
+            .expect("Failed to refresh session");
+
+            let refresh_result = RefreshSession::refresh(
+                &mut conn,
+                identity_config,
+                &refresh_token.value,
+                &DeviceInfo::default(),
+            )
+            .await
+            .expect("Failed to refresh session");
 
             assert_eq!(refresh_result, RefreshResult::TokenAlreadyUsed);
 
@@ -467,7 +823,7 @@ mod tests {
                 .first(&mut conn)
                 .await
                 .expect("Failed to find session");
-            assert_eq!(stored_session.invalidated, true);
+            assert!(stored_session.invalidated);
         }
 
         #[actix_web::test]
@@ -475,6 +831,7 @@ mod tests {
             let user = User {
                 id: Uuid::new_v4(),
                 name: None,
+                discriminator: None,
             };
 
             let session = {
@@ -488,6 +845,11 @@ mod tests {
                     expires_at,
                     count: 5,
                     invalidated: false,
+                    device_id: None,
+                    device_label: None,
+                    platform: None,
+                    user_agent: None,
+                    created_at: issued_at,
                 }
             };
 
@@ -515,10 +877,14 @@ mod tests {
                 session.generate_token(identity_config)
             };
 
-            let refresh_result =
-                RefreshSession::refresh(&mut conn, &identity_config, &refresh_token.value)
-                    .await
-                    .expect("Failed to refresh session");
+            let refresh_result = RefreshSession::refresh(
+                &mut conn,
+                identity_config,
+                &refresh_token.value,
+                &DeviceInfo::default(),
+            )
+            .await
+            .expect("Failed to refresh session");
 
             assert_eq!(refresh_result, RefreshResult::SessionExpired);
 
@@ -527,7 +893,7 @@ mod tests {
                 .first(&mut conn)
                 .await
                 .expect("Failed to find session");
-            assert_eq!(stored_session.invalidated, true);
+            assert!(stored_session.invalidated);
         }
 
         #[actix_web::test]
@@ -535,6 +901,7 @@ mod tests {
             let user = User {
                 id: Uuid::new_v4(),
                 name: None,
+                discriminator: None,
             };
 
             let session = {
@@ -548,6 +915,11 @@ mod tests {
                     expires_at,
                     count: 5,
                     invalidated: true,
+                    device_id: None,
+                    device_label: None,
+                    platform: None,
+                    user_agent: None,
+                    created_at: issued_at,
                 }
             };
 
@@ -575,12 +947,86 @@ mod tests {
                 session.generate_token(identity_config)
             };
 
-            let refresh_result =
-                RefreshSession::refresh(&mut conn, &identity_config, &refresh_token.value)
-                    .await
-                    .expect("Failed to refresh session");
+            let refresh_result = RefreshSession::refresh(
+                &mut conn,
+                identity_config,
+                &refresh_token.value,
+                &DeviceInfo::default(),
+            )
+            .await
+            .expect("Failed to refresh session");
 
             assert_eq!(refresh_result, RefreshResult::SessionInvalidated);
         }
+
+        #[actix_web::test]
+        async fn refresh_with_used_token_only_invalidates_that_session() {
+            let user = User {
+                id: Uuid::new_v4(),
+                name: None,
+                discriminator: None,
+            };
+
+            let identity_config = &config::IDENTITY_CONFIG;
+
+            let pool = db::initialize_db_pool(&config::DB_URL).await;
+            let mut conn = pool
+                .get()
+                .await
+                .expect("Failed to get a database connection");
+
+            diesel::insert_into(schema::user::table)
+                .values(&user)
+                .execute(&mut conn)
+                .await
+                .expect("Failed to insert user");
+
+            let phone_session = RefreshSession::create(
+                &mut conn,
+                identity_config,
+                &user.id,
+                &DeviceInfo::default(),
+            )
+            .await
+            .expect("Failed to create phone session");
+            let desktop_session = RefreshSession::create(
+                &mut conn,
+                identity_config,
+                &user.id,
+                &DeviceInfo::default(),
+            )
+            .await
+            .expect("Failed to create desktop session");
+
+            let phone_token = phone_session.generate_token(identity_config);
+            let _ = RefreshSession::refresh(
+                &mut conn,
+                identity_config,
+                &phone_token.value,
+                &DeviceInfo::default(),
+            )
+            .await
+            .expect("Failed to refresh session");
+            let refresh_result = RefreshSession::refresh(
+                &mut conn,
+                identity_config,
+                &phone_token.value,
+                &DeviceInfo::default(),
+            )
+            .await
+            .expect("Failed to refresh session");
+            assert_eq!(refresh_result, RefreshResult::TokenAlreadyUsed);
+
+            let desktop_token = desktop_session.generate_token(identity_config);
+            let refresh_result = RefreshSession::refresh(
+                &mut conn,
+                identity_config,
+                &desktop_token.value,
+                &DeviceInfo::default(),
+            )
+            .await
+            .expect("Failed to refresh session");
+            assert!(matches!(refresh_result, RefreshResult::Success(_)));
+        }
     }
 }
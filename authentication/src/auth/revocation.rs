@@ -0,0 +1,295 @@
+use crate::auth::identity::IdentityConfig;
+use crate::config::REVOCATIONS_SERVICE_KEY;
+use crate::db::{DbConnection, DbError, DbPool};
+use crate::service_key::ServiceKey;
+use crate::{diesel_insertable, schema};
+use actix_web::{error, get, web, HttpResponse};
+use chrono::{DateTime, Utc};
+use diesel::{
+    deserialize::{FromSql, FromSqlRow},
+    expression::AsExpression,
+    pg::Pg,
+    prelude::*,
+    serialize::ToSql,
+    sql_types,
+};
+use diesel_async::RunQueryDsl;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+pub fn config_service(cfg: &mut web::ServiceConfig) {
+    cfg.service(list);
+}
+
+diesel_insertable! {
+    #[derive(Queryable, Selectable, Insertable, AsChangeset)]
+    /// Rejects the access tokens of a subject issued up to `revoked_at`. Every
+    /// such token has expired by `expires_at`.
+    #[diesel(table_name = schema::token_revocation)]
+    #[diesel(check_for_backend(Pg))]
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct TokenRevocation {
+        pub kind: RevocationKind,
+        pub subject_id: Uuid,
+        pub revoked_at: DateTime<Utc>,
+        pub expires_at: DateTime<Utc>,
+    }
+}
+
+/// What a revocation applies to.
+///
+/// Every access token of the subject that was issued before the revocation is
+/// rejected.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, AsExpression, FromSqlRow,
+)]
+#[diesel(sql_type = sql_types::Text)]
+#[serde(rename_all = "snake_case")]
+pub enum RevocationKind {
+    /// A single access token, by its `jti`.
+    Token,
+    /// The access tokens of a refresh session, by their `sid`.
+    Session,
+    /// All access tokens of a user, by their `sub`.
+    User,
+}
+
+impl FromSql<sql_types::Text, Pg> for RevocationKind {
+    fn from_sql(
+        bytes: <Pg as diesel::backend::Backend>::RawValue<'_>,
+    ) -> diesel::deserialize::Result<Self> {
+        match String::from_sql(bytes)?.as_ref() {
+            "token" => Ok(RevocationKind::Token),
+            "session" => Ok(RevocationKind::Session),
+            "user" => Ok(RevocationKind::User),
+            _ => Err("Unknown `RevocationKind` received".into()),
+        }
+    }
+}
+
+impl ToSql<sql_types::Text, Pg> for RevocationKind {
+    fn to_sql<'b>(
+        &'b self,
+        out: &mut diesel::serialize::Output<'b, '_, Pg>,
+    ) -> diesel::serialize::Result {
+        <str as ToSql<sql_types::Text, Pg>>::to_sql(
+            match self {
+                RevocationKind::Token => "token",
+                RevocationKind::Session => "session",
+                RevocationKind::User => "user",
+            },
+            out,
+        )
+    }
+}
+
+/// The revocations that still apply to unexpired access tokens.
+#[derive(Debug, Clone, Default)]
+pub struct RevocationList {
+    revoked_at: HashMap<(RevocationKind, Uuid), DateTime<Utc>>,
+}
+
+impl RevocationList {
+    pub async fn load(conn: &mut DbConnection) -> Result<Self, DbError> {
+        let revocations: Vec<TokenRevocation> = schema::token_revocation::table
+            .filter(schema::token_revocation::expires_at.gt(Utc::now()))
+            .get_results(conn)
+            .await?;
+
+        let mut revocation_list = RevocationList::default();
+        for revocation in &revocations {
+            revocation_list.insert(revocation);
+        }
+        Ok(revocation_list)
+    }
+
+    pub fn insert(&mut self, revocation: &TokenRevocation) {
+        let revoked_at = self
+            .revoked_at
+            .entry((revocation.kind, revocation.subject_id))
+            .or_insert(revocation.revoked_at);
+        *revoked_at = (*revoked_at).max(revocation.revoked_at);
+    }
+
+    /// Whether an access token with the given claims was issued before one of
+    /// its subjects was revoked, where `issued_at_ms` is in milliseconds.
+    pub fn is_revoked(
+        &self,
+        token_id: &Uuid,
+        session_id: Option<&Uuid>,
+        user_id: &Uuid,
+        issued_at_ms: i64,
+    ) -> bool {
+        let subjects = [
+            Some((RevocationKind::Token, *token_id)),
+            session_id.map(|id| (RevocationKind::Session, *id)),
+            Some((RevocationKind::User, *user_id)),
+        ];
+        subjects
+            .into_iter()
+            .flatten()
+            .filter_map(|subject| self.revoked_at.get(&subject))
+            .any(|revoked_at| issued_at_ms <= revoked_at.timestamp_millis())
+    }
+}
+
+/// Rejects every access token of the subject that has been issued so far.
+pub async fn revoke(
+    conn: &mut DbConnection,
+    config: &IdentityConfig,
+    kind: RevocationKind,
+    subject_id: &Uuid,
+) -> Result<(), DbError> {
+    let revoked_at = Utc::now();
+    let expires_at = revoked_at + config.expires_in;
+    let revocation: TokenRevocation = diesel::insert_into(schema::token_revocation::table)
+        .values(TokenRevocationInsert {
+            kind,
+            subject_id: *subject_id,
+            revoked_at,
+            expires_at,
+        })
+        .on_conflict((
+            schema::token_revocation::kind,
+            schema::token_revocation::subject_id,
+        ))
+        .do_update()
+        .set((
+            schema::token_revocation::revoked_at.eq(revoked_at),
+            schema::token_revocation::expires_at.eq(expires_at),
+        ))
+        .get_result(conn)
+        .await?;
+
+    config
+        .revocations
+        .write()
+        .expect("Failed to get write lock on revocations")
+        .insert(&revocation);
+    Ok(())
+}
+
+/// Lists the revocations that still apply, so that other services that verify
+/// access tokens can reject revoked tokens too.
+#[get("/")]
+async fn list(service_key: ServiceKey, pool: web::Data<DbPool>) -> actix_web::Result<HttpResponse> {
+    service_key.validate(REVOCATIONS_SERVICE_KEY.as_deref())?;
+
+    let mut conn = pool.get().await.map_err(error::ErrorInternalServerError)?;
+
+    let revocations: Vec<TokenRevocation> = schema::token_revocation::table
+        .filter(schema::token_revocation::expires_at.gt(Utc::now()))
+        .get_results(&mut conn)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(revocations))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::identity::Identity;
+    use crate::user::User;
+    use crate::{config, db};
+    use actix_web::{http::StatusCode, test, App};
+    use chrono::Duration;
+
+    use super::*;
+
+    #[actix_web::test]
+    async fn only_tokens_issued_before_the_revocation_are_revoked() {
+        let user_id = Uuid::new_v4();
+        let revoked_at = Utc::now();
+
+        let mut revocation_list = RevocationList::default();
+        revocation_list.insert(&TokenRevocation {
+            id: Uuid::new_v4(),
+            kind: RevocationKind::User,
+            subject_id: user_id,
+            revoked_at,
+            expires_at: revoked_at + Duration::hours(1),
+        });
+
+        let before = (revoked_at - Duration::minutes(1)).timestamp_millis();
+        let after = (revoked_at + Duration::minutes(1)).timestamp_millis();
+        assert!(revocation_list.is_revoked(&Uuid::new_v4(), None, &user_id, before));
+        assert!(!revocation_list.is_revoked(&Uuid::new_v4(), None, &user_id, after));
+        assert!(!revocation_list.is_revoked(&Uuid::new_v4(), None, &Uuid::new_v4(), before));
+    }
+
+    #[actix_web::test]
+    async fn tokens_issued_after_the_revocation_in_the_same_second_are_kept() {
+        let user_id = Uuid::new_v4();
+        let revoked_at = DateTime::from_timestamp_millis(1_700_000_000_500).unwrap();
+
+        let mut revocation_list = RevocationList::default();
+        revocation_list.insert(&TokenRevocation {
+            id: Uuid::new_v4(),
+            kind: RevocationKind::User,
+            subject_id: user_id,
+            revoked_at,
+            expires_at: revoked_at + Duration::hours(1),
+        });
+
+        let just_before = revoked_at.timestamp_millis() - 1;
+        let just_after = revoked_at.timestamp_millis() + 1;
+        assert!(revocation_list.is_revoked(&Uuid::new_v4(), None, &user_id, just_before));
+        assert!(!revocation_list.is_revoked(&Uuid::new_v4(), None, &user_id, just_after));
+    }
+
+    #[actix_web::test]
+    async fn revoked_tokens_are_rejected_and_listed() {
+        let user = User {
+            id: Uuid::new_v4(),
+            name: None,
+            discriminator: None,
+        };
+        let identity_config = IdentityConfig {
+            revocations: Default::default(),
+            ..config::IDENTITY_CONFIG.clone()
+        };
+
+        let token = Identity::from_user(&user)
+            .generate_token(&identity_config)
+            .value;
+        assert!(Identity::from_token(&identity_config, &token).is_ok());
+
+        let pool = db::initialize_db_pool(&config::DB_URL).await;
+        {
+            let mut conn = pool
+                .get()
+                .await
+                .expect("Failed to get a database connection");
+            revoke(&mut conn, &identity_config, RevocationKind::User, &user.id)
+                .await
+                .expect("Failed to revoke tokens");
+        }
+        assert!(Identity::from_token(&identity_config, &token).is_err());
+
+        // Signing in again right after the revocation, e.g. within the same
+        // second, issues a token that is not revoked.
+        actix_web::rt::time::sleep(std::time::Duration::from_millis(2)).await;
+        let new_token = Identity::from_user(&user)
+            .generate_token(&identity_config)
+            .value;
+        assert!(Identity::from_token(&identity_config, &new_token).is_ok());
+
+        let app = test::init_service(App::new().app_data(web::Data::new(pool)).service(list)).await;
+        let req = test::TestRequest::get().uri("/").to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let service_key = REVOCATIONS_SERVICE_KEY
+            .as_ref()
+            .expect("REVOCATIONS_SERVICE_KEY must be set for tests");
+        let req = test::TestRequest::get()
+            .uri("/")
+            .insert_header(("Service-Key", service_key.as_str()))
+            .to_request();
+        let revocations: Vec<TokenRevocation> = test::call_and_read_body_json(&app, req).await;
+        assert!(revocations
+            .iter()
+            .any(|r| r.kind == RevocationKind::User && r.subject_id == user.id));
+    }
+}
@@ -0,0 +1,271 @@
+use crate::auth::device::DeviceInfo;
+use crate::auth::event::{AuthEventInsert, AuthEventKind, AuthEventOutcome};
+use crate::auth::identity::{Identity, IdentityConfig};
+use crate::auth::refresh::session::RefreshSession;
+use crate::auth::revocation::{self, RevocationKind};
+use crate::db::DbPool;
+use crate::schema;
+use actix_web::{delete, error, get, web, HttpResponse};
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use serde::Serialize;
+use uuid::Uuid;
+
+pub fn config_service(cfg: &mut web::ServiceConfig) {
+    cfg.service(list).service(revoke);
+}
+
+/// A refresh session as shown to the user that owns it.
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(test, derive(serde::Deserialize, PartialEq))]
+pub struct SessionDescription {
+    pub id: Uuid,
+    pub device_label: Option<String>,
+    pub platform: Option<String>,
+    pub user_agent: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_refreshed_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    /// Whether this is the session that made the request.
+    pub current: bool,
+}
+
+impl SessionDescription {
+    fn new(session: RefreshSession, identity: &Identity) -> Self {
+        SessionDescription {
+            id: session.id,
+            current: identity.session_id == Some(session.id),
+            device_label: session.device_label,
+            platform: session.platform,
+            user_agent: session.user_agent,
+            created_at: session.created_at,
+            last_refreshed_at: session.issued_at,
+            expires_at: session.expires_at,
+        }
+    }
+}
+
+/// Lists the caller's sessions that can still be refreshed.
+#[get("/")]
+async fn list(pool: web::Data<DbPool>, identity: Identity) -> actix_web::Result<HttpResponse> {
+    let mut conn = pool.get().await.map_err(error::ErrorInternalServerError)?;
+
+    let sessions: Vec<RefreshSession> = schema::refresh_session::table
+        .filter(schema::refresh_session::user_id.eq(&identity.user_id))
+        .filter(schema::refresh_session::invalidated.eq(false))
+        .filter(schema::refresh_session::expires_at.gt(Utc::now()))
+        .order(schema::refresh_session::issued_at.desc())
+        .get_results(&mut conn)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(
+        sessions
+            .into_iter()
+            .map(|session| SessionDescription::new(session, &identity))
+            .collect::<Vec<_>>(),
+    ))
+}
+
+/// Revokes one of the caller's sessions so that it can no longer be refreshed
+/// and its access tokens are rejected.
+#[delete("/{id}/")]
+async fn revoke(
+    pool: web::Data<DbPool>,
+    identity_config: web::Data<IdentityConfig>,
+    identity: Identity,
+    session_id: web::Path<Uuid>,
+    device: DeviceInfo,
+) -> actix_web::Result<HttpResponse> {
+    let mut conn = pool.get().await.map_err(error::ErrorInternalServerError)?;
+
+    let revoked = diesel::update(schema::refresh_session::table.find(*session_id))
+        .filter(schema::refresh_session::user_id.eq(&identity.user_id))
+        .set(schema::refresh_session::invalidated.eq(true))
+        .execute(&mut conn)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    if revoked == 0 {
+        return Ok(HttpResponse::NotFound().body(format!("No session found with id {session_id}")));
+    }
+
+    revocation::revoke(
+        &mut conn,
+        &identity_config,
+        RevocationKind::Session,
+        &session_id,
+    )
+    .await
+    .map_err(error::ErrorInternalServerError)?;
+    AuthEventInsert::new(
+        identity.user_id,
+        AuthEventKind::SessionRevoked,
+        AuthEventOutcome::Success,
+        &device,
+    )
+    .session_id(*session_id)
+    .record(&mut conn)
+    .await
+    .map_err(error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::refresh::session::RefreshResult;
+    use crate::user::User;
+    use crate::{config, db};
+    use actix_web::{http::header::AUTHORIZATION, http::StatusCode, test, App};
+
+    use super::*;
+
+    #[actix_web::test]
+    async fn sessions_can_be_listed_and_revoked() {
+        let user = User {
+            id: Uuid::new_v4(),
+            name: None,
+            discriminator: None,
+        };
+        let identity_config = web::Data::new(config::IDENTITY_CONFIG.clone());
+
+        let pool = db::initialize_db_pool(&config::DB_URL).await;
+        let (phone_session, desktop_session) = {
+            let mut conn = pool
+                .get()
+                .await
+                .expect("Failed to get a database connection");
+
+            diesel::insert_into(schema::user::table)
+                .values(&user)
+                .execute(&mut conn)
+                .await
+                .expect("Failed to insert user");
+
+            let phone = DeviceInfo {
+                device_id: Some("phone".to_string()),
+                device_label: Some("Adam's phone".to_string()),
+                ..DeviceInfo::default()
+            };
+            let desktop = DeviceInfo {
+                device_id: Some("desktop".to_string()),
+                device_label: Some("Adam's desktop".to_string()),
+                ..DeviceInfo::default()
+            };
+            (
+                RefreshSession::create(&mut conn, &identity_config, &user.id, &phone)
+                    .await
+                    .expect("Failed to create phone session"),
+                RefreshSession::create(&mut conn, &identity_config, &user.id, &desktop)
+                    .await
+                    .expect("Failed to create desktop session"),
+            )
+        };
+
+        let token = Identity::from_session(&phone_session)
+            .generate_token(&identity_config)
+            .value;
+
+        let pool = web::Data::new(pool);
+        let app = test::init_service(
+            App::new()
+                .app_data(pool.clone())
+                .app_data(identity_config.clone())
+                .configure(config_service),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .insert_header((AUTHORIZATION, format!("Bearer {token}")))
+            .uri("/")
+            .to_request();
+        let sessions: Vec<SessionDescription> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(sessions.len(), 2);
+        assert!(sessions
+            .iter()
+            .any(|s| s.id == phone_session.id && s.current));
+        assert!(sessions
+            .iter()
+            .any(|s| s.id == desktop_session.id && !s.current));
+
+        let req = test::TestRequest::delete()
+            .insert_header((AUTHORIZATION, format!("Bearer {token}")))
+            .uri(&format!("/{}/", desktop_session.id))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+
+        let mut conn = pool
+            .get()
+            .await
+            .expect("Failed to get a database connection");
+        let refresh_result = RefreshSession::refresh(
+            &mut conn,
+            &identity_config,
+            &desktop_session.generate_token(&identity_config).value,
+            &DeviceInfo::default(),
+        )
+        .await
+        .expect("Failed to refresh session");
+        assert_eq!(refresh_result, RefreshResult::SessionInvalidated);
+    }
+
+    #[actix_web::test]
+    async fn other_users_sessions_cannot_be_revoked() {
+        let user = User {
+            id: Uuid::new_v4(),
+            name: None,
+            discriminator: None,
+        };
+        let other_user = User {
+            id: Uuid::new_v4(),
+            name: None,
+            discriminator: None,
+        };
+        let identity_config = web::Data::new(config::IDENTITY_CONFIG.clone());
+
+        let pool = db::initialize_db_pool(&config::DB_URL).await;
+        let other_session = {
+            let mut conn = pool
+                .get()
+                .await
+                .expect("Failed to get a database connection");
+
+            diesel::insert_into(schema::user::table)
+                .values(&vec![user.clone(), other_user.clone()])
+                .execute(&mut conn)
+                .await
+                .expect("Failed to insert users");
+
+            RefreshSession::create(
+                &mut conn,
+                &identity_config,
+                &other_user.id,
+                &DeviceInfo::default(),
+            )
+            .await
+            .expect("Failed to create session")
+        };
+
+        let token = Identity::from_user(&user)
+            .generate_token(&identity_config)
+            .value;
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool))
+                .app_data(identity_config)
+                .configure(config_service),
+        )
+        .await;
+
+        let req = test::TestRequest::delete()
+            .insert_header((AUTHORIZATION, format!("Bearer {token}")))
+            .uri(&format!("/{}/", other_session.id))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,93 @@
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Why a player could not sign in or link with a provider.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignInError {
+    /// The provider could not be reached, or responded unexpectedly.
+    UpstreamUnavailable(String),
+    /// The provider rejected the credential, e.g. an expired ticket or token.
+    InvalidCredential(String),
+    /// The credential is valid, but the provider has no profile to share, e.g.
+    /// the profile is private or deleted.
+    ProfileUnavailable(String),
+    /// The player is banned from signing in.
+    Banned(String),
+}
+
+impl SignInError {
+    pub fn upstream_unavailable(err: impl fmt::Display) -> Self {
+        SignInError::UpstreamUnavailable(err.to_string())
+    }
+
+    /// A machine-readable code that clients can branch on.
+    pub fn code(&self) -> &'static str {
+        match self {
+            SignInError::UpstreamUnavailable(_) => "upstream_unavailable",
+            SignInError::InvalidCredential(_) => "invalid_credential",
+            SignInError::ProfileUnavailable(_) => "profile_unavailable",
+            SignInError::Banned(_) => "banned",
+        }
+    }
+
+    fn message(&self) -> &str {
+        match self {
+            SignInError::UpstreamUnavailable(message)
+            | SignInError::InvalidCredential(message)
+            | SignInError::ProfileUnavailable(message)
+            | SignInError::Banned(message) => message,
+        }
+    }
+}
+
+impl fmt::Display for SignInError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.code(), self.message())
+    }
+}
+
+impl std::error::Error for SignInError {}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SignInErrorBody {
+    pub code: String,
+    pub message: String,
+}
+
+impl ResponseError for SignInError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            SignInError::UpstreamUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            SignInError::InvalidCredential(_) => StatusCode::UNAUTHORIZED,
+            SignInError::ProfileUnavailable(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            SignInError::Banned(_) => StatusCode::FORBIDDEN,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(SignInErrorBody {
+            code: self.code().to_string(),
+            message: self.message().to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::body::to_bytes;
+
+    use super::*;
+
+    #[actix_web::test]
+    async fn errors_respond_with_their_code() {
+        let resp = SignInError::Banned("The player is VAC banned".to_string()).error_response();
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+        let body = to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!(
+            body,
+            r#"{"code":"banned","message":"The player is VAC banned"}"#
+        );
+    }
+}
@@ -0,0 +1,29 @@
+use crate::auth::identity::Identity;
+use crate::auth::link::link_provider;
+use crate::auth::steam::player::authenticate;
+use crate::auth::steam::steam_api::{user::SteamUserService, user_auth::SteamUserAuthService};
+use crate::config::STEAM_CONFIG;
+use crate::db::DbPool;
+use actix_web::{error, post, web, HttpResponse};
+
+/// Links a Steam account to the caller.
+#[post("/link/")]
+async fn link(
+    auth_ticket: String,
+    identity: Identity,
+    pool: web::Data<DbPool>,
+    user_service: web::Data<dyn SteamUserService>,
+    user_auth_service: web::Data<dyn SteamUserAuthService>,
+) -> actix_web::Result<HttpResponse> {
+    let steam_player = authenticate(
+        &auth_ticket,
+        &STEAM_CONFIG.policies,
+        user_service.get_ref(),
+        user_auth_service.get_ref(),
+    )
+    .await?;
+
+    let mut conn = pool.get().await.map_err(error::ErrorInternalServerError)?;
+    let user_with_providers = link_provider(&mut conn, &identity.user_id, &steam_player).await?;
+    Ok(HttpResponse::Ok().json(user_with_providers))
+}
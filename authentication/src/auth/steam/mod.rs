@@ -1,3 +1,5 @@
+mod link;
+mod player;
 mod sign_in;
 mod steam_api;
 
@@ -15,5 +17,6 @@ pub fn config_service(cfg: &mut web::ServiceConfig) {
         web::Data::from(Arc::new(RealSteamUserAuthService) as Arc<dyn SteamUserAuthService>);
     cfg.app_data(steam_user_service)
         .app_data(steam_user_auth_service)
-        .service(sign_in::sign_in);
+        .service(sign_in::sign_in)
+        .service(link::link);
 }
@@ -0,0 +1,186 @@
+use crate::auth::provider::{
+    AuthProviderChangeset, AuthProviderInsert, AuthProviderType, IntoAuthProviderInsert,
+};
+use crate::auth::sign_in_error::SignInError;
+use crate::auth::steam::steam_api::user::{get_player_summaries::Player, SteamUserService};
+use crate::auth::steam::steam_api::user_auth::{
+    authenticate_user_ticket::Params, SteamUserAuthService,
+};
+use crate::config::SteamPolicies;
+use crate::user::{User, UserInsert};
+
+/// A Steam player that signed in with an authenticated ticket.
+#[derive(Debug, Clone)]
+pub struct SteamPlayer {
+    pub player: Player,
+    pub ticket: Params,
+}
+
+impl SteamPlayer {
+    /// The owner of the game, if the player borrowed it through Steam Family
+    /// Sharing.
+    pub fn family_shared_owner_id(&self) -> Option<String> {
+        (self.ticket.owner_steam_id != self.ticket.steam_id)
+            .then(|| self.ticket.owner_steam_id.clone())
+    }
+}
+
+/// Authenticates a Steam auth ticket and gets the player's profile.
+pub async fn authenticate(
+    auth_ticket: &str,
+    policies: &SteamPolicies,
+    user_service: &dyn SteamUserService,
+    user_auth_service: &dyn SteamUserAuthService,
+) -> Result<SteamPlayer, SignInError> {
+    let ticket = user_auth_service
+        .authenticate_user_ticket(auth_ticket)
+        .await?;
+    check_policies(&ticket, policies)?;
+
+    let players = user_service
+        .get_player_summaries(&[&ticket.steam_id])
+        .await?;
+    let Some(player) = players.into_iter().next() else {
+        return Err(SignInError::ProfileUnavailable(format!(
+            "No profile found with id {}",
+            ticket.steam_id
+        )));
+    };
+
+    Ok(SteamPlayer { player, ticket })
+}
+
+fn check_policies(ticket: &Params, policies: &SteamPolicies) -> Result<(), SignInError> {
+    if policies.reject_vac_banned && ticket.vac_banned {
+        return Err(SignInError::Banned("The player is VAC banned".to_string()));
+    }
+    if policies.reject_publisher_banned && ticket.publisher_banned {
+        return Err(SignInError::Banned(
+            "The player is banned by the publisher".to_string(),
+        ));
+    }
+    if policies.reject_family_shared && ticket.owner_steam_id != ticket.steam_id {
+        return Err(SignInError::Banned(
+            "The player does not own the game, and borrowed it through Family Sharing".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+impl From<&SteamPlayer> for AuthProviderChangeset {
+    fn from(value: &SteamPlayer) -> Self {
+        AuthProviderChangeset {
+            email: None,
+            email_verified: false,
+            display_name: Some(value.player.persona_name.clone()),
+            user_name: Some(value.player.persona_name.clone()),
+            picture_url: Some(value.player.avatar.clone()),
+            locale: None,
+            family_shared_owner_id: Some(value.family_shared_owner_id()),
+            vac_banned: value.ticket.vac_banned,
+            publisher_banned: value.ticket.publisher_banned,
+        }
+    }
+}
+
+impl From<&SteamPlayer> for UserInsert {
+    fn from(_value: &SteamPlayer) -> Self {
+        UserInsert::default()
+    }
+}
+
+impl IntoAuthProviderInsert for SteamPlayer {
+    fn into_provider_insert(&self, user: &User) -> AuthProviderInsert {
+        AuthProviderInsert {
+            user_id: user.id,
+            order: 0,
+            provider_type: AuthProviderType::Steam,
+            provider_id: self.player.steam_id.clone(),
+            email: None,
+            email_verified: false,
+            display_name: Some(self.player.persona_name.clone()),
+            user_name: Some(self.player.persona_name.clone()),
+            picture_url: Some(self.player.avatar.clone()),
+            locale: None,
+            family_shared_owner_id: self.family_shared_owner_id(),
+            vac_banned: self.ticket.vac_banned,
+            publisher_banned: self.ticket.publisher_banned,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ticket(owner_steam_id: &str, vac_banned: bool, publisher_banned: bool) -> Params {
+        Params {
+            result: "OK".to_string(),
+            steam_id: "76561197960287930".to_string(),
+            owner_steam_id: owner_steam_id.to_string(),
+            vac_banned,
+            publisher_banned,
+        }
+    }
+
+    #[test]
+    fn only_players_matching_a_policy_are_rejected() {
+        let policies = SteamPolicies {
+            reject_vac_banned: false,
+            reject_publisher_banned: true,
+            reject_family_shared: false,
+        };
+        let owner = "76561197960287930";
+        let borrower_of = "76561197960287931";
+
+        assert!(check_policies(&ticket(owner, false, false), &policies).is_ok());
+        assert!(check_policies(&ticket(owner, true, false), &policies).is_ok());
+        assert!(check_policies(&ticket(borrower_of, false, false), &policies).is_ok());
+        assert!(check_policies(&ticket(owner, false, true), &policies).is_err());
+
+        let policies = SteamPolicies {
+            reject_vac_banned: true,
+            reject_family_shared: true,
+            ..policies
+        };
+        assert!(check_policies(&ticket(owner, true, false), &policies).is_err());
+        assert!(check_policies(&ticket(borrower_of, false, false), &policies).is_err());
+    }
+
+    #[actix_web::test]
+    async fn players_without_a_profile_are_rejected() {
+        struct MockSteamUserAuthService;
+
+        #[async_trait::async_trait]
+        impl SteamUserAuthService for MockSteamUserAuthService {
+            async fn authenticate_user_ticket(&self, _ticket: &str) -> Result<Params, SignInError> {
+                Ok(ticket("76561197960287930", false, false))
+            }
+        }
+
+        struct MockSteamUserService;
+
+        #[async_trait::async_trait]
+        impl SteamUserService for MockSteamUserService {
+            async fn get_player_summaries(
+                &self,
+                _steam_ids: &[&str],
+            ) -> Result<Vec<Player>, SignInError> {
+                Ok(vec![])
+            }
+        }
+
+        let result = authenticate(
+            "ticket",
+            &SteamPolicies {
+                reject_vac_banned: false,
+                reject_publisher_banned: false,
+                reject_family_shared: false,
+            },
+            &MockSteamUserService,
+            &MockSteamUserAuthService,
+        )
+        .await;
+        assert!(matches!(result, Err(SignInError::ProfileUnavailable(_))));
+    }
+}
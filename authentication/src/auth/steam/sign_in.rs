@@ -1,7 +1,10 @@
+use crate::auth::device::DeviceInfo;
 use crate::auth::identity::IdentityConfig;
 use crate::auth::provider::{AuthProvider, AuthProviderChangeset, AuthProviderType};
+use crate::auth::steam::player::authenticate;
 use crate::auth::steam::steam_api::{user::SteamUserService, user_auth::SteamUserAuthService};
 use crate::auth::{create_new_user, generate_sign_in_success_response};
+use crate::config::STEAM_CONFIG;
 use crate::db::DbPool;
 use crate::schema;
 use crate::user::{User, UserWithAuthProviders};
@@ -14,25 +17,23 @@ async fn sign_in(
     auth_ticket: String,
     pool: web::Data<DbPool>,
     identity_config: web::Data<IdentityConfig>,
+    device: DeviceInfo,
     user_service: web::Data<dyn SteamUserService>,
     user_auth_service: web::Data<dyn SteamUserAuthService>,
 ) -> actix_web::Result<HttpResponse> {
-    let user_params = user_auth_service
-        .authenticate_user_ticket(&auth_ticket)
-        .await?;
-
-    let user_info = user_service
-        .get_player_summaries(&[&user_params.steam_id])
-        .await?;
-    let user_info = user_info
-        .get(0)
-        .expect(&format!("No user found with id {}", user_params.steam_id));
+    let steam_player = authenticate(
+        &auth_ticket,
+        &STEAM_CONFIG.policies,
+        user_service.get_ref(),
+        user_auth_service.get_ref(),
+    )
+    .await?;
 
     let mut conn = pool.get().await.map_err(error::ErrorInternalServerError)?;
 
-    let provider_changeset: AuthProviderChangeset = user_info.into();
+    let provider_changeset: AuthProviderChangeset = (&steam_player).into();
     let matching_provider: Option<AuthProvider> = diesel::update(schema::auth_provider::table)
-        .filter(schema::auth_provider::provider_id.eq(&user_info.steam_id))
+        .filter(schema::auth_provider::provider_id.eq(&steam_player.player.steam_id))
         .filter(schema::auth_provider::provider_type.eq(AuthProviderType::Steam))
         .set(&provider_changeset)
         .get_result(&mut conn)
@@ -55,14 +56,119 @@ async fn sign_in(
         return generate_sign_in_success_response(
             &mut conn,
             UserWithAuthProviders { user, providers },
+            &AuthProviderType::Steam,
             &identity_config,
+            &device,
         )
         .await;
     }
 
-    let new_user = create_new_user(&mut conn, user_info)
+    let new_user = create_new_user(&mut conn, &steam_player)
         .await
         .map_err(error::ErrorInternalServerError)?;
 
-    generate_sign_in_success_response(&mut conn, new_user, &identity_config).await
+    generate_sign_in_success_response(
+        &mut conn,
+        new_user,
+        &AuthProviderType::Steam,
+        &identity_config,
+        &device,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::sign_in_error::SignInError;
+    use crate::auth::steam::steam_api::{
+        user::get_player_summaries::Player, user_auth::authenticate_user_ticket::Params,
+    };
+    use crate::auth::SignInResult;
+    use crate::{config, db};
+    use actix_web::{test, App};
+    use std::sync::Arc;
+    use uuid::Uuid;
+
+    use super::*;
+
+    /// Authenticates the ticket `owned` as a player who owns the game, and any
+    /// other ticket as a player who borrowed it.
+    struct MockSteamUserAuthService {
+        steam_id: String,
+    }
+
+    #[async_trait::async_trait]
+    impl SteamUserAuthService for MockSteamUserAuthService {
+        async fn authenticate_user_ticket(&self, ticket: &str) -> Result<Params, SignInError> {
+            Ok(Params {
+                result: "OK".to_string(),
+                steam_id: self.steam_id.clone(),
+                owner_steam_id: if ticket == "owned" {
+                    self.steam_id.clone()
+                } else {
+                    "76561197960287931".to_string()
+                },
+                vac_banned: false,
+                publisher_banned: false,
+            })
+        }
+    }
+
+    struct MockSteamUserService;
+
+    #[async_trait::async_trait]
+    impl SteamUserService for MockSteamUserService {
+        async fn get_player_summaries(
+            &self,
+            steam_ids: &[&str],
+        ) -> Result<Vec<Player>, SignInError> {
+            Ok(vec![Player {
+                steam_id: steam_ids[0].to_string(),
+                community_visibility_state: None,
+                profile_state: None,
+                persona_name: "bryanmylee".to_string(),
+                last_logoff_ts: None,
+                profile_url: "".to_string(),
+                avatar: "".to_string(),
+                avatar_medium: "".to_string(),
+                avatar_full: "".to_string(),
+            }])
+        }
+    }
+
+    #[actix_web::test]
+    async fn buying_the_game_clears_family_sharing() {
+        let steam_id = Uuid::new_v4().as_u128().to_string();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(
+                    db::initialize_db_pool(&config::DB_URL).await,
+                ))
+                .app_data(web::Data::new(config::IDENTITY_CONFIG.clone()))
+                .app_data(web::Data::from(
+                    Arc::new(MockSteamUserService) as Arc<dyn SteamUserService>
+                ))
+                .app_data(web::Data::from(Arc::new(MockSteamUserAuthService {
+                    steam_id: steam_id.clone(),
+                })
+                    as Arc<dyn SteamUserAuthService>))
+                .service(sign_in),
+        )
+        .await;
+
+        let mut owner_ids = vec![];
+        for ticket in ["borrowed", "owned"] {
+            let req = test::TestRequest::post()
+                .uri("/sign-in/")
+                .set_payload(ticket)
+                .to_request();
+            let SignInResult::Success(success) = test::call_and_read_body_json(&app, req).await
+            else {
+                panic!("Expected the sign-in to succeed");
+            };
+            owner_ids.push(success.user.providers[0].family_shared_owner_id.clone());
+        }
+
+        assert_eq!(owner_ids, [Some("76561197960287931".to_string()), None]);
+    }
 }
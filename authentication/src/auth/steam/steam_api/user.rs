@@ -3,8 +3,8 @@
  */
 
 use super::URL;
+use crate::auth::sign_in_error::SignInError;
 use crate::config::STEAM_CONFIG;
-use actix_web::error;
 use std::time::Duration;
 
 #[async_trait::async_trait]
@@ -17,7 +17,7 @@ pub trait SteamUserService: Sync {
     async fn get_player_summaries(
         &self,
         steam_ids: &[&str],
-    ) -> Result<Vec<get_player_summaries::Player>, error::Error> {
+    ) -> Result<Vec<get_player_summaries::Player>, SignInError> {
         let client = reqwest::Client::new();
 
         let resp = client
@@ -29,15 +29,17 @@ pub trait SteamUserService: Sync {
             ])
             .send()
             .await
-            .map_err(error::ErrorInternalServerError)?;
+            .map_err(SignInError::upstream_unavailable)?;
 
-        let body: get_player_summaries::Body =
-            resp.json().await.map_err(error::ErrorInternalServerError)?;
+        let body: get_player_summaries::Body = resp
+            .json()
+            .await
+            .map_err(SignInError::upstream_unavailable)?;
 
         match body.response {
             get_player_summaries::Response::Players(players) => Ok(players),
             get_player_summaries::Response::Error(err) => {
-                Err(error::ErrorInternalServerError(err.error_description))
+                Err(SignInError::UpstreamUnavailable(err.error_description))
             }
         }
     }
@@ -47,16 +49,9 @@ pub struct RealSteamUserService;
 
 impl SteamUserService for RealSteamUserService {}
 
-mod get_player_summaries {
+pub mod get_player_summaries {
     use serde::{Deserialize, Serialize};
 
-    use crate::{
-        auth::provider::{
-            AuthProviderChangeset, AuthProviderInsert, AuthProviderType, IntoAuthProviderInsert,
-        },
-        user::{User, UserInsert},
-    };
-
     #[derive(Debug, Clone, Deserialize)]
     pub struct Body {
         pub response: Response,
@@ -90,45 +85,6 @@ mod get_player_summaries {
         pub avatar_full: String,
     }
 
-    impl From<&Player> for AuthProviderChangeset {
-        fn from(value: &Player) -> Self {
-            AuthProviderChangeset {
-                order: 0,
-                email: None,
-                email_verified: false,
-                display_name: Some(value.persona_name.clone()),
-                user_name: Some(value.persona_name.clone()),
-                picture_url: Some(value.avatar.clone()),
-                locale: None,
-            }
-        }
-    }
-
-    impl From<&Player> for UserInsert {
-        fn from(value: &Player) -> Self {
-            UserInsert {
-                name: Some(value.persona_name.clone()),
-            }
-        }
-    }
-
-    impl IntoAuthProviderInsert for Player {
-        fn into_provider_insert(&self, user: &User) -> AuthProviderInsert {
-            AuthProviderInsert {
-                user_id: user.id,
-                order: 0,
-                provider_type: AuthProviderType::Steam,
-                provider_id: self.steam_id.clone(),
-                email: None,
-                email_verified: false,
-                display_name: Some(self.persona_name.clone()),
-                user_name: Some(self.persona_name.clone()),
-                picture_url: Some(self.avatar.clone()),
-                locale: None,
-            }
-        }
-    }
-
     #[derive(Debug, Clone, Serialize, Deserialize)]
     pub struct Error {
         #[serde(rename = "errorcode")]
@@ -3,8 +3,8 @@
  */
 
 use super::{AUTH_SERVER_STEAM_IDENTITY, URL};
+use crate::auth::sign_in_error::SignInError;
 use crate::config::STEAM_CONFIG;
-use actix_web::error;
 use std::time::Duration;
 
 #[async_trait::async_trait]
@@ -17,7 +17,7 @@ pub trait SteamUserAuthService: Sync {
     async fn authenticate_user_ticket(
         &self,
         ticket: &str,
-    ) -> Result<authenticate_user_ticket::Params, error::Error> {
+    ) -> Result<authenticate_user_ticket::Params, SignInError> {
         let client = reqwest::Client::new();
 
         let resp = client
@@ -31,15 +31,17 @@ pub trait SteamUserAuthService: Sync {
             ])
             .send()
             .await
-            .map_err(error::ErrorInternalServerError)?;
+            .map_err(SignInError::upstream_unavailable)?;
 
-        let body: authenticate_user_ticket::Body =
-            resp.json().await.map_err(error::ErrorInternalServerError)?;
+        let body: authenticate_user_ticket::Body = resp
+            .json()
+            .await
+            .map_err(SignInError::upstream_unavailable)?;
 
         match body.response {
             authenticate_user_ticket::Response::Params(params) => Ok(params),
             authenticate_user_ticket::Response::Error(err) => {
-                Err(error::ErrorUnauthorized(err.error_description))
+                Err(SignInError::InvalidCredential(err.error_description))
             }
         }
     }
@@ -49,7 +51,7 @@ pub struct RealSteamUserAuthService;
 
 impl SteamUserAuthService for RealSteamUserAuthService {}
 
-mod authenticate_user_ticket {
+pub mod authenticate_user_ticket {
     use serde::{Deserialize, Serialize};
 
     #[derive(Debug, Clone, Deserialize)]
@@ -1,4 +1,6 @@
 use actix_web::{error, http, FromRequest};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL_SAFE_NO_PAD;
+use base64::prelude::*;
 use std::{
     future::{ready, Ready},
     ops::Deref,
@@ -37,3 +39,10 @@ impl AsRef<String> for BearerToken {
         &self.0
     }
 }
+
+/// Generates a random URL-safe string with 256 bits of entropy.
+pub fn generate_secret() -> String {
+    let mut bytes = [0; 32];
+    openssl::rand::rand_bytes(&mut bytes).expect("Failed to generate random bytes");
+    BASE64_URL_SAFE_NO_PAD.encode(bytes)
+}
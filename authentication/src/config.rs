@@ -1,7 +1,14 @@
 use crate::auth::identity::IdentityConfig;
+use crate::auth::keys::access::AccessSigningKey;
+use crate::auth::keys::ring::{KeyRing, RefreshSigningKey};
+use crate::rate_limit::RateLimit;
 use actix_cors::Cors;
 use chrono::Duration;
+use openssl::x509::X509;
 use std::env;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use uuid::Uuid;
 
 fn get_secret_text_or_file(var: &str) -> Option<String> {
     let secret_text = env::var(var);
@@ -25,9 +32,9 @@ fn get_required_secret_text_or_file(var: &str) -> String {
     } else {
         use std::fs;
         let secret_file = env::var(format!("{var}_FILE"))
-            .expect(format!("Expected either {var} or {var}_FILE to be set").as_str());
+            .unwrap_or_else(|_| panic!("Expected either {var} or {var}_FILE to be set"));
         fs::read_to_string(secret_file)
-            .expect(format!("The file at {var}_FILE should contain the secret").as_str())
+            .unwrap_or_else(|_| panic!("The file at {var}_FILE should contain the secret"))
     }
 }
 
@@ -46,23 +53,31 @@ fn get_db_url() -> String {
     format!("postgres://{user}:{password}@{host}:{port}/{db}")
 }
 
+/// The keys from the environment only seed the key ring. Once the database
+/// has keys, they are managed through the `/admin/keys/` routes instead.
 fn get_identity_config() -> IdentityConfig {
-    let secret = get_required_secret_text_or_file("IDENTITY_SECRET");
+    let access_key = AccessSigningKey::from_pem(
+        get_required_secret_text_or_file("IDENTITY_PRIVATE_KEY").as_bytes(),
+    )
+    .expect("IDENTITY_PRIVATE_KEY should be a PEM encoded RSA or Ed25519 private key");
     let expires_in_seconds = get_secret_text_or_file("IDENTITY_EXPIRES_IN_SECS")
         .and_then(|p| p.parse::<i64>().ok())
         .unwrap_or(3600);
     let expires_in = Duration::seconds(expires_in_seconds);
 
-    let refresh_secret = get_required_secret_text_or_file("REFRESH_SECRET");
+    let refresh_key = RefreshSigningKey::new(
+        &Uuid::new_v4().to_string(),
+        &get_required_secret_text_or_file("REFRESH_SECRET"),
+    );
     let refresh_expires_in_days = get_secret_text_or_file("REFRESH_EXPIRES_IN_DAYS")
         .and_then(|p| p.parse::<i64>().ok())
         .unwrap_or(7);
     let refresh_expires_in = Duration::days(refresh_expires_in_days);
 
     IdentityConfig {
-        secret,
+        keys: Arc::new(RwLock::new(KeyRing::new(access_key, refresh_key))),
+        revocations: Arc::default(),
         expires_in,
-        refresh_secret,
         refresh_expires_in,
     }
 }
@@ -79,15 +94,288 @@ fn get_oauth_client_secrets() -> OAuthClientSecrets {
     }
 }
 
+pub struct OAuthFlowConfig {
+    /// The URL of this server's `/auth/oauth2/callback/` route, as registered
+    /// with the OAuth 2.0 client.
+    pub redirect_url: String,
+    /// The origins that the browser may return to once signed in.
+    pub return_origins: Vec<String>,
+}
+
+fn get_oauth_flow_config() -> OAuthFlowConfig {
+    OAuthFlowConfig {
+        redirect_url: get_required_secret_text_or_file("OAUTH_REDIRECT_URL"),
+        return_origins: get_comma_separated("ALLOWED_ORIGINS"),
+    }
+}
+
 pub struct SteamConfig {
     pub app_id: String,
     pub web_api_key: String,
+    pub policies: SteamPolicies,
+}
+
+/// Which Steam players are rejected at sign-in. Players that are not rejected
+/// still have their ban and Family Sharing status stored on their provider.
+#[derive(Debug, Clone)]
+pub struct SteamPolicies {
+    pub reject_vac_banned: bool,
+    pub reject_publisher_banned: bool,
+    pub reject_family_shared: bool,
+}
+
+fn get_bool_or(var: &str, default: bool) -> bool {
+    get_secret_text_or_file(var)
+        .and_then(|p| p.trim().parse::<bool>().ok())
+        .unwrap_or(default)
 }
 
 fn get_steam_config() -> SteamConfig {
     SteamConfig {
         app_id: get_required_secret_text_or_file("STEAM_APP_ID"),
         web_api_key: get_required_secret_text_or_file("STEAM_WEB_API_KEY"),
+        policies: SteamPolicies {
+            reject_vac_banned: get_bool_or("STEAM_REJECT_VAC_BANNED", false),
+            reject_publisher_banned: get_bool_or("STEAM_REJECT_PUBLISHER_BANNED", true),
+            reject_family_shared: get_bool_or("STEAM_REJECT_FAMILY_SHARED", false),
+        },
+    }
+}
+
+pub struct GameCenterConfig {
+    /// The bundle ids of the games that players may sign in from.
+    pub bundle_ids: Vec<String>,
+    /// How far the timestamp of a Game Center identity may be from the current
+    /// time, so that a captured identity cannot be replayed later.
+    pub timestamp_window: Duration,
+    /// The certificates that Apple's public key certificates must chain to.
+    /// Self-signed certificates are trusted as roots, and the rest are used as
+    /// intermediates.
+    pub certificates: Vec<X509>,
+}
+
+fn get_game_center_config() -> GameCenterConfig {
+    let timestamp_window_seconds = get_secret_text_or_file("GAME_CENTER_TIMESTAMP_WINDOW_SECS")
+        .and_then(|p| p.parse::<i64>().ok())
+        .unwrap_or(300);
+    GameCenterConfig {
+        bundle_ids: get_required_secret_text_or_file("GAME_CENTER_BUNDLE_IDS")
+            .split(',')
+            .map(|id| id.trim().to_string())
+            .filter(|id| !id.is_empty())
+            .collect(),
+        timestamp_window: Duration::seconds(timestamp_window_seconds),
+        certificates: X509::stack_from_pem(include_bytes!("auth/game_center/certificates.pem"))
+            .expect("The bundled Game Center certificates should be PEM encoded"),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct OidcIssuerConfig {
+    /// The name of the issuer in routes and provider types, e.g. `apple`.
+    pub name: String,
+    /// The issuer identifier, which serves its discovery document at
+    /// `{issuer}/.well-known/openid-configuration`.
+    pub issuer: String,
+    /// The client ids that ID tokens may be issued to.
+    pub client_ids: Vec<String>,
+}
+
+fn get_comma_separated(var: &str) -> Vec<String> {
+    get_secret_text_or_file(var)
+        .unwrap_or_default()
+        .split(',')
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+        .collect()
+}
+
+/// Every issuer named in `OIDC_ISSUERS` is configured by
+/// `OIDC_{NAME}_ISSUER` and `OIDC_{NAME}_CLIENT_IDS`.
+fn get_oidc_issuers() -> Vec<OidcIssuerConfig> {
+    get_comma_separated("OIDC_ISSUERS")
+        .into_iter()
+        .map(|name| {
+            assert!(
+                name.chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_'),
+                "OIDC issuer names should only contain lowercase letters, digits and underscores"
+            );
+            let prefix = format!("OIDC_{}", name.to_uppercase());
+            let client_ids = get_comma_separated(&format!("{prefix}_CLIENT_IDS"));
+            assert!(
+                !client_ids.is_empty(),
+                "Expected {prefix}_CLIENT_IDS to be set"
+            );
+            OidcIssuerConfig {
+                issuer: get_required_secret_text_or_file(&format!("{prefix}_ISSUER")),
+                name,
+                client_ids,
+            }
+        })
+        .collect()
+}
+
+/// Guests are deleted once they have not signed in or refreshed their session
+/// for `GUEST_EXPIRES_IN_DAYS`.
+fn get_guest_expires_in() -> Duration {
+    let guest_expires_in_days = get_secret_text_or_file("GUEST_EXPIRES_IN_DAYS")
+        .and_then(|p| p.parse::<i64>().ok())
+        .unwrap_or(30);
+    Duration::days(guest_expires_in_days)
+}
+
+pub enum MailerConfig {
+    /// Prints outgoing mail, for local testing.
+    Log,
+    /// Writes every outgoing mail to a file in `dir`, for local testing.
+    File { dir: PathBuf },
+}
+
+pub struct MailConfig {
+    pub mailer: MailerConfig,
+    /// The sender of outgoing mail.
+    pub from: String,
+}
+
+fn get_mail_config() -> MailConfig {
+    let mailer = match get_secret_text_or_file("MAILER").as_deref().map(str::trim) {
+        None | Some("") | Some("log") => MailerConfig::Log,
+        Some("file") => MailerConfig::File {
+            dir: get_secret_text_or_file("MAILER_DIR")
+                .unwrap_or_else(|| "mail".to_string())
+                .into(),
+        },
+        Some(mailer) => panic!("Unknown MAILER {mailer}, expected `log` or `file`"),
+    };
+    MailConfig {
+        mailer,
+        from: get_secret_text_or_file("MAIL_FROM")
+            .unwrap_or_else(|| "noreply@localhost".to_string()),
+    }
+}
+
+pub struct PasswordConfig {
+    /// The page that verifies an email, which is sent `?token=` by mail.
+    pub verify_email_url: Option<String>,
+    /// The page that resets a password, which is sent `?token=` by mail.
+    pub reset_password_url: Option<String>,
+}
+
+fn get_password_config() -> PasswordConfig {
+    PasswordConfig {
+        verify_email_url: get_secret_text_or_file("PASSWORD_VERIFY_EMAIL_URL"),
+        reset_password_url: get_secret_text_or_file("PASSWORD_RESET_URL"),
+    }
+}
+
+pub enum RateLimitStoreConfig {
+    /// Every instance of the server limits requests on its own.
+    Memory,
+    /// Every instance of the server shares the limits in the database.
+    Postgres,
+}
+
+pub struct RouteRateLimits {
+    pub per_ip: Option<RateLimit>,
+    pub per_credential: Option<RateLimit>,
+}
+
+pub struct RateLimitConfig {
+    pub store: RateLimitStoreConfig,
+    /// Signing in and linking providers.
+    pub sign_in: RouteRateLimits,
+    pub refresh: RouteRateLimits,
+    /// Routes that send mail.
+    pub mail: RouteRateLimits,
+}
+
+/// A rate limit is set as `{capacity}/{period in seconds}`, or `off`.
+fn get_rate_limit_or(var: &str, default: &str) -> Option<RateLimit> {
+    let limit = get_secret_text_or_file(var).unwrap_or_else(|| default.to_string());
+    if limit.trim() == "off" {
+        return None;
+    }
+    Some(
+        limit
+            .parse()
+            .unwrap_or_else(|err| panic!("Invalid {var}: {err}")),
+    )
+}
+
+/// The limits of a group of routes are set with `RATE_LIMIT_{NAME}_PER_IP`
+/// and `RATE_LIMIT_{NAME}_PER_CREDENTIAL`.
+fn get_route_rate_limits(name: &str, per_ip: &str, per_credential: &str) -> RouteRateLimits {
+    RouteRateLimits {
+        per_ip: get_rate_limit_or(&format!("RATE_LIMIT_{name}_PER_IP"), per_ip),
+        per_credential: get_rate_limit_or(
+            &format!("RATE_LIMIT_{name}_PER_CREDENTIAL"),
+            per_credential,
+        ),
+    }
+}
+
+fn get_rate_limit_config() -> RateLimitConfig {
+    let store = match get_secret_text_or_file("RATE_LIMIT_STORE")
+        .as_deref()
+        .map(str::trim)
+    {
+        None | Some("") | Some("memory") => RateLimitStoreConfig::Memory,
+        Some("postgres") => RateLimitStoreConfig::Postgres,
+        Some(store) => panic!("Unknown RATE_LIMIT_STORE {store}, expected `memory` or `postgres`"),
+    };
+    RateLimitConfig {
+        store,
+        sign_in: get_route_rate_limits("SIGN_IN", "30/60", "5/60"),
+        refresh: get_route_rate_limits("REFRESH", "60/60", "3/60"),
+        mail: get_route_rate_limits("MAIL", "10/3600", "3/3600"),
+    }
+}
+
+pub enum NameUniqueness {
+    /// No two users may have the same name.
+    Unique,
+    /// Users may share a name, and are told apart by a random number, e.g.
+    /// `Adam#0042`.
+    Discriminator,
+}
+
+pub struct DisplayNameConfig {
+    pub uniqueness: NameUniqueness,
+    /// The length of a name in characters.
+    pub min_length: usize,
+    pub max_length: usize,
+    /// Names that contain any of these words are rejected.
+    pub banned_words: Vec<String>,
+}
+
+fn get_display_name_config() -> DisplayNameConfig {
+    let uniqueness = match get_secret_text_or_file("DISPLAY_NAME_UNIQUENESS")
+        .as_deref()
+        .map(str::trim)
+    {
+        None | Some("") | Some("discriminator") => NameUniqueness::Discriminator,
+        Some("unique") => NameUniqueness::Unique,
+        Some(uniqueness) => panic!(
+            "Unknown DISPLAY_NAME_UNIQUENESS {uniqueness}, expected `discriminator` or `unique`"
+        ),
+    };
+    let get_length_or = |var: &str, default: usize| {
+        get_secret_text_or_file(var)
+            .and_then(|p| p.trim().parse::<usize>().ok())
+            .unwrap_or(default)
+    };
+    DisplayNameConfig {
+        uniqueness,
+        min_length: get_length_or("DISPLAY_NAME_MIN_LENGTH", 3),
+        max_length: get_length_or("DISPLAY_NAME_MAX_LENGTH", 24),
+        // A file of banned words can have one word per line.
+        banned_words: get_secret_text_or_file("DISPLAY_NAME_BANNED_WORDS")
+            .unwrap_or_default()
+            .split([',', '\n'])
+            .map(|word| word.trim().to_string())
+            .filter(|word| !word.is_empty())
+            .collect(),
     }
 }
 
@@ -95,7 +383,24 @@ lazy_static::lazy_static! {
     pub static ref DB_URL: String = get_db_url();
     pub static ref IDENTITY_CONFIG: IdentityConfig = get_identity_config();
     pub static ref OAUTH_CLIENT_SECRETS: OAuthClientSecrets = get_oauth_client_secrets();
+    pub static ref OAUTH_FLOW_CONFIG: OAuthFlowConfig = get_oauth_flow_config();
     pub static ref STEAM_CONFIG: SteamConfig = get_steam_config();
+    pub static ref GAME_CENTER_CONFIG: GameCenterConfig = get_game_center_config();
+    pub static ref OIDC_ISSUERS: Vec<OidcIssuerConfig> = get_oidc_issuers();
+    pub static ref GUEST_EXPIRES_IN: Duration = get_guest_expires_in();
+    pub static ref MAIL_CONFIG: MailConfig = get_mail_config();
+    pub static ref PASSWORD_CONFIG: PasswordConfig = get_password_config();
+    pub static ref RATE_LIMIT_CONFIG: RateLimitConfig = get_rate_limit_config();
+    pub static ref DISPLAY_NAME_CONFIG: DisplayNameConfig = get_display_name_config();
+    /// Whether clients are identified by the IP in `Forwarded` or
+    /// `X-Forwarded-For` instead of the peer address, for when the server runs
+    /// behind a proxy.
+    pub static ref TRUST_FORWARDED_FOR: bool = get_bool_or("TRUST_FORWARDED_FOR", false);
+    /// The `/admin/` routes are disabled unless an admin key is configured.
+    pub static ref ADMIN_KEY: Option<String> = get_secret_text_or_file("ADMIN_KEY");
+    /// The key that other services list token revocations with.
+    pub static ref REVOCATIONS_SERVICE_KEY: Option<String> =
+        get_secret_text_or_file("REVOCATIONS_SERVICE_KEY");
 }
 
 pub fn get_cors_config() -> Cors {
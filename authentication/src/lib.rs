@@ -1,7 +1,11 @@
+pub mod admin;
 pub mod auth;
 pub mod config;
 pub mod db;
+pub mod mail;
+pub mod rate_limit;
 pub mod schema;
+pub mod service_key;
 pub mod user;
 
 /// Given a Diesel struct `Data`, create a struct `InsertData` that contains
@@ -0,0 +1,92 @@
+use crate::config::{MailConfig, MailerConfig};
+use chrono::Utc;
+use std::path::PathBuf;
+use std::sync::Arc;
+use uuid::Uuid;
+
+pub type MailError = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[async_trait::async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, mail: &Mail) -> Result<(), MailError>;
+}
+
+pub struct LogMailer {
+    from: String,
+}
+
+#[async_trait::async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, mail: &Mail) -> Result<(), MailError> {
+        println!("{}", format_mail(&self.from, mail));
+        Ok(())
+    }
+}
+
+pub struct FileMailer {
+    from: String,
+    dir: PathBuf,
+}
+
+#[async_trait::async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, mail: &Mail) -> Result<(), MailError> {
+        std::fs::create_dir_all(&self.dir)?;
+        let file_name = format!("{}-{}.eml", Utc::now().timestamp_millis(), Uuid::new_v4());
+        std::fs::write(self.dir.join(file_name), format_mail(&self.from, mail))?;
+        Ok(())
+    }
+}
+
+fn format_mail(from: &str, mail: &Mail) -> String {
+    format!(
+        "From: {from}\r\nTo: {}\r\nSubject: {}\r\n\r\n{}\r\n",
+        mail.to, mail.subject, mail.body
+    )
+}
+
+pub fn configured_mailer(config: &MailConfig) -> Arc<dyn Mailer> {
+    let from = config.from.clone();
+    match &config.mailer {
+        MailerConfig::Log => Arc::new(LogMailer { from }),
+        MailerConfig::File { dir } => Arc::new(FileMailer {
+            from,
+            dir: dir.clone(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_web::test]
+    async fn file_mailer_writes_each_mail_to_a_file() {
+        let dir = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        let mailer = configured_mailer(&MailConfig {
+            mailer: MailerConfig::File { dir: dir.clone() },
+            from: "noreply@example.com".to_string(),
+        });
+        let mail = Mail {
+            to: "bryan@example.com".to_string(),
+            subject: "Hello".to_string(),
+            body: "World".to_string(),
+        };
+        mailer.send(&mail).await.unwrap();
+        mailer.send(&mail).await.unwrap();
+
+        let files: Vec<_> = std::fs::read_dir(&dir).unwrap().collect();
+        assert_eq!(files.len(), 2);
+        let contents = std::fs::read_to_string(files[0].as_ref().unwrap().path()).unwrap();
+        assert!(contents.starts_with("From: noreply@example.com\r\nTo: bryan@example.com\r\n"));
+        assert!(contents.contains("\r\n\r\nWorld"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,14 +1,30 @@
 use actix_web::{get, middleware, web, App, HttpServer, Responder};
-use authentication::{auth, config, db, user};
+use authentication::rate_limit::{self, RateLimitStore, RateLimiter};
+use authentication::{admin, auth, auth::identity::IdentityConfig, config, db, user};
+use std::sync::Arc;
 
 #[get("/")]
 async fn hello() -> impl Responder {
     "MultiplayerBase Authentication Server"
 }
 
-const HOST: &'static str = "0.0.0.0";
+const HOST: &str = "0.0.0.0";
 const PORT: u16 = 8000;
 
+/// How often signing keys are reloaded to pick up rotations made by other
+/// instances.
+const KEYS_SYNC_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// How often token revocations are reloaded to pick up revocations made by
+/// other instances.
+const REVOCATIONS_SYNC_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// How often guests that are no longer used are deleted.
+const GUEST_CLEANUP_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+/// How often rate limit buckets that have refilled are forgotten.
+const RATE_LIMIT_PRUNE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     dotenvy::dotenv().ok();
@@ -17,6 +33,24 @@ async fn main() -> std::io::Result<()> {
 
     let db_pool = db::initialize_db_pool(&config::DB_URL).await;
     let identity_config = config::IDENTITY_CONFIG.clone();
+    {
+        let mut conn = db_pool
+            .get()
+            .await
+            .expect("Failed to get a database connection");
+        identity_config
+            .sync_keys(&mut conn)
+            .await
+            .expect("Failed to load signing keys");
+        identity_config
+            .sync_revocations(&mut conn)
+            .await
+            .expect("Failed to load token revocations");
+    }
+    sync_identity_config_periodically(db_pool.clone(), identity_config.clone());
+    delete_unused_guests_periodically(db_pool.clone());
+    let rate_limit_store = rate_limit::configured_store(&config::RATE_LIMIT_CONFIG, &db_pool);
+    prune_rate_limits_periodically(rate_limit_store.clone());
 
     HttpServer::new(move || {
         App::new()
@@ -28,10 +62,81 @@ async fn main() -> std::io::Result<()> {
             .wrap(config::get_cors_config())
             .wrap(middleware::Logger::default())
             .service(hello)
-            .service(web::scope("/auth").configure(auth::config_service))
+            .service(web::scope("/.well-known").configure(auth::keys::config_service))
+            .service(
+                web::scope("/auth")
+                    .wrap(
+                        RateLimiter::new(
+                            rate_limit_store.clone(),
+                            auth::rate_limit_rules(&config::RATE_LIMIT_CONFIG),
+                        )
+                        .trust_forwarded_for(*config::TRUST_FORWARDED_FOR),
+                    )
+                    .configure(auth::config_service),
+            )
             .service(web::scope("/user").configure(user::config_service))
+            .service(web::scope("/admin").configure(admin::config_service))
     })
     .bind((HOST, PORT))?
     .run()
     .await
 }
+
+fn sync_identity_config_periodically(db_pool: db::DbPool, identity_config: IdentityConfig) {
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(REVOCATIONS_SYNC_INTERVAL);
+        let mut keys_synced_at = std::time::Instant::now();
+        loop {
+            interval.tick().await;
+            let mut conn = match db_pool.get().await {
+                Ok(conn) => conn,
+                Err(err) => {
+                    println!("Failed to get a database connection: {err}");
+                    continue;
+                }
+            };
+            if let Err(err) = identity_config.sync_revocations(&mut conn).await {
+                println!("Failed to sync token revocations: {err}");
+            }
+            if keys_synced_at.elapsed() >= KEYS_SYNC_INTERVAL {
+                keys_synced_at = std::time::Instant::now();
+                if let Err(err) = identity_config.sync_keys(&mut conn).await {
+                    println!("Failed to sync signing keys: {err}");
+                }
+            }
+        }
+    });
+}
+
+fn delete_unused_guests_periodically(db_pool: db::DbPool) {
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(GUEST_CLEANUP_INTERVAL);
+        loop {
+            interval.tick().await;
+            let mut conn = match db_pool.get().await {
+                Ok(conn) => conn,
+                Err(err) => {
+                    println!("Failed to get a database connection: {err}");
+                    continue;
+                }
+            };
+            match auth::guest::delete_unused_guests(&mut conn, *config::GUEST_EXPIRES_IN).await {
+                Ok(0) => {}
+                Ok(count) => println!("Deleted {count} unused guests"),
+                Err(err) => println!("Failed to delete unused guests: {err}"),
+            }
+        }
+    });
+}
+
+fn prune_rate_limits_periodically(store: Arc<dyn RateLimitStore>) {
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(RATE_LIMIT_PRUNE_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(err) = store.prune().await {
+                println!("Failed to prune rate limits: {err}");
+            }
+        }
+    });
+}
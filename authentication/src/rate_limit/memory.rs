@@ -0,0 +1,89 @@
+use crate::rate_limit::{RateLimit, RateLimitStore, StoreError, Take};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Instant;
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+    full_at: Instant,
+}
+
+/// Keeps buckets in memory, so every instance of the server limits requests
+/// on its own.
+#[derive(Default)]
+pub struct MemoryStore {
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl MemoryStore {
+    fn take_at(&self, key: &str, limit: &RateLimit, now: Instant) -> Take {
+        let mut buckets = self
+            .buckets
+            .lock()
+            .expect("Failed to get lock on rate limit buckets");
+        let capacity = limit.capacity as f64;
+        let refill_rate = limit.refill_rate();
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: capacity,
+            updated_at: now,
+            full_at: now,
+        });
+
+        let elapsed = now.duration_since(bucket.updated_at).as_secs_f64();
+        let tokens = (bucket.tokens + elapsed * refill_rate).min(capacity);
+        if tokens < 1.0 {
+            return Take::RetryAfter(limit.retry_after(tokens));
+        }
+        bucket.tokens = tokens - 1.0;
+        bucket.updated_at = now;
+        bucket.full_at =
+            now + std::time::Duration::from_secs_f64((capacity - bucket.tokens) / refill_rate);
+        Take::Allowed
+    }
+}
+
+#[async_trait::async_trait]
+impl RateLimitStore for MemoryStore {
+    async fn take(&self, key: &str, limit: &RateLimit) -> Result<Take, StoreError> {
+        Ok(self.take_at(key, limit, Instant::now()))
+    }
+
+    async fn prune(&self) -> Result<(), StoreError> {
+        let now = Instant::now();
+        self.buckets
+            .lock()
+            .expect("Failed to get lock on rate limit buckets")
+            .retain(|_, bucket| bucket.full_at > now);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn buckets_refill_over_their_period() {
+        let store = MemoryStore::default();
+        let limit: RateLimit = "2/4".parse().unwrap();
+        let start = Instant::now();
+
+        assert_eq!(store.take_at("a", &limit, start), Take::Allowed);
+        assert_eq!(store.take_at("a", &limit, start), Take::Allowed);
+        assert_eq!(
+            store.take_at("a", &limit, start),
+            Take::RetryAfter(Duration::from_secs(2))
+        );
+        assert_eq!(store.take_at("b", &limit, start), Take::Allowed);
+
+        let later = start + Duration::from_secs(2);
+        assert_eq!(store.take_at("a", &limit, later), Take::Allowed);
+        assert_eq!(
+            store.take_at("a", &limit, later + Duration::from_secs(1)),
+            Take::RetryAfter(Duration::from_secs(1))
+        );
+    }
+}
@@ -0,0 +1,335 @@
+pub mod memory;
+pub mod postgres;
+
+use crate::auth::device::client_ip;
+use crate::config::{RateLimitConfig, RateLimitStoreConfig};
+use crate::db::DbPool;
+use actix_web::body::EitherBody;
+use actix_web::dev::{
+    forward_ready, ResourceDef, Service, ServiceRequest, ServiceResponse, Transform,
+};
+use actix_web::http::header::RETRY_AFTER;
+use actix_web::web::Bytes;
+use actix_web::HttpResponse;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL_SAFE_NO_PAD;
+use base64::prelude::*;
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub type StoreError = Box<dyn std::error::Error + Send + Sync>;
+
+/// A token bucket that holds up to `capacity` requests, and refills completely
+/// over `period`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    pub capacity: u32,
+    pub period: Duration,
+}
+
+impl RateLimit {
+    /// The tokens added to a bucket per second.
+    pub fn refill_rate(&self) -> f64 {
+        self.capacity as f64 / self.period.as_secs_f64()
+    }
+
+    /// How long until a bucket with `tokens` has a whole token.
+    pub fn retry_after(&self, tokens: f64) -> Duration {
+        Duration::from_secs_f64(((1.0 - tokens) / self.refill_rate()).max(0.0))
+    }
+}
+
+/// Parses `{capacity}/{period in seconds}`, e.g. `20/60`.
+impl FromStr for RateLimit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Expected a rate limit like `20/60`, got `{s}`");
+        let (capacity, period) = s.trim().split_once('/').ok_or_else(invalid)?;
+        let capacity: u32 = capacity.trim().parse().map_err(|_| invalid())?;
+        let period: u64 = period.trim().parse().map_err(|_| invalid())?;
+        if capacity == 0 || period == 0 {
+            return Err(invalid());
+        }
+        Ok(RateLimit {
+            capacity,
+            period: Duration::from_secs(period),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Take {
+    Allowed,
+    RetryAfter(Duration),
+}
+
+/// Keeps the token buckets of every key.
+#[async_trait::async_trait]
+pub trait RateLimitStore: Send + Sync {
+    /// Takes a token from the bucket of `key`, which starts full.
+    async fn take(&self, key: &str, limit: &RateLimit) -> Result<Take, StoreError>;
+
+    /// Forgets buckets that have refilled completely.
+    async fn prune(&self) -> Result<(), StoreError>;
+}
+
+pub fn configured_store(config: &RateLimitConfig, pool: &DbPool) -> Arc<dyn RateLimitStore> {
+    match config.store {
+        RateLimitStoreConfig::Memory => Arc::new(memory::MemoryStore::default()),
+        RateLimitStoreConfig::Postgres => Arc::new(postgres::PostgresStore::new(pool.clone())),
+    }
+}
+
+/// What identifies the credential of a request, so that attempts with the
+/// same credential are limited across IPs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CredentialKey {
+    /// The whole body, e.g. a Steam ticket or an ID token.
+    Body,
+    /// A string field of a JSON body, e.g. an email.
+    JsonField(&'static str),
+}
+
+impl CredentialKey {
+    fn credential(&self, body: &Bytes) -> Option<String> {
+        let credential = match self {
+            CredentialKey::Body => body.to_vec(),
+            CredentialKey::JsonField(field) => serde_json::from_slice::<serde_json::Value>(body)
+                .ok()?
+                .get(field)?
+                .as_str()?
+                .trim()
+                .to_lowercase()
+                .into_bytes(),
+        };
+        if credential.is_empty() {
+            return None;
+        }
+        // Credentials are hashed so that the store never holds them.
+        Some(BASE64_URL_SAFE_NO_PAD.encode(openssl::sha::sha256(&credential)))
+    }
+}
+
+/// Limits the requests to the routes that match any of its patterns, which
+/// share their buckets.
+#[derive(Debug, Clone)]
+pub struct RateLimitRule {
+    pub name: &'static str,
+    pub routes: ResourceDef,
+    pub per_ip: Option<RateLimit>,
+    pub per_credential: Option<(CredentialKey, RateLimit)>,
+}
+
+impl RateLimitRule {
+    pub fn new(name: &'static str, patterns: &[&str]) -> Self {
+        RateLimitRule {
+            name,
+            routes: ResourceDef::new(patterns.to_vec()),
+            per_ip: None,
+            per_credential: None,
+        }
+    }
+
+    pub fn per_ip(self, limit: Option<RateLimit>) -> Self {
+        RateLimitRule {
+            per_ip: limit,
+            ..self
+        }
+    }
+
+    pub fn per_credential(self, key: CredentialKey, limit: Option<RateLimit>) -> Self {
+        RateLimitRule {
+            per_credential: limit.map(|limit| (key, limit)),
+            ..self
+        }
+    }
+}
+
+/// Middleware that responds with `429 Too Many Requests` once a bucket of the
+/// first rule that matches a request is empty.
+#[derive(Clone)]
+pub struct RateLimiter {
+    store: Arc<dyn RateLimitStore>,
+    rules: Rc<Vec<RateLimitRule>>,
+    trust_forwarded_for: bool,
+}
+
+impl RateLimiter {
+    pub fn new(store: Arc<dyn RateLimitStore>, rules: Vec<RateLimitRule>) -> Self {
+        RateLimiter {
+            store,
+            rules: Rc::new(rules),
+            trust_forwarded_for: false,
+        }
+    }
+
+    /// Limits by the client IP in `Forwarded` or `X-Forwarded-For`, for when
+    /// the server runs behind a proxy.
+    pub fn trust_forwarded_for(self, trust_forwarded_for: bool) -> Self {
+        RateLimiter {
+            trust_forwarded_for,
+            ..self
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimiter
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Transform = RateLimiterMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimiterMiddleware {
+            service: Rc::new(service),
+            limiter: self.clone(),
+        }))
+    }
+}
+
+pub struct RateLimiterMiddleware<S> {
+    service: Rc<S>,
+    limiter: RateLimiter,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimiterMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let limiter = self.limiter.clone();
+        Box::pin(async move {
+            let Some(rule) = limiter
+                .rules
+                .iter()
+                .find(|rule| rule.routes.is_match(req.path()))
+            else {
+                return service.call(req).await.map(|res| res.map_into_left_body());
+            };
+
+            let mut buckets = vec![];
+            if let Some(limit) = rule.per_ip {
+                if let Some(ip) = client_ip(req.request(), limiter.trust_forwarded_for) {
+                    buckets.push((format!("{}:ip:{ip}", rule.name), limit));
+                }
+            }
+            if let Some((key, limit)) = rule.per_credential {
+                let body = req.extract::<Bytes>().await?;
+                if let Some(credential) = key.credential(&body) {
+                    buckets.push((format!("{}:credential:{credential}", rule.name), limit));
+                }
+                // The body was taken for the key, so it is put back for the
+                // route.
+                let (_, mut payload) = actix_http::h1::Payload::create(true);
+                payload.unread_data(body);
+                req.set_payload(payload.into());
+            }
+
+            for (key, limit) in buckets {
+                match limiter.store.take(&key, &limit).await {
+                    Ok(Take::Allowed) => {}
+                    Ok(Take::RetryAfter(retry_after)) => {
+                        let response = HttpResponse::TooManyRequests()
+                            .insert_header((RETRY_AFTER, retry_after.as_secs_f64().ceil() as u64))
+                            .body("Too many requests, try again later");
+                        return Ok(req.into_response(response).map_into_right_body());
+                    }
+                    // An unavailable store should not lock everyone out.
+                    Err(err) => println!("Failed to take a rate limit token: {err}"),
+                }
+            }
+
+            service.call(req).await.map(|res| res.map_into_left_body())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rate_limit::memory::MemoryStore;
+    use actix_web::http::StatusCode;
+    use actix_web::{post, test, App};
+
+    use super::*;
+
+    #[post("/sign-in/")]
+    async fn sign_in(body: String) -> String {
+        body
+    }
+
+    #[actix_web::test]
+    async fn rate_limits_are_parsed() {
+        assert_eq!(
+            "20/60".parse(),
+            Ok(RateLimit {
+                capacity: 20,
+                period: Duration::from_secs(60)
+            })
+        );
+        for limit in ["20", "0/60", "20/0", "a/b"] {
+            assert!(limit.parse::<RateLimit>().is_err(), "{limit}");
+        }
+    }
+
+    #[actix_web::test]
+    async fn requests_are_limited_per_ip_and_per_credential() {
+        let rule = RateLimitRule::new("sign_in", &["/sign-in/"])
+            .per_ip(Some("3/60".parse().unwrap()))
+            .per_credential(
+                CredentialKey::JsonField("email"),
+                Some("2/60".parse().unwrap()),
+            );
+        let app = test::init_service(
+            App::new()
+                .wrap(RateLimiter::new(
+                    Arc::new(MemoryStore::default()),
+                    vec![rule],
+                ))
+                .service(sign_in),
+        )
+        .await;
+
+        let sign_in_from = |ip: &str, email: &str| {
+            test::TestRequest::post()
+                .uri("/sign-in/")
+                .peer_addr(format!("{ip}:1234").parse().unwrap())
+                .set_payload(format!(r#"{{"email":"{email}"}}"#))
+                .to_request()
+        };
+
+        for _ in 0..2 {
+            let resp =
+                test::call_service(&app, sign_in_from("10.0.0.1", "bryan@example.com")).await;
+            assert_eq!(resp.status(), StatusCode::OK);
+            let body = test::read_body(resp).await;
+            assert_eq!(body, r#"{"email":"bryan@example.com"}"#);
+        }
+
+        let resp = test::call_service(&app, sign_in_from("10.0.0.2", "Bryan@Example.com")).await;
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        let retry_after = resp.headers().get(RETRY_AFTER).unwrap().to_str().unwrap();
+        assert_eq!(retry_after, "30");
+
+        let resp = test::call_service(&app, sign_in_from("10.0.0.1", "adam@example.com")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let resp = test::call_service(&app, sign_in_from("10.0.0.1", "eve@example.com")).await;
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+}
@@ -0,0 +1,110 @@
+use crate::db::DbPool;
+use crate::rate_limit::{RateLimit, RateLimitStore, StoreError, Take};
+use crate::schema;
+use diesel::prelude::*;
+use diesel::sql_types::{Double, Text};
+use diesel_async::RunQueryDsl;
+
+/// The tokens of a bucket after refilling it since it was last updated.
+const REFILLED_TOKENS: &str = "least($2, rate_limit_bucket.tokens + extract(epoch from now() - rate_limit_bucket.updated_at)::float8 * $3)";
+
+#[derive(QueryableByName)]
+struct BucketTokens {
+    #[diesel(sql_type = Double)]
+    tokens: f64,
+}
+
+/// Keeps buckets in the `rate_limit_bucket` table, so that every instance of
+/// the server shares them.
+pub struct PostgresStore {
+    pool: DbPool,
+}
+
+impl PostgresStore {
+    pub fn new(pool: DbPool) -> Self {
+        PostgresStore { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl RateLimitStore for PostgresStore {
+    async fn take(&self, key: &str, limit: &RateLimit) -> Result<Take, StoreError> {
+        let mut conn = self.pool.get().await?;
+        let capacity = limit.capacity as f64;
+        let refill_rate = limit.refill_rate();
+
+        // Only takes a token if the refilled bucket has a whole one.
+        let taken: Option<BucketTokens> = diesel::sql_query(format!(
+            "insert into rate_limit_bucket (key, tokens, updated_at, full_at)
+            values ($1, $2 - 1, now(), now() + make_interval(secs => 1 / $3))
+            on conflict (key) do update set
+                tokens = {REFILLED_TOKENS} - 1,
+                updated_at = now(),
+                full_at = now() + make_interval(secs => ($2 - {REFILLED_TOKENS} + 1) / $3)
+            where {REFILLED_TOKENS} >= 1
+            returning tokens"
+        ))
+        .bind::<Text, _>(key)
+        .bind::<Double, _>(capacity)
+        .bind::<Double, _>(refill_rate)
+        .get_result(&mut conn)
+        .await
+        .optional()?;
+        if taken.is_some() {
+            return Ok(Take::Allowed);
+        }
+
+        let bucket: BucketTokens = diesel::sql_query(format!(
+            "select {REFILLED_TOKENS} as tokens from rate_limit_bucket where key = $1"
+        ))
+        .bind::<Text, _>(key)
+        .bind::<Double, _>(capacity)
+        .bind::<Double, _>(refill_rate)
+        .get_result(&mut conn)
+        .await?;
+        Ok(Take::RetryAfter(limit.retry_after(bucket.tokens)))
+    }
+
+    async fn prune(&self) -> Result<(), StoreError> {
+        let mut conn = self.pool.get().await?;
+        diesel::delete(schema::rate_limit_bucket::table)
+            .filter(schema::rate_limit_bucket::full_at.lt(diesel::dsl::now))
+            .execute(&mut conn)
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{config, db};
+    use std::time::Duration;
+
+    use super::*;
+
+    #[actix_web::test]
+    async fn buckets_are_shared_through_the_database() {
+        let store = PostgresStore::new(db::initialize_db_pool(&config::DB_URL).await);
+        let limit: RateLimit = "2/4".parse().unwrap();
+
+        assert_eq!(store.take("a", &limit).await.unwrap(), Take::Allowed);
+        assert_eq!(store.take("a", &limit).await.unwrap(), Take::Allowed);
+        // The test transaction keeps `now()` fixed, so the bucket does not
+        // refill.
+        assert_eq!(
+            store.take("a", &limit).await.unwrap(),
+            Take::RetryAfter(Duration::from_secs(2))
+        );
+        assert_eq!(store.take("b", &limit).await.unwrap(), Take::Allowed);
+
+        store.prune().await.unwrap();
+        let mut conn = store.pool.get().await.unwrap();
+        let keys: Vec<String> = schema::rate_limit_bucket::table
+            .select(schema::rate_limit_bucket::key)
+            .order(schema::rate_limit_bucket::key.asc())
+            .load(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(keys, ["a", "b"]);
+    }
+}
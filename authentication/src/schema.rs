@@ -1,5 +1,19 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    auth_event (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        kind -> Text,
+        outcome -> Text,
+        provider_type -> Nullable<Text>,
+        session_id -> Nullable<Uuid>,
+        ip -> Nullable<Text>,
+        user_agent -> Nullable<Text>,
+        created_at -> Timestamptz,
+    }
+}
+
 diesel::table! {
     auth_provider (id) {
         id -> Uuid,
@@ -13,6 +27,26 @@ diesel::table! {
         user_name -> Nullable<Text>,
         picture_url -> Nullable<Text>,
         locale -> Nullable<Text>,
+        family_shared_owner_id -> Nullable<Text>,
+        vac_banned -> Bool,
+        publisher_banned -> Bool,
+    }
+}
+
+diesel::table! {
+    password_credential (auth_provider_id) {
+        auth_provider_id -> Uuid,
+        password_hash -> Text,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    rate_limit_bucket (key) {
+        key -> Text,
+        tokens -> Float8,
+        updated_at -> Timestamptz,
+        full_at -> Timestamptz,
     }
 }
 
@@ -24,6 +58,33 @@ diesel::table! {
         expires_at -> Timestamptz,
         count -> Int8,
         invalidated -> Bool,
+        device_id -> Nullable<Text>,
+        device_label -> Nullable<Text>,
+        platform -> Nullable<Text>,
+        user_agent -> Nullable<Text>,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    signing_key (id) {
+        id -> Uuid,
+        kid -> Text,
+        purpose -> Text,
+        material -> Text,
+        status -> Text,
+        created_at -> Timestamptz,
+        retires_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    token_revocation (id) {
+        id -> Uuid,
+        kind -> Text,
+        subject_id -> Uuid,
+        revoked_at -> Timestamptz,
+        expires_at -> Timestamptz,
     }
 }
 
@@ -31,14 +92,34 @@ diesel::table! {
     user (id) {
         id -> Uuid,
         name -> Nullable<Text>,
+        discriminator -> Nullable<Int2>,
+    }
+}
+
+diesel::table! {
+    user_merge (id) {
+        id -> Uuid,
+        surviving_user_id -> Uuid,
+        merged_user_id -> Uuid,
+        moved_provider_ids -> Array<Uuid>,
+        initiated_by -> Text,
+        merged_at -> Timestamptz,
     }
 }
 
+diesel::joinable!(auth_event -> user (user_id));
 diesel::joinable!(auth_provider -> user (user_id));
+diesel::joinable!(password_credential -> auth_provider (auth_provider_id));
 diesel::joinable!(refresh_session -> user (user_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    auth_event,
     auth_provider,
+    password_credential,
+    rate_limit_bucket,
     refresh_session,
+    signing_key,
+    token_revocation,
     user,
+    user_merge,
 );
@@ -0,0 +1,39 @@
+use actix_web::{error, FromRequest};
+use openssl::memcmp;
+use std::future::{ready, Ready};
+
+/// The `Service-Key` header of a request from another service.
+pub struct ServiceKey(String);
+
+impl ServiceKey {
+    /// Accepts the key if it is the configured key. Requests are always
+    /// rejected when no key is configured.
+    pub fn validate(&self, expected: Option<&str>) -> Result<(), error::Error> {
+        match expected {
+            Some(expected)
+                if self.0.len() == expected.len()
+                    && memcmp::eq(self.0.as_bytes(), expected.as_bytes()) =>
+            {
+                Ok(())
+            }
+            _ => Err(error::ErrorUnauthorized("Invalid Service-Key header")),
+        }
+    }
+}
+
+impl FromRequest for ServiceKey {
+    type Future = Ready<Result<Self, Self::Error>>;
+    type Error = error::Error;
+
+    fn from_request(req: &actix_web::HttpRequest, _: &mut actix_web::dev::Payload) -> Self::Future {
+        let Some(key) = req
+            .headers()
+            .get("Service-Key")
+            .and_then(|h| h.to_str().ok())
+        else {
+            return ready(Err(error::ErrorUnauthorized("Missing Service-Key header")));
+        };
+
+        ready(Ok(ServiceKey(key.into())))
+    }
+}
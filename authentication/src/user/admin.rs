@@ -0,0 +1,65 @@
+use crate::admin::AdminKey;
+use crate::auth::identity::IdentityConfig;
+use crate::auth::refresh::session::RefreshSession;
+use crate::auth::revocation::{self, RevocationKind};
+use crate::db::DbPool;
+use crate::user::merge::{merge_response, MergeInitiator};
+use actix_web::{error, post, web, HttpResponse};
+use serde::Deserialize;
+use uuid::Uuid;
+
+pub fn config_service(cfg: &mut web::ServiceConfig) {
+    cfg.service(sign_out).service(merge);
+}
+
+/// Signs a user out of every session and rejects their access tokens, e.g.
+/// after banning them.
+#[post("/{id}/sign-out/")]
+async fn sign_out(
+    _: AdminKey,
+    user_id: web::Path<Uuid>,
+    pool: web::Data<DbPool>,
+    identity_config: web::Data<IdentityConfig>,
+) -> actix_web::Result<HttpResponse> {
+    let mut conn = pool.get().await.map_err(error::ErrorInternalServerError)?;
+
+    RefreshSession::invalidate_all(&mut conn, &user_id)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+    revocation::revoke(&mut conn, &identity_config, RevocationKind::User, &user_id)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[derive(Deserialize)]
+struct MergeRequestBody {
+    merged_user_id: Uuid,
+}
+
+/// Merges another user into a user, e.g. when a player asks support to combine
+/// two accounts.
+#[post("/{id}/merge/")]
+async fn merge(
+    _: AdminKey,
+    user_id: web::Path<Uuid>,
+    body: web::Json<MergeRequestBody>,
+    pool: web::Data<DbPool>,
+    identity_config: web::Data<IdentityConfig>,
+) -> actix_web::Result<HttpResponse> {
+    if body.merged_user_id == *user_id {
+        return Err(error::ErrorBadRequest("Cannot merge a user into itself"));
+    }
+
+    let mut conn = pool.get().await.map_err(error::ErrorInternalServerError)?;
+
+    merge_response(
+        &mut conn,
+        &identity_config,
+        *user_id,
+        body.merged_user_id,
+        MergeInitiator::Admin,
+    )
+    .await
+}
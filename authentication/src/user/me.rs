@@ -1,10 +1,37 @@
 use crate::auth::identity::Identity;
+use crate::config::DISPLAY_NAME_CONFIG;
 use crate::db::DbPool;
 use crate::schema;
+use crate::user::name::{set_name, validate_name};
 use crate::user::{User, UserWithAuthProviders};
-use actix_web::{error, get, web, HttpResponse};
+use actix_web::{error, get, patch, web, HttpResponse};
 use diesel::prelude::*;
 use diesel_async::RunQueryDsl;
+use serde::{Deserialize, Serialize};
+
+/// A user as shown on their profile.
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(test, derive(Deserialize, PartialEq))]
+struct Profile {
+    #[serde(flatten)]
+    user: UserWithAuthProviders,
+    /// The name that the user set, or the display name of their first
+    /// provider until they set one.
+    display_name: Option<String>,
+}
+
+impl From<UserWithAuthProviders> for Profile {
+    fn from(user: UserWithAuthProviders) -> Self {
+        let display_name = user
+            .user
+            .tagged_name()
+            .or_else(|| match user.providers.as_slice() {
+                [first_provider, ..] => first_provider.display_name.clone(),
+                [] => None,
+            });
+        Profile { user, display_name }
+    }
+}
 
 #[get("/me/")]
 async fn me(pool: web::Data<DbPool>, identity: Identity) -> actix_web::Result<HttpResponse> {
@@ -28,15 +55,61 @@ async fn me(pool: web::Data<DbPool>, identity: Identity) -> actix_web::Result<Ht
         .await
         .map_err(error::ErrorInternalServerError)?;
 
-    Ok(HttpResponse::Ok().json(UserWithAuthProviders { user, providers }))
+    Ok(HttpResponse::Ok().json(Profile::from(UserWithAuthProviders { user, providers })))
+}
+
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(Serialize))]
+struct UpdateMeBody {
+    /// The new name of the caller, or `null` to show the name of their first
+    /// provider instead.
+    name: Option<String>,
+}
+
+/// Sets the caller's name.
+#[patch("/me/")]
+async fn update_me(
+    pool: web::Data<DbPool>,
+    identity: Identity,
+    body: web::Json<UpdateMeBody>,
+) -> actix_web::Result<HttpResponse> {
+    let name = body
+        .into_inner()
+        .name
+        .map(|name| validate_name(&name, &DISPLAY_NAME_CONFIG))
+        .transpose()
+        .map_err(error::ErrorBadRequest)?;
+
+    let mut conn = pool.get().await.map_err(error::ErrorInternalServerError)?;
+
+    let Some(user) = set_name(
+        &mut conn,
+        &identity.user_id,
+        name.as_deref(),
+        &DISPLAY_NAME_CONFIG.uniqueness,
+    )
+    .await
+    .map_err(error::ErrorInternalServerError)?
+    else {
+        return Err(error::ErrorConflict("This name is taken"));
+    };
+
+    let providers = user
+        .get_providers(&mut conn)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(Profile::from(UserWithAuthProviders { user, providers })))
 }
 
 #[cfg(test)]
 mod tests {
     use crate::auth::oauth2::google_user_info_api::GoogleUserInfo;
-    use crate::auth::provider::{AuthProvider, IntoAuthProviderInsert};
+    use crate::auth::provider::{
+        AuthProvider, AuthProviderInsert, AuthProviderType, IntoAuthProviderInsert,
+    };
     use crate::{config, db};
-    use actix_web::{http::header::AUTHORIZATION, test, web, App};
+    use actix_web::{http::header::AUTHORIZATION, http::StatusCode, test, web, App};
     use uuid::Uuid;
 
     use super::*;
@@ -59,6 +132,7 @@ mod tests {
         let user = User {
             id: Uuid::new_v4(),
             name: Some("Adam".to_string()),
+            discriminator: None,
         };
 
         let main_provider = USER_INFO.into_provider_insert(&user).into_row();
@@ -146,6 +220,7 @@ mod tests {
         let user = User {
             id: Uuid::new_v4(),
             name: Some("Adam".to_string()),
+            discriminator: None,
         };
 
         let main_provider = USER_INFO.into_provider_insert(&user).into_row();
@@ -207,4 +282,84 @@ mod tests {
             }
         );
     }
+
+    #[actix_web::test]
+    async fn users_can_set_their_name() {
+        let user = User {
+            id: Uuid::new_v4(),
+            name: None,
+            discriminator: None,
+        };
+        let provider = AuthProviderInsert {
+            provider_type: AuthProviderType::Steam,
+            provider_id: Uuid::new_v4().to_string(),
+            display_name: Some("Adam".to_string()),
+            ..Default::default()
+        }
+        .into_provider_insert(&user)
+        .into_row();
+
+        let pool = db::initialize_db_pool(&config::DB_URL).await;
+        {
+            let mut conn = pool
+                .get()
+                .await
+                .expect("Failed to get a database connection");
+            diesel::insert_into(schema::user::table)
+                .values(&user)
+                .execute(&mut conn)
+                .await
+                .expect("Failed to insert user");
+            diesel::insert_into(schema::auth_provider::table)
+                .values(&provider)
+                .execute(&mut conn)
+                .await
+                .expect("Failed to insert provider");
+        }
+
+        let identity_config = web::Data::new(config::IDENTITY_CONFIG.clone());
+        let token = Identity::from_user(&user)
+            .generate_token(&identity_config)
+            .value;
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool))
+                .app_data(identity_config)
+                .service(me)
+                .service(update_me),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .insert_header((AUTHORIZATION, format!("Bearer {token}")))
+            .uri("/me/")
+            .to_request();
+        let profile: Profile = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(profile.display_name, Some("Adam".to_string()));
+
+        let update = |name: &str| {
+            test::TestRequest::patch()
+                .insert_header((AUTHORIZATION, format!("Bearer {token}")))
+                .uri("/me/")
+                .set_json(UpdateMeBody {
+                    name: Some(name.to_string()),
+                })
+                .to_request()
+        };
+
+        let resp = test::call_service(&app, update("<Adam>")).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let profile: Profile = test::call_and_read_body_json(&app, update(" Bryan ")).await;
+        assert_eq!(profile.user.user.name, Some("Bryan".to_string()));
+        let discriminator = profile
+            .user
+            .user
+            .discriminator
+            .expect("Expected a discriminator");
+        assert_eq!(
+            profile.display_name,
+            Some(format!("Bryan#{discriminator:04}"))
+        );
+    }
 }
@@ -0,0 +1,395 @@
+use crate::auth::identity::{Identity, IdentityConfig};
+use crate::auth::link::next_provider_order;
+use crate::auth::refresh::session::RefreshSession;
+use crate::auth::revocation::{self, RevocationKind};
+use crate::db::{DbConnection, DbError, DbPool};
+use crate::user::{User, UserWithAuthProviders};
+use crate::{diesel_insertable, schema};
+use actix_web::{error, post, web, HttpResponse};
+use chrono::{DateTime, Utc};
+use diesel::{
+    deserialize::{FromSql, FromSqlRow},
+    expression::AsExpression,
+    pg::Pg,
+    prelude::*,
+    serialize::ToSql,
+    sql_types,
+};
+use diesel_async::scoped_futures::ScopedFutureExt;
+use diesel_async::{AsyncConnection, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+diesel_insertable! {
+    #[derive(Queryable, Selectable, Insertable)]
+    /// A record of one user being merged into another.
+    #[diesel(table_name = schema::user_merge)]
+    #[diesel(check_for_backend(Pg))]
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct UserMerge {
+        pub surviving_user_id: Uuid,
+        pub merged_user_id: Uuid,
+        pub moved_provider_ids: Vec<Uuid>,
+        pub initiated_by: MergeInitiator,
+        pub merged_at: DateTime<Utc>,
+    }
+}
+
+/// Who asked for a merge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[diesel(sql_type = sql_types::Text)]
+#[serde(rename_all = "snake_case")]
+pub enum MergeInitiator {
+    /// The owner of both users.
+    User,
+    /// An operator, through the admin routes.
+    Admin,
+}
+
+impl FromSql<sql_types::Text, Pg> for MergeInitiator {
+    fn from_sql(
+        bytes: <Pg as diesel::backend::Backend>::RawValue<'_>,
+    ) -> diesel::deserialize::Result<Self> {
+        match String::from_sql(bytes)?.as_ref() {
+            "user" => Ok(MergeInitiator::User),
+            "admin" => Ok(MergeInitiator::Admin),
+            _ => Err("Unknown `MergeInitiator` received".into()),
+        }
+    }
+}
+
+impl ToSql<sql_types::Text, Pg> for MergeInitiator {
+    fn to_sql<'b>(
+        &'b self,
+        out: &mut diesel::serialize::Output<'b, '_, Pg>,
+    ) -> diesel::serialize::Result {
+        <str as ToSql<sql_types::Text, Pg>>::to_sql(
+            match self {
+                MergeInitiator::User => "user",
+                MergeInitiator::Admin => "admin",
+            },
+            out,
+        )
+    }
+}
+
+/// Merges one user into another.
+///
+/// The providers of the merged user are moved after the providers of the
+/// surviving user, the merged user's sessions are invalidated and the merged
+/// user is deleted. Returns `None` if either user does not exist.
+pub async fn merge_users(
+    conn: &mut DbConnection,
+    identity_config: &IdentityConfig,
+    surviving_user_id: Uuid,
+    merged_user_id: Uuid,
+    initiated_by: MergeInitiator,
+) -> Result<Option<UserMerge>, DbError> {
+    let user_merge = conn
+        .transaction::<_, DbError, _>(|conn| {
+            async move {
+                let users: Vec<User> = schema::user::table
+                    .filter(schema::user::id.eq_any([surviving_user_id, merged_user_id]))
+                    .for_update()
+                    .get_results(conn)
+                    .await?;
+                if users.len() != 2 {
+                    return Ok(None);
+                }
+
+                let first_order = next_provider_order(conn, &surviving_user_id).await?;
+                let moved_provider_ids: Vec<Uuid> = diesel::update(schema::auth_provider::table)
+                    .filter(schema::auth_provider::user_id.eq(merged_user_id))
+                    .set((
+                        schema::auth_provider::user_id.eq(surviving_user_id),
+                        schema::auth_provider::order.eq(schema::auth_provider::order + first_order),
+                    ))
+                    .returning(schema::auth_provider::id)
+                    .get_results(conn)
+                    .await?;
+
+                RefreshSession::invalidate_all(conn, &merged_user_id).await?;
+                // Keep the security history of the merged user.
+                diesel::update(schema::auth_event::table)
+                    .filter(schema::auth_event::user_id.eq(merged_user_id))
+                    .set(schema::auth_event::user_id.eq(surviving_user_id))
+                    .execute(conn)
+                    .await?;
+
+                diesel::delete(schema::user::table.find(merged_user_id))
+                    .execute(conn)
+                    .await?;
+
+                diesel::insert_into(schema::user_merge::table)
+                    .values(UserMergeInsert {
+                        surviving_user_id,
+                        merged_user_id,
+                        moved_provider_ids,
+                        initiated_by,
+                        merged_at: Utc::now(),
+                    })
+                    .get_result(conn)
+                    .await
+                    .map(Some)
+            }
+            .scope_boxed()
+        })
+        .await?;
+
+    if user_merge.is_some() {
+        revocation::revoke(conn, identity_config, RevocationKind::User, &merged_user_id).await?;
+    }
+    Ok(user_merge)
+}
+
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(Serialize))]
+struct MergeRequestBody {
+    /// An access token of the user to merge into the caller.
+    access_token: String,
+}
+
+/// Merges another user into the caller.
+///
+/// The caller proves that they own both users with an access token of each.
+#[post("/me/merge/")]
+async fn merge(
+    body: web::Json<MergeRequestBody>,
+    identity: Identity,
+    pool: web::Data<DbPool>,
+    identity_config: web::Data<IdentityConfig>,
+) -> actix_web::Result<HttpResponse> {
+    let merged = Identity::from_token(&identity_config, &body.access_token)?;
+    if merged.user_id == identity.user_id {
+        return Err(error::ErrorBadRequest("Cannot merge a user into itself"));
+    }
+
+    let mut conn = pool.get().await.map_err(error::ErrorInternalServerError)?;
+
+    merge_response(
+        &mut conn,
+        &identity_config,
+        identity.user_id,
+        merged.user_id,
+        MergeInitiator::User,
+    )
+    .await
+}
+
+/// Merges one user into another and responds with the surviving user.
+pub async fn merge_response(
+    conn: &mut DbConnection,
+    identity_config: &IdentityConfig,
+    surviving_user_id: Uuid,
+    merged_user_id: Uuid,
+    initiated_by: MergeInitiator,
+) -> actix_web::Result<HttpResponse> {
+    let user_merge = merge_users(
+        conn,
+        identity_config,
+        surviving_user_id,
+        merged_user_id,
+        initiated_by,
+    )
+    .await
+    .map_err(error::ErrorInternalServerError)?;
+    if user_merge.is_none() {
+        return Ok(HttpResponse::NotFound().body("No user found to merge"));
+    }
+
+    let user: User = schema::user::table
+        .find(surviving_user_id)
+        .first(conn)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+    let providers = user
+        .get_providers(conn)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().json(UserWithAuthProviders { user, providers }))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::device::DeviceInfo;
+    use crate::auth::provider::{AuthProvider, AuthProviderInsert, AuthProviderType};
+    use crate::auth::refresh::session::RefreshResult;
+    use crate::{config, db};
+    use actix_web::{http::header::AUTHORIZATION, http::StatusCode, test, App};
+
+    use super::*;
+
+    async fn insert_user(
+        conn: &mut DbConnection,
+        provider_type: AuthProviderType,
+        provider_count: usize,
+    ) -> User {
+        let user = User {
+            id: Uuid::new_v4(),
+            name: None,
+            discriminator: None,
+        };
+        diesel::insert_into(schema::user::table)
+            .values(&user)
+            .execute(conn)
+            .await
+            .expect("Failed to insert user");
+
+        let providers: Vec<AuthProviderInsert> = (0..provider_count)
+            .map(|order| AuthProviderInsert {
+                user_id: user.id,
+                order: order as i16,
+                provider_type: provider_type.clone(),
+                provider_id: Uuid::new_v4().to_string(),
+                ..AuthProviderInsert::default()
+            })
+            .collect();
+        diesel::insert_into(schema::auth_provider::table)
+            .values(&providers)
+            .execute(conn)
+            .await
+            .expect("Failed to insert providers");
+        user
+    }
+
+    #[actix_web::test]
+    async fn merge_moves_providers_and_removes_the_merged_user() {
+        let identity_config = IdentityConfig {
+            revocations: Default::default(),
+            ..config::IDENTITY_CONFIG.clone()
+        };
+        let pool = db::initialize_db_pool(&config::DB_URL).await;
+        let mut conn = pool
+            .get()
+            .await
+            .expect("Failed to get a database connection");
+
+        let surviving_user = insert_user(&mut conn, AuthProviderType::Steam, 1).await;
+        let merged_user = insert_user(&mut conn, AuthProviderType::GooglePlayGames, 2).await;
+        let merged_session = RefreshSession::create(
+            &mut conn,
+            &identity_config,
+            &merged_user.id,
+            &DeviceInfo::default(),
+        )
+        .await
+        .expect("Failed to create session");
+        let merged_token = Identity::from_user(&merged_user)
+            .generate_token(&identity_config)
+            .value;
+
+        let user_merge = merge_users(
+            &mut conn,
+            &identity_config,
+            surviving_user.id,
+            merged_user.id,
+            MergeInitiator::Admin,
+        )
+        .await
+        .expect("Failed to merge users")
+        .expect("Both users should exist");
+        assert_eq!(user_merge.moved_provider_ids.len(), 2);
+
+        let providers = surviving_user
+            .get_providers(&mut conn)
+            .await
+            .expect("Failed to get providers");
+        assert_eq!(
+            providers
+                .iter()
+                .map(|p| (p.order, p.provider_type.clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                (0, AuthProviderType::Steam),
+                (1, AuthProviderType::GooglePlayGames),
+                (2, AuthProviderType::GooglePlayGames),
+            ]
+        );
+
+        let merged_user_exists: Option<User> = schema::user::table
+            .find(merged_user.id)
+            .first(&mut conn)
+            .await
+            .optional()
+            .expect("Failed to find user");
+        assert!(merged_user_exists.is_none());
+
+        let refresh_result = RefreshSession::refresh(
+            &mut conn,
+            &identity_config,
+            &merged_session.generate_token(&identity_config).value,
+            &DeviceInfo::default(),
+        )
+        .await
+        .expect("Failed to refresh session");
+        assert_eq!(refresh_result, RefreshResult::SessionNotFound);
+        assert!(Identity::from_token(&identity_config, &merged_token).is_err());
+
+        let audit: Vec<UserMerge> = schema::user_merge::table
+            .filter(schema::user_merge::merged_user_id.eq(merged_user.id))
+            .get_results(&mut conn)
+            .await
+            .expect("Failed to get merges");
+        assert_eq!(audit, vec![user_merge]);
+    }
+
+    #[actix_web::test]
+    async fn merge_requires_an_access_token_of_both_users() {
+        let pool = db::initialize_db_pool(&config::DB_URL).await;
+        let (user, other_user) = {
+            let mut conn = pool
+                .get()
+                .await
+                .expect("Failed to get a database connection");
+            (
+                insert_user(&mut conn, AuthProviderType::Steam, 1).await,
+                insert_user(&mut conn, AuthProviderType::GooglePlayGames, 1).await,
+            )
+        };
+        let identity_config = web::Data::new(IdentityConfig {
+            revocations: Default::default(),
+            ..config::IDENTITY_CONFIG.clone()
+        });
+        let token = Identity::from_user(&user)
+            .generate_token(&identity_config)
+            .value;
+        let other_token = Identity::from_user(&other_user)
+            .generate_token(&identity_config)
+            .value;
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool))
+                .app_data(identity_config)
+                .service(merge),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .insert_header((AUTHORIZATION, format!("Bearer {token}")))
+            .uri("/me/merge/")
+            .set_json(MergeRequestBody {
+                access_token: "not-a-token".to_string(),
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let req = test::TestRequest::post()
+            .insert_header((AUTHORIZATION, format!("Bearer {token}")))
+            .uri("/me/merge/")
+            .set_json(MergeRequestBody {
+                access_token: other_token,
+            })
+            .to_request();
+        let body: UserWithAuthProviders = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body.user.id, user.id);
+        assert_eq!(
+            body.providers
+                .iter()
+                .map(|p: &AuthProvider| p.provider_type.clone())
+                .collect::<Vec<_>>(),
+            vec![AuthProviderType::Steam, AuthProviderType::GooglePlayGames]
+        );
+    }
+}
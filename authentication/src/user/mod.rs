@@ -1,4 +1,9 @@
+pub mod admin;
 mod me;
+pub mod merge;
+pub mod name;
+mod providers;
+mod security_events;
 
 use crate::db::DbError;
 use crate::{auth::provider::AuthProvider, db::DbConnection};
@@ -16,20 +21,39 @@ diesel_insertable! {
     #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
     pub struct User {
         pub name: Option<String>,
+        pub discriminator: Option<i16>,
     }
 }
 
 pub fn config_service(cfg: &mut web::ServiceConfig) {
-    cfg.service(me::me);
+    cfg.service(me::me)
+        .service(me::update_me)
+        .service(providers::unlink)
+        .service(providers::reorder)
+        .service(merge::merge)
+        .service(security_events::list);
 }
 
 impl Default for UserInsert {
     fn default() -> Self {
-        UserInsert { name: None }
+        UserInsert {
+            name: None,
+            discriminator: None,
+        }
     }
 }
 
 impl User {
+    /// The name that the user set, with their discriminator if they have one,
+    /// e.g. `Adam#0042`.
+    pub fn tagged_name(&self) -> Option<String> {
+        let name = self.name.as_ref()?;
+        Some(match self.discriminator {
+            Some(discriminator) => format!("{name}#{discriminator:04}"),
+            None => name.clone(),
+        })
+    }
+
     pub async fn get_providers(
         &self,
         conn: &mut DbConnection,
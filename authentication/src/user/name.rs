@@ -0,0 +1,281 @@
+use crate::config::{DisplayNameConfig, NameUniqueness};
+use crate::db::{DbConnection, DbError};
+use crate::schema;
+use crate::user::User;
+use diesel::prelude::*;
+use diesel::result::DatabaseErrorKind;
+use diesel::sql_types::{Nullable, Text};
+use diesel_async::RunQueryDsl;
+use std::collections::HashSet;
+use uuid::Uuid;
+
+/// Discriminators are numbered from 1 to this.
+const MAX_DISCRIMINATOR: i16 = 9999;
+
+/// How often a discriminator is chosen again after another user took it at
+/// the same time.
+const DISCRIMINATOR_ATTEMPTS: usize = 3;
+
+sql_function!(fn lower(x: Nullable<Text>) -> Nullable<Text>);
+
+/// Trims and collapses the whitespace of a requested name, and checks it
+/// against the length, character and banned word rules.
+pub fn validate_name(name: &str, config: &DisplayNameConfig) -> Result<String, String> {
+    let name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    let length = name.chars().count();
+    if length < config.min_length || length > config.max_length {
+        return Err(format!(
+            "Names must be {} to {} characters long",
+            config.min_length, config.max_length
+        ));
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_alphanumeric() || matches!(c, ' ' | '_' | '-' | '.'))
+    {
+        return Err("Names may only contain letters, digits, spaces, `_`, `-` and `.`".to_string());
+    }
+    if !name.chars().any(char::is_alphanumeric) {
+        return Err("Names must contain a letter or digit".to_string());
+    }
+    if contains_banned_word(&name, &config.banned_words) {
+        return Err("This name is not allowed".to_string());
+    }
+
+    Ok(name)
+}
+
+/// Lowercases letters, reads common digit and symbol substitutions as letters,
+/// and drops everything else, so that `B.a-d` and `b4d` both read as `bad`.
+fn skeleton(text: &str) -> String {
+    text.chars()
+        .flat_map(char::to_lowercase)
+        .filter_map(|c| match c {
+            '0' => Some('o'),
+            '1' | '!' => Some('i'),
+            '3' => Some('e'),
+            '4' | '@' => Some('a'),
+            '5' | '$' => Some('s'),
+            '7' => Some('t'),
+            c if c.is_alphanumeric() => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+fn contains_banned_word(name: &str, banned_words: &[String]) -> bool {
+    let name = skeleton(name);
+    banned_words
+        .iter()
+        .map(|word| skeleton(word))
+        .any(|word| !word.is_empty() && name.contains(&word))
+}
+
+/// Picks a random discriminator that is not in `used`.
+fn random_discriminator(used: &HashSet<i16>) -> Option<i16> {
+    let mut bytes = [0; 2];
+    openssl::rand::rand_bytes(&mut bytes).expect("Failed to generate random bytes");
+    let start = (u16::from_le_bytes(bytes) % MAX_DISCRIMINATOR as u16) as i16;
+    (0..MAX_DISCRIMINATOR)
+        .map(|offset| (start + offset) % MAX_DISCRIMINATOR + 1)
+        .find(|discriminator| !used.contains(discriminator))
+}
+
+/// Sets the name of a user, or clears it so that their profile falls back to
+/// their first provider. Returns `None` if the name is taken.
+///
+/// A user that keeps their name with a different case keeps their
+/// discriminator.
+pub async fn set_name(
+    conn: &mut DbConnection,
+    user_id: &Uuid,
+    name: Option<&str>,
+    uniqueness: &NameUniqueness,
+) -> Result<Option<User>, DbError> {
+    let Some(name) = name else {
+        return diesel::update(schema::user::table.find(user_id))
+            .set((
+                schema::user::name.eq(None::<String>),
+                schema::user::discriminator.eq(None::<i16>),
+            ))
+            .get_result(conn)
+            .await
+            .map(Some);
+    };
+
+    for _ in 0..DISCRIMINATOR_ATTEMPTS {
+        let discriminator = match uniqueness {
+            NameUniqueness::Unique => None,
+            NameUniqueness::Discriminator => {
+                let user: User = schema::user::table.find(user_id).first(conn).await?;
+                let same_name = user
+                    .name
+                    .as_ref()
+                    .is_some_and(|current| current.to_lowercase() == name.to_lowercase());
+                match user.discriminator {
+                    Some(discriminator) if same_name => Some(discriminator),
+                    _ => {
+                        let used: HashSet<i16> = schema::user::table
+                            .filter(lower(schema::user::name).eq(name.to_lowercase()))
+                            .filter(schema::user::id.ne(user_id))
+                            .select(schema::user::discriminator)
+                            .load::<Option<i16>>(conn)
+                            .await?
+                            .into_iter()
+                            .flatten()
+                            .collect();
+                        let Some(discriminator) = random_discriminator(&used) else {
+                            return Ok(None);
+                        };
+                        Some(discriminator)
+                    }
+                }
+            }
+        };
+
+        let updated = diesel::update(schema::user::table.find(user_id))
+            .set((
+                schema::user::name.eq(name),
+                schema::user::discriminator.eq(discriminator),
+            ))
+            .get_result(conn)
+            .await;
+        match updated {
+            Ok(user) => return Ok(Some(user)),
+            Err(DbError::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
+                if discriminator.is_none() {
+                    return Ok(None);
+                }
+            }
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{config, db};
+
+    use super::*;
+
+    fn name_config(banned_words: &[&str]) -> DisplayNameConfig {
+        DisplayNameConfig {
+            uniqueness: NameUniqueness::Discriminator,
+            min_length: 3,
+            max_length: 16,
+            banned_words: banned_words.iter().map(|word| word.to_string()).collect(),
+        }
+    }
+
+    #[actix_web::test]
+    async fn names_are_checked_against_the_rules() {
+        let config = name_config(&["darn"]);
+
+        assert_eq!(
+            validate_name("  Adam   the  Great ", &config),
+            Ok("Adam the Great".to_string())
+        );
+        assert_eq!(validate_name("Zoë_99", &config), Ok("Zoë_99".to_string()));
+
+        for name in [
+            "Al",
+            "Adam the Greatest Ever",
+            "Adam#1234",
+            "<script>",
+            "Ad\u{200b}am",
+            "._-",
+            "Darn",
+            "d.a.r.n",
+            "D4rn1t",
+        ] {
+            assert!(validate_name(name, &config).is_err(), "{name}");
+        }
+    }
+
+    async fn insert_user(conn: &mut DbConnection) -> Uuid {
+        let user: User = diesel::insert_into(schema::user::table)
+            .values(crate::user::UserInsert::default())
+            .get_result(conn)
+            .await
+            .unwrap();
+        user.id
+    }
+
+    #[actix_web::test]
+    async fn users_with_the_same_name_get_different_discriminators() {
+        let pool = db::initialize_db_pool(&config::DB_URL).await;
+        let mut conn = pool.get().await.unwrap();
+        let name = format!("Adam {}", &Uuid::new_v4().simple().to_string()[..8]);
+
+        let adam = insert_user(&mut conn).await;
+        let other_adam = insert_user(&mut conn).await;
+
+        let first = set_name(
+            &mut conn,
+            &adam,
+            Some(&name),
+            &NameUniqueness::Discriminator,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        let second = set_name(
+            &mut conn,
+            &other_adam,
+            Some(&name.to_uppercase()),
+            &NameUniqueness::Discriminator,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert!(first.discriminator.is_some());
+        assert!(second.discriminator.is_some());
+        assert_ne!(first.discriminator, second.discriminator);
+
+        let renamed = set_name(
+            &mut conn,
+            &adam,
+            Some(&name.to_lowercase()),
+            &NameUniqueness::Discriminator,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(renamed.discriminator, first.discriminator);
+
+        let cleared = set_name(&mut conn, &adam, None, &NameUniqueness::Discriminator)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!((cleared.name, cleared.discriminator), (None, None));
+    }
+
+    #[actix_web::test]
+    async fn unique_names_cannot_be_taken_twice() {
+        let pool = db::initialize_db_pool(&config::DB_URL).await;
+        let mut conn = pool.get().await.unwrap();
+        let name = format!("Eve {}", &Uuid::new_v4().simple().to_string()[..8]);
+
+        let eve = insert_user(&mut conn).await;
+        let other_eve = insert_user(&mut conn).await;
+
+        let taken = set_name(&mut conn, &eve, Some(&name), &NameUniqueness::Unique)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(taken.discriminator, None);
+
+        let rejected = set_name(
+            &mut conn,
+            &other_eve,
+            Some(&name.to_lowercase()),
+            &NameUniqueness::Unique,
+        )
+        .await
+        .unwrap();
+        assert_eq!(rejected, None);
+    }
+}
@@ -0,0 +1,311 @@
+use crate::auth::identity::Identity;
+use crate::auth::provider::AuthProvider;
+use crate::db::{DbConnection, DbError, DbPool};
+use crate::schema;
+use crate::user::{User, UserWithAuthProviders};
+use actix_web::{delete, error, patch, web, HttpResponse};
+use diesel::prelude::*;
+use diesel_async::scoped_futures::ScopedFutureExt;
+use diesel_async::{AsyncConnection, RunQueryDsl};
+use serde::Deserialize;
+use std::collections::HashSet;
+use uuid::Uuid;
+
+enum ProviderUpdate {
+    Updated,
+    NotFound,
+    Rejected(&'static str),
+}
+
+/// Locks the providers of a user for the rest of the transaction, in their
+/// current order.
+async fn lock_providers(
+    conn: &mut DbConnection,
+    user_id: &Uuid,
+) -> Result<Vec<AuthProvider>, DbError> {
+    schema::auth_provider::table
+        .filter(schema::auth_provider::user_id.eq(user_id))
+        .order(schema::auth_provider::order.asc())
+        .for_update()
+        .get_results(conn)
+        .await
+}
+
+/// Gives the providers the orders `0..n` in the given sequence.
+async fn set_orders(conn: &mut DbConnection, provider_ids: &[Uuid]) -> Result<(), DbError> {
+    for (order, provider_id) in provider_ids.iter().enumerate() {
+        diesel::update(schema::auth_provider::table.find(provider_id))
+            .set(schema::auth_provider::order.eq(order as i16))
+            .execute(conn)
+            .await?;
+    }
+    Ok(())
+}
+
+async fn get_user_with_providers(
+    conn: &mut DbConnection,
+    user_id: &Uuid,
+) -> actix_web::Result<UserWithAuthProviders> {
+    let user: User = schema::user::table
+        .find(user_id)
+        .first(conn)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+    let providers = user
+        .get_providers(conn)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+    Ok(UserWithAuthProviders { user, providers })
+}
+
+/// Unlinks one of the caller's providers. The last provider cannot be unlinked,
+/// since the user could no longer sign in.
+#[delete("/me/providers/{id}/")]
+async fn unlink(
+    pool: web::Data<DbPool>,
+    identity: Identity,
+    provider_id: web::Path<Uuid>,
+) -> actix_web::Result<HttpResponse> {
+    let provider_id = provider_id.into_inner();
+    let user_id = identity.user_id;
+
+    let mut conn = pool.get().await.map_err(error::ErrorInternalServerError)?;
+
+    let update = conn
+        .transaction::<_, DbError, _>(|conn| {
+            async move {
+                let providers = lock_providers(conn, &user_id).await?;
+                if !providers.iter().any(|p| p.id == provider_id) {
+                    return Ok(ProviderUpdate::NotFound);
+                }
+                if providers.len() == 1 {
+                    return Ok(ProviderUpdate::Rejected(
+                        "Cannot unlink the last provider of a user",
+                    ));
+                }
+
+                diesel::delete(schema::auth_provider::table.find(provider_id))
+                    .execute(conn)
+                    .await?;
+
+                let remaining: Vec<Uuid> = providers
+                    .iter()
+                    .map(|p| p.id)
+                    .filter(|id| *id != provider_id)
+                    .collect();
+                set_orders(conn, &remaining).await?;
+                Ok(ProviderUpdate::Updated)
+            }
+            .scope_boxed()
+        })
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    match update {
+        ProviderUpdate::Updated => {
+            Ok(HttpResponse::Ok().json(get_user_with_providers(&mut conn, &user_id).await?))
+        }
+        ProviderUpdate::NotFound => {
+            Ok(HttpResponse::NotFound().body(format!("No provider found with id {provider_id}")))
+        }
+        ProviderUpdate::Rejected(reason) => Err(error::ErrorConflict(reason)),
+    }
+}
+
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(serde::Serialize))]
+struct ReorderRequestBody {
+    /// Every provider of the caller, in their new order.
+    provider_ids: Vec<Uuid>,
+}
+
+/// Reorders the caller's providers. The first provider decides the display
+/// name and picture shown on the user's profile.
+#[patch("/me/providers/order/")]
+async fn reorder(
+    pool: web::Data<DbPool>,
+    identity: Identity,
+    body: web::Json<ReorderRequestBody>,
+) -> actix_web::Result<HttpResponse> {
+    let provider_ids = body.into_inner().provider_ids;
+    let user_id = identity.user_id;
+
+    let mut conn = pool.get().await.map_err(error::ErrorInternalServerError)?;
+
+    let update = conn
+        .transaction::<_, DbError, _>(|conn| {
+            async move {
+                let providers = lock_providers(conn, &user_id).await?;
+                let existing: HashSet<Uuid> = providers.iter().map(|p| p.id).collect();
+                let requested: HashSet<Uuid> = provider_ids.iter().copied().collect();
+                if requested.len() != provider_ids.len() || requested != existing {
+                    return Ok(ProviderUpdate::Rejected(
+                        "The order must list every provider of the user exactly once",
+                    ));
+                }
+
+                set_orders(conn, &provider_ids).await?;
+                Ok(ProviderUpdate::Updated)
+            }
+            .scope_boxed()
+        })
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    match update {
+        ProviderUpdate::Updated => {
+            Ok(HttpResponse::Ok().json(get_user_with_providers(&mut conn, &user_id).await?))
+        }
+        ProviderUpdate::NotFound => Ok(HttpResponse::NotFound().finish()),
+        ProviderUpdate::Rejected(reason) => Err(error::ErrorBadRequest(reason)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::provider::AuthProviderInsert;
+    use crate::{config, db};
+    use actix_web::{http::header::AUTHORIZATION, http::StatusCode, test, App};
+
+    use super::*;
+
+    async fn insert_user_with_providers(pool: &DbPool, count: usize) -> (User, Vec<AuthProvider>) {
+        let user = User {
+            id: Uuid::new_v4(),
+            name: None,
+            discriminator: None,
+        };
+
+        let mut conn = pool
+            .get()
+            .await
+            .expect("Failed to get a database connection");
+
+        diesel::insert_into(schema::user::table)
+            .values(&user)
+            .execute(&mut conn)
+            .await
+            .expect("Failed to insert user");
+
+        let providers: Vec<AuthProvider> = (0..count)
+            .map(|order| {
+                AuthProviderInsert {
+                    user_id: user.id,
+                    order: order as i16,
+                    provider_id: Uuid::new_v4().to_string(),
+                    ..AuthProviderInsert::default()
+                }
+                .into_row()
+            })
+            .collect();
+        diesel::insert_into(schema::auth_provider::table)
+            .values(&providers)
+            .execute(&mut conn)
+            .await
+            .expect("Failed to insert providers");
+
+        (user, providers)
+    }
+
+    #[actix_web::test]
+    async fn providers_can_be_unlinked_except_the_last() {
+        let pool = db::initialize_db_pool(&config::DB_URL).await;
+        let (user, providers) = insert_user_with_providers(&pool, 2).await;
+        let identity_config = web::Data::new(config::IDENTITY_CONFIG.clone());
+        let token = Identity::from_user(&user)
+            .generate_token(&identity_config)
+            .value;
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool))
+                .app_data(identity_config)
+                .service(unlink),
+        )
+        .await;
+
+        let req = test::TestRequest::delete()
+            .insert_header((AUTHORIZATION, format!("Bearer {token}")))
+            .uri(&format!("/me/providers/{}/", providers[0].id))
+            .to_request();
+        let body: UserWithAuthProviders = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body.providers.len(), 1);
+        assert_eq!(body.providers[0].id, providers[1].id);
+        assert_eq!(body.providers[0].order, 0);
+
+        let req = test::TestRequest::delete()
+            .insert_header((AUTHORIZATION, format!("Bearer {token}")))
+            .uri(&format!("/me/providers/{}/", providers[1].id))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+    }
+
+    #[actix_web::test]
+    async fn other_users_providers_cannot_be_unlinked() {
+        let pool = db::initialize_db_pool(&config::DB_URL).await;
+        let (user, _) = insert_user_with_providers(&pool, 2).await;
+        let (_, other_providers) = insert_user_with_providers(&pool, 2).await;
+        let identity_config = web::Data::new(config::IDENTITY_CONFIG.clone());
+        let token = Identity::from_user(&user)
+            .generate_token(&identity_config)
+            .value;
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool))
+                .app_data(identity_config)
+                .service(unlink),
+        )
+        .await;
+
+        let req = test::TestRequest::delete()
+            .insert_header((AUTHORIZATION, format!("Bearer {token}")))
+            .uri(&format!("/me/providers/{}/", other_providers[0].id))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn providers_can_be_reordered() {
+        let pool = db::initialize_db_pool(&config::DB_URL).await;
+        let (user, providers) = insert_user_with_providers(&pool, 3).await;
+        let identity_config = web::Data::new(config::IDENTITY_CONFIG.clone());
+        let token = Identity::from_user(&user)
+            .generate_token(&identity_config)
+            .value;
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool))
+                .app_data(identity_config)
+                .service(reorder),
+        )
+        .await;
+
+        let new_order = vec![providers[2].id, providers[0].id, providers[1].id];
+        let req = test::TestRequest::patch()
+            .insert_header((AUTHORIZATION, format!("Bearer {token}")))
+            .uri("/me/providers/order/")
+            .set_json(ReorderRequestBody {
+                provider_ids: new_order.clone(),
+            })
+            .to_request();
+        let body: UserWithAuthProviders = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(
+            body.providers.iter().map(|p| p.id).collect::<Vec<_>>(),
+            new_order
+        );
+
+        let req = test::TestRequest::patch()
+            .insert_header((AUTHORIZATION, format!("Bearer {token}")))
+            .uri("/me/providers/order/")
+            .set_json(ReorderRequestBody {
+                provider_ids: vec![providers[0].id, providers[0].id, providers[1].id],
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,129 @@
+use crate::auth::event::AuthEvent;
+use crate::auth::identity::Identity;
+use crate::db::DbPool;
+use crate::schema;
+use actix_web::{error, get, web, HttpResponse};
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+
+const RECENT_EVENT_COUNT: i64 = 50;
+
+/// Lists the caller's most recent authentication events, newest first.
+#[get("/me/security-events/")]
+async fn list(pool: web::Data<DbPool>, identity: Identity) -> actix_web::Result<HttpResponse> {
+    let mut conn = pool.get().await.map_err(error::ErrorInternalServerError)?;
+
+    let events: Vec<AuthEvent> = schema::auth_event::table
+        .filter(schema::auth_event::user_id.eq(&identity.user_id))
+        .order(schema::auth_event::created_at.desc())
+        .limit(RECENT_EVENT_COUNT)
+        .get_results(&mut conn)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(events))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::device::DeviceInfo;
+    use crate::auth::event::{AuthEventKind, AuthEventOutcome};
+    use crate::auth::guest::Guest;
+    use crate::auth::provider::AuthProviderType;
+    use crate::auth::refresh::session::{RefreshResult, RefreshSession};
+    use crate::auth::token::generate_secret;
+    use crate::auth::{create_new_user, generate_sign_in_success};
+    use crate::{config, db};
+    use actix_web::{http::header::AUTHORIZATION, test, App};
+
+    use super::*;
+
+    #[actix_web::test]
+    async fn users_see_their_sign_ins_and_refreshes() {
+        let pool = db::initialize_db_pool(&config::DB_URL).await;
+        let device = DeviceInfo {
+            ip: Some("10.0.0.1".to_string()),
+            user_agent: Some("Godot/4.2".to_string()),
+            ..DeviceInfo::default()
+        };
+        let (user_id, session_id) = {
+            let mut conn = pool.get().await.unwrap();
+            let guest = create_new_user(&mut conn, &Guest::from_secret(&generate_secret()))
+                .await
+                .unwrap();
+            let user_id = guest.user.id;
+            generate_sign_in_success(
+                &mut conn,
+                guest,
+                &AuthProviderType::Guest,
+                &config::IDENTITY_CONFIG,
+                &device,
+            )
+            .await
+            .unwrap();
+
+            let session: RefreshSession = schema::refresh_session::table
+                .filter(schema::refresh_session::user_id.eq(user_id))
+                .first(&mut conn)
+                .await
+                .unwrap();
+            let refresh_token = session.generate_token(&config::IDENTITY_CONFIG).value;
+            let refreshed = RefreshSession::refresh(
+                &mut conn,
+                &config::IDENTITY_CONFIG,
+                &refresh_token,
+                &device,
+            )
+            .await
+            .unwrap();
+            assert!(matches!(refreshed, RefreshResult::Success(_)));
+            let reused = RefreshSession::refresh(
+                &mut conn,
+                &config::IDENTITY_CONFIG,
+                &refresh_token,
+                &device,
+            )
+            .await
+            .unwrap();
+            assert_eq!(reused, RefreshResult::TokenAlreadyUsed);
+
+            (user_id, session.id)
+        };
+
+        let identity_config = web::Data::new(config::IDENTITY_CONFIG.clone());
+        let token = Identity::from_user_id(&user_id)
+            .generate_token(&identity_config)
+            .value;
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool))
+                .app_data(identity_config)
+                .service(list),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/me/security-events/")
+            .insert_header((AUTHORIZATION, format!("Bearer {token}")))
+            .to_request();
+        let events: Vec<AuthEvent> = test::call_and_read_body_json(&app, req).await;
+
+        let summary: Vec<_> = events
+            .iter()
+            .map(|event| (event.kind, event.outcome))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (AuthEventKind::Refresh, AuthEventOutcome::TokenAlreadyUsed),
+                (AuthEventKind::Refresh, AuthEventOutcome::Success),
+                (AuthEventKind::SignIn, AuthEventOutcome::Success),
+            ]
+        );
+        let sign_in = &events[2];
+        assert_eq!(sign_in.provider_type, Some(AuthProviderType::Guest));
+        assert_eq!(sign_in.session_id, Some(session_id));
+        assert_eq!(sign_in.ip.as_deref(), Some("10.0.0.1"));
+        assert_eq!(sign_in.user_agent.as_deref(), Some("Godot/4.2"));
+    }
+}
@@ -2,13 +2,22 @@ use crate::game::{Game, GameDescription, GamesData};
 use crate::ServiceKey;
 use actix_web::{error, post, web, HttpResponse};
 use chrono::{DateTime, Utc};
+use serde::Deserialize;
 use std::fs::File;
 use std::time::SystemTime;
 use subprocess::{Popen, PopenConfig, Redirection};
 
+#[derive(Debug, Default, Deserialize)]
+struct SpawnBody {
+    /// Extra arguments to launch the game server with, e.g. its game mode.
+    #[serde(default)]
+    args: Vec<String>,
+}
+
 #[post("/spawn/")]
 async fn spawn(
     service_key: ServiceKey,
+    body: Option<web::Json<SpawnBody>>,
     games_data: web::Data<GamesData>,
 ) -> actix_web::Result<HttpResponse> {
     service_key.validate()?;
@@ -41,16 +50,12 @@ async fn spawn(
         ..Default::default()
     };
 
-    let process = Popen::create(
-        &[
-            "/game-server/run",
-            "--server",
-            "--headless",
-            &format!("--port={game_port}"),
-        ],
-        config,
-    )
-    .map_err(error::ErrorInternalServerError)?;
+    let body = body.map(web::Json::into_inner).unwrap_or_default();
+    let port_arg = format!("--port={game_port}");
+    let mut argv = vec!["/game-server/run", "--server", "--headless", &port_arg];
+    argv.extend(body.args.iter().map(String::as_str));
+
+    let process = Popen::create(&argv, config).map_err(error::ErrorInternalServerError)?;
 
     let game = Game {
         process,
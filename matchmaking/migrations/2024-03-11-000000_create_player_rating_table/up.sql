@@ -0,0 +1,9 @@
+-- The Glicko-2 rating of a user, on the Glicko scale where new players start
+-- at 1500 with a deviation of 350.
+create table "player_rating" (
+  "user_id" uuid primary key not null,
+  "rating" double precision not null,
+  "deviation" double precision not null,
+  "volatility" double precision not null,
+  "updated_at" timestamptz not null default now()
+);
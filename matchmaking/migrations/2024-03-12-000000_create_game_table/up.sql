@@ -0,0 +1,9 @@
+-- A game that was started, so that its result is only rated once and only
+-- for its players.
+create table "game" (
+  "id" uuid primary key not null default gen_random_uuid(),
+  "mode" text not null,
+  "user_ids" uuid[] not null,
+  "created_at" timestamptz not null default now(),
+  "reported_at" timestamptz
+);
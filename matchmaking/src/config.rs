@@ -1,6 +1,8 @@
 use crate::identity::IdentityConfig;
 use actix_cors::Cors;
 use chrono::Duration;
+use serde::{Deserialize, Deserializer};
+use std::collections::HashMap;
 use std::env;
 
 fn get_secret_text_or_file(var: &str) -> Option<String> {
@@ -25,30 +27,35 @@ fn get_required_secret_text_or_file(var: &str) -> String {
     } else {
         use std::fs;
         let secret_file = env::var(format!("{var}_FILE"))
-            .expect(format!("Expected either {var} or {var}_FILE to be set").as_str());
+            .unwrap_or_else(|_| panic!("Expected either {var} or {var}_FILE to be set"));
         fs::read_to_string(secret_file)
-            .expect(format!("The file at {var}_FILE should contain the secret").as_str())
+            .unwrap_or_else(|_| panic!("The file at {var}_FILE should contain the secret"))
     }
 }
 
 fn get_identity_config() -> IdentityConfig {
-    let secret = get_required_secret_text_or_file("IDENTITY_SECRET");
-    let expires_in_seconds = get_secret_text_or_file("IDENTITY_EXPIRES_IN_SECS")
-        .and_then(|p| p.parse::<i64>().ok())
-        .unwrap_or(3600);
-    let expires_in = Duration::seconds(expires_in_seconds);
-
-    let refresh_secret = get_required_secret_text_or_file("REFRESH_SECRET");
-    let refresh_expires_in_days = get_secret_text_or_file("REFRESH_EXPIRES_IN_DAYS")
-        .and_then(|p| p.parse::<i64>().ok())
-        .unwrap_or(7);
-    let refresh_expires_in = Duration::days(refresh_expires_in_days);
+    let jwks_url = get_required_secret_text_or_file("IDENTITY_JWKS_URL");
+    let jwks_refresh_interval = Duration::seconds(
+        get_secret_text_or_file("IDENTITY_JWKS_REFRESH_SECS")
+            .and_then(|s| s.parse::<i64>().ok())
+            .unwrap_or(300),
+    );
+
+    let revocations_url = get_required_secret_text_or_file("IDENTITY_REVOCATIONS_URL");
+    let revocations_service_key =
+        get_required_secret_text_or_file("IDENTITY_REVOCATIONS_SERVICE_KEY");
+    let revocations_refresh_interval = Duration::seconds(
+        get_secret_text_or_file("IDENTITY_REVOCATIONS_REFRESH_SECS")
+            .and_then(|s| s.parse::<i64>().ok())
+            .unwrap_or(5),
+    );
 
     IdentityConfig {
-        secret,
-        expires_in,
-        refresh_secret,
-        refresh_expires_in,
+        jwks_url,
+        jwks_refresh_interval,
+        revocations_url,
+        revocations_service_key,
+        revocations_refresh_interval,
     }
 }
 
@@ -67,35 +74,111 @@ fn get_game_server_manager_config() -> GameServerManagerConfig {
     }
 }
 
+/// The settings of one queue, i.e. one game mode.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct QueueConfig {
+    pub min_size: u8,
+    pub desired_size: u8,
+    /// How long players wait for a game of the desired size before they are
+    /// matched into a game of at least the minimum size.
+    #[serde(
+        rename = "desired_max_wait_secs",
+        deserialize_with = "deserialize_seconds"
+    )]
+    pub desired_max_wait_time: Duration,
+    /// How far apart the ratings of players in a game may be at first, or
+    /// `None` to match players regardless of their rating.
+    pub rating_spread: Option<f64>,
+    /// How much the allowed rating spread grows per second that the player
+    /// who waited longest has waited.
+    pub rating_spread_per_sec: f64,
+    /// The number of teams that the players of a game are split into, or
+    /// `None` if every player plays for themselves.
+    pub team_count: Option<u8>,
+    /// The arguments that the game servers of this queue are launched with.
+    pub game_server_args: Vec<String>,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        QueueConfig {
+            min_size: 2,
+            desired_size: 4,
+            desired_max_wait_time: Duration::minutes(1),
+            rating_spread: None,
+            rating_spread_per_sec: 10.0,
+            team_count: None,
+            game_server_args: vec![],
+        }
+    }
+}
+
+impl QueueConfig {
+    /// The largest party that fits in a game, or on one team in modes with
+    /// teams.
+    pub fn max_party_size(&self) -> u8 {
+        match self.team_count {
+            Some(team_count) => self.desired_size / team_count,
+            None => self.desired_size,
+        }
+    }
+
+    /// Panics unless the queue of `mode` can form games of its sizes.
+    fn validate(&self, mode: &str) {
+        assert!(
+            0 < self.min_size && self.min_size <= self.desired_size,
+            "The {mode} queue should have a minimum size between 1 and its desired size"
+        );
+        if let Some(team_count) = self.team_count {
+            assert!(
+                team_count > 1
+                    && self.min_size.is_multiple_of(team_count)
+                    && self.desired_size.is_multiple_of(team_count),
+                "The {mode} queue should have at least 2 teams that its minimum and desired sizes split into"
+            );
+        }
+    }
+}
+
+fn deserialize_seconds<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    i64::deserialize(deserializer).map(Duration::seconds)
+}
+
 #[derive(Debug, Clone)]
 pub struct MatchmakingConfig {
-    pub solo_game_min_size: u8,
-    pub solo_game_desired_size: u8,
-    pub solo_queue_desired_max_wait_time: Duration,
+    /// The queues by the name of their game mode.
+    pub queues: HashMap<String, QueueConfig>,
+    /// How often the queues are checked for matches, so that players who
+    /// waited long enough are matched even if nobody else joins.
+    pub queue_tick_interval: Duration,
 }
 
 impl Default for MatchmakingConfig {
     fn default() -> Self {
         MatchmakingConfig {
-            solo_game_min_size: 2,
-            solo_game_desired_size: 4,
-            solo_queue_desired_max_wait_time: Duration::minutes(1),
+            queues: HashMap::from([("solo".to_string(), QueueConfig::default())]),
+            queue_tick_interval: Duration::seconds(1),
         }
     }
 }
 
 fn get_matchmaking_config() -> MatchmakingConfig {
+    let queues: HashMap<String, QueueConfig> = match get_secret_text_or_file("QUEUES") {
+        Some(queues) => serde_json::from_str(&queues)
+            .expect("QUEUES should be a JSON object of queue configs by game mode"),
+        None => MatchmakingConfig::default().queues,
+    };
+    for (mode, queue) in &queues {
+        queue.validate(mode);
+    }
+
     MatchmakingConfig {
-        solo_game_min_size: get_secret_text_or_file("SOLO_GAME_MIN_SIZE")
-            .and_then(|s| s.parse::<u8>().ok())
-            .unwrap_or(2),
-        solo_game_desired_size: get_secret_text_or_file("SOLO_GAME_DESIRED_SIZE")
-            .and_then(|s| s.parse::<u8>().ok())
-            .unwrap_or(4),
-        solo_queue_desired_max_wait_time: Duration::seconds(
-            get_secret_text_or_file("SOLO_QUEUE_DESIRED_MAX_WAIT_SECS")
+        queues,
+        queue_tick_interval: Duration::milliseconds(
+            get_secret_text_or_file("QUEUE_TICK_INTERVAL_MILLIS")
                 .and_then(|s| s.parse::<i64>().ok())
-                .unwrap_or(1),
+                .unwrap_or(1000),
         ),
     }
 }
@@ -105,6 +188,9 @@ lazy_static::lazy_static! {
     pub static ref IDENTITY_CONFIG: IdentityConfig = get_identity_config();
     pub static ref GAME_SERVER_MANAGER_CONFIG: GameServerManagerConfig = get_game_server_manager_config();
     pub static ref MATCHMAKING_CONFIG: MatchmakingConfig = get_matchmaking_config();
+    /// The key that game servers report results with. Results are rejected
+    /// unless a key is configured.
+    pub static ref GAME_RESULTS_SERVICE_KEY: Option<String> = get_secret_text_or_file("GAME_RESULTS_SERVICE_KEY");
 }
 
 pub fn get_cors_config() -> Cors {
@@ -118,3 +204,43 @@ pub fn get_cors_config() -> Cors {
         cors
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_accepts_sizes_that_split_into_teams() {
+        QueueConfig {
+            min_size: 4,
+            desired_size: 6,
+            team_count: Some(2),
+            ..QueueConfig::default()
+        }
+        .validate("duo");
+    }
+
+    #[test]
+    #[should_panic(expected = "The duo queue should have at least 2 teams")]
+    fn validate_rejects_desired_size_that_does_not_split_into_teams() {
+        QueueConfig {
+            min_size: 4,
+            desired_size: 5,
+            team_count: Some(2),
+            ..QueueConfig::default()
+        }
+        .validate("duo");
+    }
+
+    #[test]
+    #[should_panic(expected = "The duo queue should have at least 2 teams")]
+    fn validate_rejects_min_size_that_does_not_split_into_teams() {
+        QueueConfig {
+            min_size: 3,
+            desired_size: 6,
+            team_count: Some(2),
+            ..QueueConfig::default()
+        }
+        .validate("duo");
+    }
+}
@@ -4,6 +4,7 @@ use serde::{Deserialize, Serialize};
 
 use crate::config::GameServerManagerConfig;
 use std::time::Duration;
+use uuid::Uuid;
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct GameServerManagerDescription {
@@ -17,12 +18,26 @@ pub struct GameServerDescription {
     pub port: u16,
     pub host: String,
     pub created_at: DateTime<Utc>,
+    /// The game that the game server reports the result of.
+    pub game_id: Uuid,
+    /// The user ids of every team, or no teams in modes without teams.
+    pub teams: Vec<Vec<Uuid>>,
+}
+
+#[derive(Debug, Serialize)]
+struct SpawnRequest<'a> {
+    args: Vec<&'a str>,
 }
 
 #[async_trait::async_trait]
 pub trait GameServerManager: Sync {
+    /// Spawns a game server for `game_id` that is launched with the extra
+    /// `args`, and told the user ids of every team.
     async fn spawn_new_game_server(
         &self,
+        game_id: &Uuid,
+        args: &[String],
+        teams: &[Vec<Uuid>],
     ) -> Result<GameServerDescription, Box<dyn std::error::Error>>;
 }
 
@@ -40,12 +55,35 @@ impl RealGameServerManager {
 impl GameServerManager for RealGameServerManager {
     async fn spawn_new_game_server(
         &self,
+        game_id: &Uuid,
+        args: &[String],
+        teams: &[Vec<Uuid>],
     ) -> Result<GameServerDescription, Box<dyn std::error::Error>> {
+        let game_id_arg = format!("--game-id={game_id}");
+        let mut args: Vec<&str> = args.iter().map(String::as_str).collect();
+        args.push(&game_id_arg);
+        // Teams are separated by semicolons, and their players by commas.
+        let teams_arg = teams
+            .iter()
+            .map(|team| {
+                team.iter()
+                    .map(Uuid::to_string)
+                    .collect::<Vec<_>>()
+                    .join(",")
+            })
+            .collect::<Vec<_>>()
+            .join(";");
+        let teams_arg = format!("--teams={teams_arg}");
+        if !teams.is_empty() {
+            args.push(&teams_arg);
+        }
+
         let client = reqwest::Client::new();
 
         let resp = client
             .post(format!("{}/game/spawn", self.config.url))
             .header("Service-Key", &self.config.service_key)
+            .json(&SpawnRequest { args })
             .timeout(Duration::from_secs(5))
             .send()
             .await?;
@@ -65,6 +103,8 @@ impl GameServerManager for RealGameServerManager {
             host: self.config.game_server_external_host.clone(),
             port: spawned_server.port,
             created_at: spawned_server.created_at,
+            game_id: *game_id,
+            teams: teams.to_vec(),
         })
     }
 }
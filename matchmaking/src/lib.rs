@@ -1,7 +1,12 @@
 pub mod config;
+pub mod db;
 pub mod game_server_manager;
 pub mod identity;
+pub mod party;
 pub mod queue;
+pub mod rating;
+pub mod schema;
+pub mod service_key;
 pub mod websocket;
 
 use std::collections::BinaryHeap;
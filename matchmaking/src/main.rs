@@ -4,9 +4,13 @@ use actix::Actor;
 use actix_web::{get, middleware, web, App, HttpServer, Responder};
 use matchmaking::{
     config::{self, MATCHMAKING_CONFIG},
+    db,
     game_server_manager::{GameServerManager, RealGameServerManager},
     identity::{IdentityService, RealIdentityService},
-    queue, websocket,
+    party::{self, PartyData},
+    queue,
+    rating::{self, RatingService, RealRatingService},
+    websocket,
 };
 
 #[get("/")]
@@ -14,7 +18,7 @@ async fn hello() -> impl Responder {
     "MultiplayerBase Matchmaking"
 }
 
-const HOST: &'static str = "0.0.0.0";
+const HOST: &str = "0.0.0.0";
 const PORT: u16 = 8100;
 
 #[actix_web::main]
@@ -23,7 +27,17 @@ async fn main() -> std::io::Result<()> {
     std::env::set_var("RUST_LOG", "actix_web=debug,actix_server=info");
     env_logger::init();
 
-    let queue_data = web::Data::new(queue::QueueData::new());
+    let db_pool = db::initialize_db_pool(&config::POSTGRES_URL).await;
+    let real_rating_service = Arc::new(RealRatingService::new(db_pool));
+
+    let queue_data = web::Data::new(queue::QueueData::new(&MATCHMAKING_CONFIG));
+    let largest_party_size = MATCHMAKING_CONFIG
+        .queues
+        .values()
+        .map(|queue_config| queue_config.max_party_size())
+        .max()
+        .unwrap_or_default();
+    let party_data = web::Data::new(PartyData::new(largest_party_size));
 
     let game_server_manager = web::Data::from(Arc::new(RealGameServerManager::new(
         config::GAME_SERVER_MANAGER_CONFIG.clone(),
@@ -33,14 +47,17 @@ async fn main() -> std::io::Result<()> {
         queue_data.clone(),
         MATCHMAKING_CONFIG.clone(),
         game_server_manager.clone(),
+        web::Data::from(real_rating_service.clone() as Arc<dyn RatingService>),
     );
 
     let server_address = web::Data::new(server.start());
 
+    let real_id_service = Arc::new(RealIdentityService::new(config::IDENTITY_CONFIG.clone()));
+    refresh_identity_periodically(real_id_service.clone());
+
     HttpServer::new(move || {
-        let id_service = web::Data::from(Arc::new(RealIdentityService::new(
-            config::IDENTITY_CONFIG.clone(),
-        )) as Arc<dyn IdentityService>);
+        let id_service = web::Data::from(real_id_service.clone() as Arc<dyn IdentityService>);
+        let rating_service = web::Data::from(real_rating_service.clone() as Arc<dyn RatingService>);
 
         App::new()
             .wrap(middleware::NormalizePath::new(
@@ -49,13 +66,51 @@ async fn main() -> std::io::Result<()> {
             .wrap(config::get_cors_config())
             .wrap(middleware::Logger::default())
             .app_data(queue_data.clone())
+            .app_data(party_data.clone())
             .app_data(server_address.clone())
             .app_data(id_service)
+            .app_data(rating_service)
             .service(hello)
             .service(websocket::listen)
             .service(web::scope("/queue").configure(queue::config_service))
+            .service(web::scope("/party").configure(party::config_service))
+            .service(web::scope("/ratings").configure(rating::config_service))
     })
     .bind((HOST, PORT))?
     .run()
     .await
 }
+
+/// Keeps the access token verification keys and revoked tokens in sync with
+/// the authentication server.
+fn refresh_identity_periodically(id_service: Arc<RealIdentityService>) {
+    let keys_interval = id_service
+        .keys_refresh_interval()
+        .to_std()
+        .expect("The JWKS refresh interval should be positive");
+    let revocations_interval = id_service
+        .revocations_refresh_interval()
+        .to_std()
+        .expect("The revocations refresh interval should be positive");
+
+    let keys_id_service = id_service.clone();
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(keys_interval);
+        loop {
+            interval.tick().await;
+            if let Err(err) = keys_id_service.refresh_keys().await {
+                println!("Failed to refresh identity keys: {err}");
+            }
+        }
+    });
+
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(revocations_interval);
+        loop {
+            interval.tick().await;
+            if let Err(err) = id_service.refresh_revocations().await {
+                println!("Failed to refresh token revocations: {err}");
+            }
+        }
+    });
+}
@@ -0,0 +1,172 @@
+use crate::{
+    identity::{BearerToken, IdentityService},
+    party::{Party, PartyData},
+    queue::QueueData,
+    websocket::server::{Notify, ServerToClientMessage, WebsocketServer},
+};
+use actix::Addr;
+use actix_web::{error, get, post, web, HttpResponse, Responder};
+use serde::Deserialize;
+use uuid::Uuid;
+
+pub fn config_service(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_party)
+        .service(create)
+        .service(invite)
+        .service(accept)
+        .service(leave);
+}
+
+/// Pushes the new state of a party to its members.
+fn notify_members(server_address: &Addr<WebsocketServer>, party: &Party) -> actix_web::Result<()> {
+    server_address
+        .try_send(Notify {
+            user_ids: party.member_ids.clone(),
+            message: ServerToClientMessage::PartyUpdated(party.clone()),
+        })
+        .map_err(error::ErrorInternalServerError)
+}
+
+#[get("/")]
+async fn get_party(
+    token: BearerToken,
+    id_service: web::Data<dyn IdentityService>,
+    party_data: web::Data<PartyData>,
+) -> actix_web::Result<impl Responder> {
+    let identity = id_service.get_identity(&token)?;
+
+    let parties = party_data
+        .parties
+        .read()
+        .expect("Failed to get read lock on parties");
+
+    let party = parties
+        .party_of(&identity.user_id)
+        .ok_or_else(|| error::ErrorNotFound("Not in a party"))?;
+
+    Ok(HttpResponse::Ok().json(party))
+}
+
+#[post("/")]
+async fn create(
+    token: BearerToken,
+    id_service: web::Data<dyn IdentityService>,
+    party_data: web::Data<PartyData>,
+    queue_data: web::Data<QueueData>,
+) -> actix_web::Result<impl Responder> {
+    let identity = id_service.get_identity(&token)?;
+
+    let mut parties = party_data
+        .parties
+        .write()
+        .expect("Failed to get write lock on parties");
+
+    let queues = queue_data
+        .queues
+        .read()
+        .expect("Failed to get read lock on queues");
+    if queues.contains_player(&identity.user_id) {
+        return Err(error::ErrorBadRequest("Cannot create a party while queued"));
+    }
+
+    let party = parties.create(identity.user_id)?;
+
+    Ok(HttpResponse::Ok().json(party))
+}
+
+#[derive(Deserialize)]
+struct InviteBody {
+    user_id: Uuid,
+}
+
+/// Invites a player to the party of its leader.
+#[post("/invite/")]
+async fn invite(
+    token: BearerToken,
+    body: web::Json<InviteBody>,
+    id_service: web::Data<dyn IdentityService>,
+    party_data: web::Data<PartyData>,
+    server_address: web::Data<Addr<WebsocketServer>>,
+) -> actix_web::Result<impl Responder> {
+    let identity = id_service.get_identity(&token)?;
+
+    let mut parties = party_data
+        .parties
+        .write()
+        .expect("Failed to get write lock on parties");
+
+    let party = parties.invite(&identity.user_id, body.user_id)?;
+
+    notify_members(&server_address, &party)?;
+    server_address
+        .try_send(Notify {
+            user_ids: vec![body.user_id],
+            message: ServerToClientMessage::PartyInvited(party.clone()),
+        })
+        .map_err(error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(party))
+}
+
+/// Joins a party that the player was invited to. A party that gains a member
+/// leaves the queue, so that its leader queues again with the new member.
+#[post("/{party_id}/accept/")]
+async fn accept(
+    token: BearerToken,
+    party_id: web::Path<Uuid>,
+    id_service: web::Data<dyn IdentityService>,
+    party_data: web::Data<PartyData>,
+    queue_data: web::Data<QueueData>,
+    server_address: web::Data<Addr<WebsocketServer>>,
+) -> actix_web::Result<impl Responder> {
+    let identity = id_service.get_identity(&token)?;
+
+    let mut parties = party_data
+        .parties
+        .write()
+        .expect("Failed to get write lock on parties");
+
+    let mut queues = queue_data
+        .queues
+        .write()
+        .expect("Failed to get write lock on queues");
+    if queues.contains_player(&identity.user_id) {
+        return Err(error::ErrorBadRequest("Cannot join a party while queued"));
+    }
+
+    let party = parties.accept(identity.user_id, &party_id)?;
+    queues.remove_party(&party.id);
+
+    notify_members(&server_address, &party)?;
+
+    Ok(HttpResponse::Ok().json(party))
+}
+
+/// Leaves the party of the player. A party that loses a member leaves the
+/// queue.
+#[post("/leave/")]
+async fn leave(
+    token: BearerToken,
+    id_service: web::Data<dyn IdentityService>,
+    party_data: web::Data<PartyData>,
+    queue_data: web::Data<QueueData>,
+    server_address: web::Data<Addr<WebsocketServer>>,
+) -> actix_web::Result<impl Responder> {
+    let identity = id_service.get_identity(&token)?;
+
+    let mut parties = party_data
+        .parties
+        .write()
+        .expect("Failed to get write lock on parties");
+
+    let party = parties.leave(&identity.user_id)?;
+    queue_data
+        .queues
+        .write()
+        .expect("Failed to get write lock on queues")
+        .remove_party(&party.id);
+
+    notify_members(&server_address, &party)?;
+
+    Ok(HttpResponse::Ok().json(party))
+}
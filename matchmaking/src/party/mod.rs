@@ -0,0 +1,198 @@
+mod membership;
+
+use actix_web::{error, web};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::RwLock;
+use uuid::Uuid;
+
+pub fn config_service(cfg: &mut web::ServiceConfig) {
+    membership::config_service(cfg);
+}
+
+#[derive(Debug)]
+pub struct PartyData {
+    pub parties: RwLock<Parties>,
+}
+
+impl PartyData {
+    pub fn new(max_size: u8) -> PartyData {
+        PartyData {
+            parties: RwLock::new(Parties::new(max_size)),
+        }
+    }
+}
+
+/// Players who queue and are matched together.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Party {
+    pub id: Uuid,
+    /// The member who queues for the whole party.
+    pub leader_id: Uuid,
+    /// The members in the order they joined.
+    pub member_ids: Vec<Uuid>,
+    pub invited_ids: HashSet<Uuid>,
+}
+
+#[derive(Debug)]
+pub struct Parties {
+    parties: HashMap<Uuid, Party>,
+    /// The party of every member.
+    party_ids: HashMap<Uuid, Uuid>,
+    max_size: usize,
+}
+
+impl Parties {
+    fn new(max_size: u8) -> Parties {
+        Parties {
+            parties: HashMap::new(),
+            party_ids: HashMap::new(),
+            max_size: max_size as usize,
+        }
+    }
+
+    pub fn party_of(&self, user_id: &Uuid) -> Option<&Party> {
+        self.party_ids
+            .get(user_id)
+            .and_then(|party_id| self.parties.get(party_id))
+    }
+
+    pub fn create(&mut self, leader_id: Uuid) -> Result<Party, error::Error> {
+        if self.party_ids.contains_key(&leader_id) {
+            return Err(error::ErrorBadRequest(
+                "Cannot create a party while in a party",
+            ));
+        }
+        let party = Party {
+            id: Uuid::new_v4(),
+            leader_id,
+            member_ids: vec![leader_id],
+            invited_ids: HashSet::new(),
+        };
+        self.party_ids.insert(leader_id, party.id);
+        self.parties.insert(party.id, party.clone());
+        Ok(party)
+    }
+
+    /// Invites a player to the party of `leader_id`.
+    pub fn invite(&mut self, leader_id: &Uuid, user_id: Uuid) -> Result<Party, error::Error> {
+        let party = self
+            .party_ids
+            .get(leader_id)
+            .and_then(|party_id| self.parties.get_mut(party_id))
+            .ok_or_else(|| error::ErrorBadRequest("Cannot invite without a party"))?;
+        if &party.leader_id != leader_id {
+            return Err(error::ErrorForbidden(
+                "Only the party leader can invite players",
+            ));
+        }
+        if party.member_ids.contains(&user_id) {
+            return Err(error::ErrorBadRequest("Player is already in the party"));
+        }
+        party.invited_ids.insert(user_id);
+        Ok(party.clone())
+    }
+
+    pub fn accept(&mut self, user_id: Uuid, party_id: &Uuid) -> Result<Party, error::Error> {
+        if self.party_ids.contains_key(&user_id) {
+            return Err(error::ErrorBadRequest(
+                "Cannot join a party while in a party",
+            ));
+        }
+        let party = self
+            .parties
+            .get_mut(party_id)
+            .ok_or_else(|| error::ErrorNotFound("Party not found"))?;
+        if !party.invited_ids.contains(&user_id) {
+            return Err(error::ErrorForbidden("Not invited to the party"));
+        }
+        if party.member_ids.len() >= self.max_size {
+            return Err(error::ErrorBadRequest("The party is full"));
+        }
+        party.invited_ids.remove(&user_id);
+        party.member_ids.push(user_id);
+        self.party_ids.insert(user_id, party.id);
+        Ok(party.clone())
+    }
+
+    /// Removes a player from their party, and returns the party that they
+    /// left. The next member leads the party when its leader leaves, and a
+    /// party without members is disbanded.
+    pub fn leave(&mut self, user_id: &Uuid) -> Result<Party, error::Error> {
+        let party_id = self
+            .party_ids
+            .remove(user_id)
+            .ok_or_else(|| error::ErrorBadRequest("Cannot leave party that is not joined"))?;
+        let party = self
+            .parties
+            .get_mut(&party_id)
+            .expect("Every member should have a party");
+        party.member_ids.retain(|member_id| member_id != user_id);
+        if &party.leader_id == user_id {
+            if let Some(next_leader_id) = party.member_ids.first() {
+                party.leader_id = *next_leader_id;
+            }
+        }
+
+        let party = party.clone();
+        if party.member_ids.is_empty() {
+            self.parties.remove(&party_id);
+        }
+        Ok(party)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invited_players_join_the_party() {
+        let mut parties = Parties::new(4);
+        let [leader, friend, stranger] = [(); 3].map(|_| Uuid::new_v4());
+
+        let party = parties.create(leader).unwrap();
+        assert!(parties.invite(&friend, stranger).is_err());
+        parties.invite(&leader, friend).unwrap();
+        assert!(parties.accept(stranger, &party.id).is_err());
+
+        let party = parties.accept(friend, &party.id).unwrap();
+        assert_eq!(party.member_ids, [leader, friend]);
+        assert!(party.invited_ids.is_empty());
+        assert_eq!(parties.party_of(&friend), Some(&party));
+        assert!(parties.invite(&friend, stranger).is_err());
+        assert!(parties.create(friend).is_err());
+    }
+
+    #[test]
+    fn parties_are_not_larger_than_a_game() {
+        let mut parties = Parties::new(2);
+        let [leader, friend, other_friend] = [(); 3].map(|_| Uuid::new_v4());
+
+        let party = parties.create(leader).unwrap();
+        parties.invite(&leader, friend).unwrap();
+        parties.invite(&leader, other_friend).unwrap();
+        parties.accept(friend, &party.id).unwrap();
+
+        assert!(parties.accept(other_friend, &party.id).is_err());
+    }
+
+    #[test]
+    fn the_next_member_leads_when_the_leader_leaves() {
+        let mut parties = Parties::new(4);
+        let [leader, friend] = [(); 2].map(|_| Uuid::new_v4());
+
+        let party = parties.create(leader).unwrap();
+        parties.invite(&leader, friend).unwrap();
+        parties.accept(friend, &party.id).unwrap();
+
+        let party = parties.leave(&leader).unwrap();
+        assert_eq!(party.leader_id, friend);
+        assert_eq!(party.member_ids, [friend]);
+        assert_eq!(parties.party_of(&leader), None);
+
+        let party = parties.leave(&friend).unwrap();
+        assert!(party.member_ids.is_empty());
+        assert!(parties.parties.is_empty());
+    }
+}
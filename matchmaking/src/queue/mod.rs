@@ -1,60 +1,141 @@
-mod solo;
+mod mode;
+mod teams;
 
-use crate::config::MatchmakingConfig;
+use crate::config::{MatchmakingConfig, QueueConfig};
+use crate::rating::Rating;
 use crate::BinaryHeapExt;
 use actix_web::{error, web};
 use chrono::{DateTime, Utc};
 use derivative::Derivative;
 use serde::Serialize;
 use std::cmp::Reverse;
-use std::collections::BinaryHeap;
+use std::collections::{BinaryHeap, HashMap};
+use std::ops::RangeInclusive;
 use std::sync::RwLock;
+use teams::{balance_teams, TeamGroup};
 use uuid::Uuid;
 
 pub fn config_service(cfg: &mut web::ServiceConfig) {
-    cfg.service(web::scope("/solo").configure(solo::config_service));
+    cfg.service(web::scope("/{mode}").configure(mode::config_service));
 }
 
 #[derive(Debug)]
 pub struct QueueData {
-    pub solo: RwLock<SoloQueue>,
+    pub queues: RwLock<Queues>,
 }
 
 impl QueueData {
-    pub fn new() -> QueueData {
+    pub fn new(config: &MatchmakingConfig) -> QueueData {
         QueueData {
-            solo: RwLock::new(SoloQueue::new()),
+            queues: RwLock::new(Queues {
+                queues: config
+                    .queues
+                    .iter()
+                    .map(|(mode, queue_config)| (mode.clone(), Queue::new(queue_config.clone())))
+                    .collect(),
+            }),
         }
     }
 }
 
+/// The queue of every game mode.
 #[derive(Debug)]
-pub struct SoloQueue {
+pub struct Queues {
+    queues: HashMap<String, Queue>,
+}
+
+impl Queues {
+    pub fn get(&self, mode: &str) -> Result<&Queue, error::Error> {
+        self.queues
+            .get(mode)
+            .ok_or_else(|| error::ErrorNotFound(format!("No queue for game mode {mode}")))
+    }
+
+    pub fn get_mut(&mut self, mode: &str) -> Result<&mut Queue, error::Error> {
+        self.queues
+            .get_mut(mode)
+            .ok_or_else(|| error::ErrorNotFound(format!("No queue for game mode {mode}")))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&String, &mut Queue)> {
+        self.queues.iter_mut()
+    }
+
+    /// Whether the player is in the queue of any game mode.
+    pub fn contains_player(&self, user_id: &Uuid) -> bool {
+        self.queues
+            .values()
+            .any(|queue| queue.contains_player(user_id))
+    }
+
+    /// Removes every member of a party from the queue it joined.
+    pub fn remove_party(&mut self, party_id: &Uuid) -> Vec<QueuedPlayer> {
+        self.queues
+            .values_mut()
+            .flat_map(|queue| queue.remove_party(party_id))
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+pub struct Queue {
+    pub config: QueueConfig,
     queue: BinaryHeap<QueuedPlayer>,
 }
 
-impl SoloQueue {
-    fn new() -> SoloQueue {
-        SoloQueue {
+impl Queue {
+    fn new(config: QueueConfig) -> Queue {
+        Queue {
+            config,
             queue: BinaryHeap::new(),
         }
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Derivative, Serialize)]
-#[derivative(PartialOrd, Ord)]
+#[derive(Debug, Clone, Derivative, Serialize)]
+#[derivative(PartialEq, Eq, PartialOrd, Ord)]
 pub struct QueuedPlayer {
     joined_at: Reverse<DateTime<Utc>>,
     #[derivative(PartialOrd = "ignore", Ord = "ignore")]
     pub user_id: Uuid,
+    #[derivative(PartialEq = "ignore", PartialOrd = "ignore", Ord = "ignore")]
+    pub rating: Rating,
+    /// Players of the same party are always matched into the same game.
+    #[derivative(PartialOrd = "ignore", Ord = "ignore")]
+    pub party_id: Option<Uuid>,
+}
+
+/// Players who are matched together, i.e. a party or a single player.
+struct QueuedGroup<'a> {
+    players: Vec<&'a QueuedPlayer>,
+    /// The average rating of the players.
+    rating: f64,
 }
 
-impl SoloQueue {
+impl QueuedGroup<'_> {
+    fn joined_at(&self) -> DateTime<Utc> {
+        self.players[0].joined_at.0
+    }
+}
+
+/// The players of a game that were removed from the queue.
+#[derive(Debug, Clone)]
+pub struct ReadyGame {
+    pub players: Vec<QueuedPlayer>,
+    /// The user ids of every team, or no teams in modes without teams.
+    pub teams: Vec<Vec<Uuid>>,
+}
+
+impl Queue {
     pub fn contains_player(&self, user_id: &Uuid) -> bool {
         self.queue.iter().any(|p| &p.user_id == user_id)
     }
 
-    pub fn insert_user(&mut self, user_id: Uuid) -> Result<QueuedPlayer, error::Error> {
+    pub fn insert_user(
+        &mut self,
+        user_id: Uuid,
+        rating: Rating,
+    ) -> Result<QueuedPlayer, error::Error> {
         if self.contains_player(&user_id) {
             return Err(error::ErrorBadRequest(
                 "Cannot join queue that is already joined",
@@ -63,11 +144,42 @@ impl SoloQueue {
         let player = QueuedPlayer {
             user_id,
             joined_at: Reverse(Utc::now()),
+            rating,
+            party_id: None,
         };
         self.queue.push(player.clone());
         Ok(player)
     }
 
+    /// Queues the members of a party with their ratings, so that they are
+    /// matched into the same game.
+    pub fn insert_party(
+        &mut self,
+        party_id: Uuid,
+        members: Vec<(Uuid, Rating)>,
+    ) -> Result<Vec<QueuedPlayer>, error::Error> {
+        if members
+            .iter()
+            .any(|(user_id, _)| self.contains_player(user_id))
+        {
+            return Err(error::ErrorBadRequest(
+                "Cannot join queue that is already joined",
+            ));
+        }
+        let joined_at = Reverse(Utc::now());
+        let players: Vec<QueuedPlayer> = members
+            .into_iter()
+            .map(|(user_id, rating)| QueuedPlayer {
+                user_id,
+                joined_at,
+                rating,
+                party_id: Some(party_id),
+            })
+            .collect();
+        self.queue.extend(players.iter().cloned());
+        Ok(players)
+    }
+
     pub fn remove_player(&mut self, user_id: &Uuid) -> Result<QueuedPlayer, error::Error> {
         match self.queue.remove(|p| &p.user_id == user_id) {
             Some(removed) => Ok(removed),
@@ -77,27 +189,141 @@ impl SoloQueue {
         }
     }
 
-    pub fn remove_ready_players(
-        &mut self,
-        config: &MatchmakingConfig,
-    ) -> Result<Vec<QueuedPlayer>, error::Error> {
-        if (self.queue.len() as u8) < config.solo_game_min_size {
-            return Err(error::ErrorInternalServerError(
-                "Not enough ready players to remove",
-            ));
-        }
+    /// Removes every member of a party from the queue.
+    pub fn remove_party(&mut self, party_id: &Uuid) -> Vec<QueuedPlayer> {
         let mut removed = vec![];
-        for _ in 0..config.solo_game_desired_size {
-            let Some(player) = self.queue.pop() else {
-                break;
+        self.queue.retain(|p| {
+            if p.party_id.as_ref() == Some(party_id) {
+                removed.push(p.clone());
+                return false;
+            }
+            true
+        });
+        removed
+    }
+
+    /// Groups the queued members of each party.
+    fn groups(&self) -> Vec<QueuedGroup<'_>> {
+        let mut groups: Vec<QueuedGroup> = vec![];
+        let mut party_groups: HashMap<Uuid, usize> = HashMap::new();
+        for player in self.queue.iter() {
+            let Some(party_id) = player.party_id else {
+                groups.push(QueuedGroup {
+                    players: vec![player],
+                    rating: 0.0,
+                });
+                continue;
+            };
+            match party_groups.get(&party_id) {
+                Some(index) => groups[*index].players.push(player),
+                None => {
+                    party_groups.insert(party_id, groups.len());
+                    groups.push(QueuedGroup {
+                        players: vec![player],
+                        rating: 0.0,
+                    });
+                }
+            }
+        }
+        for group in groups.iter_mut() {
+            group.rating = group.players.iter().map(|p| p.rating.rating).sum::<f64>()
+                / group.players.len() as f64;
+        }
+        groups
+    }
+
+    /// Finds the players of the next game. Games are built around the player
+    /// who waited longest, from the players closest to them in rating, and
+    /// the ratings in a game may be further apart the longer they waited.
+    /// Parties count as one player with their average rating, but fill as
+    /// many places as they have members.
+    ///
+    /// Games have the desired size, or at least the minimum size once their
+    /// oldest player waited longer than desired.
+    fn find_ready_game(&self, now: DateTime<Utc>) -> Option<(Vec<Uuid>, Vec<Vec<Uuid>>)> {
+        let config = &self.config;
+        let mut groups = self.groups();
+        // Ties go to the group who waited longest.
+        groups.sort_by(|a, b| {
+            a.rating
+                .total_cmp(&b.rating)
+                .then(a.joined_at().cmp(&b.joined_at()))
+        });
+        let mut by_wait: Vec<usize> = (0..groups.len()).collect();
+        by_wait.sort_by_key(|index| groups[*index].joined_at());
+        let team_count = config.team_count.map(usize::from);
+
+        for oldest in by_wait.iter().copied() {
+            let waited = now.signed_duration_since(groups[oldest].joined_at());
+            let max_spread = match config.rating_spread {
+                Some(rating_spread) => {
+                    rating_spread
+                        + config.rating_spread_per_sec * waited.num_milliseconds() as f64 / 1000.0
+                }
+                None => f64::INFINITY,
+            };
+            let min_size = if waited > config.desired_max_wait_time {
+                config.min_size
+            } else {
+                config.desired_size
             };
-            removed.push(player);
+
+            for size in (min_size.max(1)..=config.desired_size).rev() {
+                let size = size as usize;
+                if team_count.is_some_and(|team_count| !size.is_multiple_of(team_count)) {
+                    continue;
+                }
+                let Some((mut game, teams)) =
+                    closest_game(&groups, &by_wait, oldest, size, max_spread, team_count)
+                else {
+                    continue;
+                };
+                game.sort();
+                let user_ids = |indices: &[usize]| -> Vec<Uuid> {
+                    indices
+                        .iter()
+                        .flat_map(|index| groups[*index].players.iter().map(|p| p.user_id))
+                        .collect()
+                };
+                return Some((
+                    user_ids(&game),
+                    teams.iter().map(|team| user_ids(team)).collect(),
+                ));
+            }
+        }
+
+        None
+    }
+
+    /// Removes the players of as many games as the queue is ready for.
+    pub fn remove_ready_games(&mut self) -> Vec<ReadyGame> {
+        let now = Utc::now();
+        let mut games = vec![];
+        while let Some((user_ids, teams)) = self.find_ready_game(now) {
+            games.push(ReadyGame {
+                players: user_ids
+                    .iter()
+                    .filter_map(|user_id| self.queue.remove(|p| &p.user_id == user_id))
+                    .collect(),
+                teams,
+            });
         }
-        Ok(removed)
+        games
     }
 
-    pub fn status(&self, config: &MatchmakingConfig) -> QueueStatus {
-        if (self.queue.len() as u8) >= config.solo_game_desired_size {
+    /// Puts players back in the queue in their original place, e.g. when
+    /// their game could not be started.
+    pub fn requeue_players(&mut self, players: Vec<QueuedPlayer>) {
+        for player in players {
+            if !self.contains_player(&player.user_id) {
+                self.queue.push(player);
+            }
+        }
+    }
+
+    pub fn status(&self) -> QueueStatus {
+        let config = &self.config;
+        if (self.queue.len() as u8) >= config.desired_size {
             return QueueStatus::Ready;
         }
 
@@ -106,9 +332,9 @@ impl SoloQueue {
         };
 
         if Utc::now().signed_duration_since(oldest_player.joined_at.0)
-            > config.solo_queue_desired_max_wait_time
+            > config.desired_max_wait_time
         {
-            return if (self.queue.len() as u8) >= config.solo_game_min_size {
+            return if (self.queue.len() as u8) >= config.min_size {
                 QueueStatus::LongWaitReady
             } else {
                 QueueStatus::LongWaitNotReady
@@ -119,6 +345,112 @@ impl SoloQueue {
     }
 }
 
+/// Finds the groups of a game of `size` players that includes the `oldest`
+/// group, with the smallest rating spread up to `max_spread`, and splits them
+/// into balanced teams if there are any. `groups` are sorted by rating, and
+/// `by_wait` indexes them from the longest wait.
+fn closest_game(
+    groups: &[QueuedGroup],
+    by_wait: &[usize],
+    oldest: usize,
+    size: usize,
+    max_spread: f64,
+    team_count: Option<usize>,
+) -> Option<(Vec<usize>, Vec<Vec<usize>>)> {
+    if groups[oldest].players.len() > size {
+        return None;
+    }
+    let mut closest: Option<(f64, Vec<usize>, Vec<Vec<usize>>)> = None;
+
+    for low in (0..=oldest).rev() {
+        if groups[oldest].rating - groups[low].rating > max_spread {
+            break;
+        }
+        for high in oldest..groups.len() {
+            let spread = groups[high].rating - groups[low].rating;
+            if spread > max_spread {
+                break;
+            }
+            let Some(game) = fill_game(groups, by_wait, oldest, low..=high, size) else {
+                continue;
+            };
+            let teams = match team_count {
+                Some(team_count) => match split_teams(groups, &game, team_count) {
+                    Some(teams) => teams,
+                    None => continue,
+                },
+                None => vec![],
+            };
+            let is_closer = match &closest {
+                Some((closest_spread, _, _)) => spread < *closest_spread,
+                None => true,
+            };
+            if is_closer {
+                closest = Some((spread, game, teams));
+            }
+            break;
+        }
+    }
+
+    closest.map(|(_, game, teams)| (game, teams))
+}
+
+/// Splits the groups of a game into balanced teams of groups.
+fn split_teams(
+    groups: &[QueuedGroup],
+    game: &[usize],
+    team_count: usize,
+) -> Option<Vec<Vec<usize>>> {
+    let team_groups: Vec<TeamGroup> = game
+        .iter()
+        .map(|index| TeamGroup {
+            size: groups[*index].players.len(),
+            total_rating: groups[*index].players.iter().map(|p| p.rating.rating).sum(),
+        })
+        .collect();
+    let team_of_group = balance_teams(&team_groups, team_count)?;
+
+    let mut teams = vec![vec![]; team_count];
+    for (index, team) in game.iter().zip(team_of_group) {
+        teams[team].push(*index);
+    }
+    Some(teams)
+}
+
+/// Picks groups in `range` that fill a game of `size` players together with
+/// the `oldest` group, preferring the groups that waited longest.
+fn fill_game(
+    groups: &[QueuedGroup],
+    by_wait: &[usize],
+    oldest: usize,
+    range: RangeInclusive<usize>,
+    size: usize,
+) -> Option<Vec<usize>> {
+    // The groups that first added up to each number of players.
+    let mut fills: Vec<Option<Vec<usize>>> = vec![None; size + 1];
+    let oldest_size = groups[oldest].players.len();
+    fills[oldest_size] = Some(vec![oldest]);
+
+    for index in by_wait.iter().copied() {
+        let group_size = groups[index].players.len();
+        if index == oldest || !range.contains(&index) || oldest_size + group_size > size {
+            continue;
+        }
+        for players in (oldest_size..=size - group_size).rev() {
+            if fills[players + group_size].is_some() {
+                continue;
+            }
+            if let Some(fill) = &fills[players] {
+                let mut fill = fill.clone();
+                fill.push(index);
+                fills[players + group_size] = Some(fill);
+            }
+        }
+    }
+
+    fills[size].take()
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum QueueStatus {
     NotReady,
@@ -138,83 +470,306 @@ mod tests {
 
         #[test]
         fn empty_queue_is_not_ready() {
-            let queue = SoloQueue::new();
-            let config = MatchmakingConfig::default();
-            assert_eq!(queue.status(&config), QueueStatus::NotReady);
+            let queue = Queue::new(QueueConfig::default());
+            assert_eq!(queue.status(), QueueStatus::NotReady);
         }
 
         #[test]
         fn less_than_min_players_and_long_wait_is_long_wait_not_ready() {
-            let mut queue = SoloQueue::new();
+            let mut queue = Queue::new(QueueConfig {
+                desired_max_wait_time: Duration::seconds(5),
+                min_size: 2,
+                ..QueueConfig::default()
+            });
 
             queue.queue.push(QueuedPlayer {
                 joined_at: Reverse(Utc::now() - Duration::seconds(10)),
                 user_id: Uuid::new_v4(),
+                rating: Rating::default(),
+                party_id: None,
             });
 
-            let config = MatchmakingConfig {
-                solo_queue_desired_max_wait_time: Duration::seconds(5),
-                solo_game_min_size: 2,
-                ..MatchmakingConfig::default()
-            };
-
-            assert_eq!(queue.status(&config), QueueStatus::LongWaitNotReady);
+            assert_eq!(queue.status(), QueueStatus::LongWaitNotReady);
         }
 
         #[test]
         fn more_than_min_players_and_long_wait_is_long_wait_ready() {
-            let mut queue = SoloQueue::new();
+            let mut queue = Queue::new(QueueConfig {
+                desired_max_wait_time: Duration::seconds(5),
+                min_size: 2,
+                ..QueueConfig::default()
+            });
 
             queue.queue.push(QueuedPlayer {
                 joined_at: Reverse(Utc::now() - Duration::seconds(10)),
                 user_id: Uuid::new_v4(),
+                rating: Rating::default(),
+                party_id: None,
             });
 
             queue.queue.push(QueuedPlayer {
                 joined_at: Reverse(Utc::now() - Duration::seconds(2)),
                 user_id: Uuid::new_v4(),
+                rating: Rating::default(),
+                party_id: None,
             });
 
-            let config = MatchmakingConfig {
-                solo_queue_desired_max_wait_time: Duration::seconds(5),
-                solo_game_min_size: 2,
-                ..MatchmakingConfig::default()
-            };
-
-            assert_eq!(queue.status(&config), QueueStatus::LongWaitReady);
+            assert_eq!(queue.status(), QueueStatus::LongWaitReady);
         }
 
         #[test]
         fn short_wait_is_not_ready() {
-            let mut queue = SoloQueue::new();
+            let mut queue = Queue::new(QueueConfig {
+                desired_max_wait_time: Duration::seconds(20),
+                ..QueueConfig::default()
+            });
 
             queue.queue.push(QueuedPlayer {
                 joined_at: Reverse(Utc::now() - Duration::seconds(10)),
                 user_id: Uuid::new_v4(),
+                rating: Rating::default(),
+                party_id: None,
             });
 
-            let config = MatchmakingConfig {
-                solo_queue_desired_max_wait_time: Duration::seconds(20),
-                ..MatchmakingConfig::default()
-            };
-
-            assert_eq!(queue.status(&config), QueueStatus::NotReady);
+            assert_eq!(queue.status(), QueueStatus::NotReady);
         }
 
         #[test]
         fn enough_for_desired_players_is_ready() {
-            let mut queue = SoloQueue::new();
+            let mut queue = Queue::new(QueueConfig {
+                desired_size: 4,
+                ..QueueConfig::default()
+            });
 
             for _ in 0..4 {
-                _ = queue.insert_user(Uuid::new_v4());
+                _ = queue.insert_user(Uuid::new_v4(), Rating::default());
             }
 
-            let config = MatchmakingConfig {
-                solo_game_desired_size: 4,
-                ..MatchmakingConfig::default()
+            assert_eq!(queue.status(), QueueStatus::Ready);
+        }
+    }
+
+    mod remove_ready_games {
+        use super::*;
+
+        fn queue_with_ratings(config: QueueConfig, waits_and_ratings: &[(i64, f64)]) -> Queue {
+            let mut queue = Queue::new(config);
+            for (wait, rating) in waits_and_ratings {
+                queue.queue.push(QueuedPlayer {
+                    joined_at: Reverse(Utc::now() - Duration::seconds(*wait)),
+                    user_id: Uuid::new_v4(),
+                    rating: Rating {
+                        rating: *rating,
+                        ..Rating::default()
+                    },
+                    party_id: None,
+                });
+            }
+            queue
+        }
+
+        fn queue_with_waits(config: QueueConfig, waits: &[i64]) -> Queue {
+            let waits_and_ratings: Vec<(i64, f64)> =
+                waits.iter().map(|wait| (*wait, 1500.0)).collect();
+            queue_with_ratings(config, &waits_and_ratings)
+        }
+
+        fn game_ratings(game: &ReadyGame) -> Vec<f64> {
+            game.players.iter().map(|p| p.rating.rating).collect()
+        }
+
+        fn game_sizes(games: &[ReadyGame]) -> Vec<usize> {
+            games.iter().map(|game| game.players.len()).collect()
+        }
+
+        #[test]
+        fn full_games_are_drained_in_one_go() {
+            let config = QueueConfig {
+                desired_size: 4,
+                desired_max_wait_time: Duration::seconds(20),
+                ..QueueConfig::default()
             };
+            let mut queue = queue_with_waits(config, &[9, 8, 7, 6, 5, 4, 3, 2, 1]);
+
+            let games = queue.remove_ready_games();
+
+            assert_eq!(game_sizes(&games), [4, 4]);
+            assert_eq!(queue.queue.len(), 1);
+            // The players who waited longest are matched first.
+            assert!(games[0]
+                .players
+                .iter()
+                .all(|p| p.joined_at > queue.queue.peek().unwrap().joined_at));
+        }
+
+        #[test]
+        fn long_waiting_players_fill_a_smaller_game() {
+            let config = QueueConfig {
+                min_size: 2,
+                desired_size: 4,
+                desired_max_wait_time: Duration::seconds(20),
+                ..QueueConfig::default()
+            };
+            let mut queue = queue_with_waits(config, &[30, 29, 28, 27, 26, 25]);
+
+            let games = queue.remove_ready_games();
 
-            assert_eq!(queue.status(&config), QueueStatus::Ready);
+            assert_eq!(game_sizes(&games), [4, 2]);
+            assert_eq!(queue.status(), QueueStatus::NotReady);
+        }
+
+        #[test]
+        fn requeued_players_keep_their_place() {
+            let config = QueueConfig {
+                min_size: 2,
+                desired_size: 2,
+                ..QueueConfig::default()
+            };
+            let mut queue = queue_with_waits(config, &[10, 5]);
+
+            let mut games = queue.remove_ready_games();
+            let players = games.pop().unwrap().players;
+            queue.queue.push(QueuedPlayer {
+                joined_at: Reverse(Utc::now()),
+                user_id: Uuid::new_v4(),
+                rating: Rating::default(),
+                party_id: None,
+            });
+            queue.requeue_players(players.clone());
+
+            assert_eq!(queue.queue.len(), 3);
+            assert_eq!(queue.queue.peek(), Some(&players[0]));
+        }
+
+        #[test]
+        fn players_are_matched_with_the_closest_ratings() {
+            let config = QueueConfig {
+                min_size: 2,
+                desired_size: 2,
+                rating_spread: Some(100.0),
+                rating_spread_per_sec: 0.0,
+                ..QueueConfig::default()
+            };
+            let mut queue = queue_with_ratings(
+                config,
+                &[(5, 1500.0), (4, 2000.0), (3, 1550.0), (2, 1420.0)],
+            );
+
+            let games = queue.remove_ready_games();
+
+            assert_eq!(games.len(), 1);
+            assert_eq!(game_ratings(&games[0]), [1500.0, 1550.0]);
+            assert_eq!(queue.queue.len(), 2);
+        }
+
+        #[test]
+        fn the_rating_spread_widens_while_players_wait() {
+            let config = QueueConfig {
+                min_size: 2,
+                desired_size: 2,
+                rating_spread: Some(100.0),
+                rating_spread_per_sec: 10.0,
+                ..QueueConfig::default()
+            };
+            let mut queue = queue_with_ratings(config, &[(10, 1500.0), (9, 1800.0)]);
+            assert!(queue.remove_ready_games().is_empty());
+
+            queue.config.rating_spread_per_sec = 30.0;
+            let games = queue.remove_ready_games();
+            assert_eq!(game_ratings(&games[0]), [1500.0, 1800.0]);
+        }
+
+        #[test]
+        fn unrated_queues_ignore_ratings() {
+            let config = QueueConfig {
+                min_size: 2,
+                desired_size: 2,
+                rating_spread: None,
+                ..QueueConfig::default()
+            };
+            let mut queue = queue_with_ratings(config, &[(5, 1000.0), (4, 2000.0)]);
+
+            let games = queue.remove_ready_games();
+
+            assert_eq!(game_sizes(&games), [2]);
+        }
+
+        #[test]
+        fn parties_are_matched_together() {
+            let config = QueueConfig {
+                min_size: 2,
+                desired_size: 4,
+                desired_max_wait_time: Duration::seconds(20),
+                ..QueueConfig::default()
+            };
+            let mut queue = queue_with_waits(config, &[10, 6]);
+            let party_id = Uuid::new_v4();
+            let party = queue
+                .insert_party(
+                    party_id,
+                    (0..3)
+                        .map(|_| (Uuid::new_v4(), Rating::default()))
+                        .collect(),
+                )
+                .unwrap();
+
+            let games = queue.remove_ready_games();
+
+            assert_eq!(game_sizes(&games), [4]);
+            assert!(party.iter().all(|member| games[0].players.contains(member)));
+            assert!(queue.remove_party(&party_id).is_empty());
+        }
+
+        #[test]
+        fn team_games_are_split_into_balanced_teams() {
+            let config = QueueConfig {
+                min_size: 4,
+                desired_size: 4,
+                team_count: Some(2),
+                ..QueueConfig::default()
+            };
+            let mut queue = queue_with_ratings(
+                config,
+                &[(5, 2000.0), (4, 1900.0), (3, 1100.0), (2, 1000.0)],
+            );
+
+            let games = queue.remove_ready_games();
+
+            assert_eq!(game_sizes(&games), [4]);
+            let team_ratings: Vec<f64> = games[0]
+                .teams
+                .iter()
+                .map(|team| {
+                    team.iter()
+                        .map(|user_id| {
+                            let player = games[0].players.iter().find(|p| &p.user_id == user_id);
+                            player.unwrap().rating.rating
+                        })
+                        .sum()
+                })
+                .collect();
+            assert_eq!(team_ratings, [3000.0, 3000.0]);
+        }
+
+        #[test]
+        fn parties_that_do_not_fit_a_team_wait() {
+            let config = QueueConfig {
+                min_size: 4,
+                desired_size: 4,
+                team_count: Some(2),
+                ..QueueConfig::default()
+            };
+            let mut queue = queue_with_waits(config, &[10]);
+            queue
+                .insert_party(
+                    Uuid::new_v4(),
+                    (0..3)
+                        .map(|_| (Uuid::new_v4(), Rating::default()))
+                        .collect(),
+                )
+                .unwrap();
+
+            assert!(queue.remove_ready_games().is_empty());
         }
     }
 }
@@ -0,0 +1,325 @@
+use crate::{
+    identity::{BearerToken, IdentityService},
+    party::PartyData,
+    queue::QueueData,
+    rating::RatingService,
+    websocket::server::{CheckQueue, WebsocketServer},
+};
+use actix::Addr;
+use actix_web::{error, post, web, HttpResponse, Responder};
+
+pub fn config_service(cfg: &mut web::ServiceConfig) {
+    cfg.service(join).service(leave);
+}
+
+/// Queues the player for a game mode, or the whole party of its leader.
+#[post("/join/")]
+async fn join(
+    token: BearerToken,
+    mode: web::Path<String>,
+    server_address: web::Data<Addr<WebsocketServer>>,
+    queue_data: web::Data<QueueData>,
+    party_data: web::Data<PartyData>,
+    id_service: web::Data<dyn IdentityService>,
+    rating_service: web::Data<dyn RatingService>,
+) -> actix_web::Result<impl Responder> {
+    let identity = id_service.get_identity(&token)?;
+    let max_party_size = queue_data
+        .queues
+        .read()
+        .expect("Failed to get read lock on queues")
+        .get(&mode)?
+        .config
+        .max_party_size();
+
+    let party = party_data
+        .parties
+        .read()
+        .expect("Failed to get read lock on parties")
+        .party_of(&identity.user_id)
+        .cloned();
+
+    let response = match party {
+        None => {
+            let rating = rating_service.get_rating(&identity.user_id).await?;
+
+            let mut queues = queue_data
+                .queues
+                .write()
+                .expect("Failed to get write lock on queues");
+            if queues.contains_player(&identity.user_id) {
+                return Err(error::ErrorBadRequest(
+                    "Cannot join queue that is already joined",
+                ));
+            }
+
+            let player = queues
+                .get_mut(&mode)?
+                .insert_user(identity.user_id, rating)?;
+            HttpResponse::Ok().json(player)
+        }
+        Some(party) => {
+            if party.leader_id != identity.user_id {
+                return Err(error::ErrorForbidden(
+                    "Only the party leader can queue the party",
+                ));
+            }
+            if party.member_ids.len() > max_party_size as usize {
+                return Err(error::ErrorBadRequest(
+                    "The party is too large for this game mode",
+                ));
+            }
+            let mut members = vec![];
+            for member_id in &party.member_ids {
+                members.push((*member_id, rating_service.get_rating(member_id).await?));
+            }
+
+            let parties = party_data
+                .parties
+                .read()
+                .expect("Failed to get read lock on parties");
+            if parties.party_of(&identity.user_id) != Some(&party) {
+                return Err(error::ErrorConflict("The party changed while joining"));
+            }
+
+            let mut queues = queue_data
+                .queues
+                .write()
+                .expect("Failed to get write lock on queues");
+            if party
+                .member_ids
+                .iter()
+                .any(|member_id| queues.contains_player(member_id))
+            {
+                return Err(error::ErrorBadRequest(
+                    "Cannot join queue that is already joined",
+                ));
+            }
+
+            let players = queues.get_mut(&mode)?.insert_party(party.id, members)?;
+            HttpResponse::Ok().json(players)
+        }
+    };
+
+    server_address
+        .as_ref()
+        .clone()
+        .try_send(CheckQueue)
+        .map_err(error::ErrorInternalServerError)?;
+
+    Ok(response)
+}
+
+/// Leaves the queue of a game mode, together with the rest of the party if
+/// the player is in one.
+#[post("/leave/")]
+async fn leave(
+    token: BearerToken,
+    mode: web::Path<String>,
+    id_service: web::Data<dyn IdentityService>,
+    queue_data: web::Data<QueueData>,
+    party_data: web::Data<PartyData>,
+) -> actix_web::Result<impl Responder> {
+    let identity = id_service.get_identity(&token)?;
+
+    let parties = party_data
+        .parties
+        .read()
+        .expect("Failed to get read lock on parties");
+
+    let mut queues = queue_data
+        .queues
+        .write()
+        .expect("Failed to get write lock on queues");
+    let queue = queues.get_mut(&mode)?;
+
+    let Some(party) = parties.party_of(&identity.user_id) else {
+        let player = queue.remove_player(&identity.user_id)?;
+        return Ok(HttpResponse::Ok().json(player));
+    };
+
+    let players = queue.remove_party(&party.id);
+    if players.is_empty() {
+        return Err(error::ErrorBadRequest(
+            "Cannot leave queue that is not joined",
+        ));
+    }
+
+    Ok(HttpResponse::Ok().json(players))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        config::{MatchmakingConfig, QueueConfig},
+        game_server_manager::{GameServerDescription, GameServerManager},
+        identity::{Identity, IdentityService},
+        queue,
+        rating::Rating,
+    };
+    use actix::Actor;
+    use actix_web::{
+        http::{header::AUTHORIZATION, StatusCode},
+        test, App,
+    };
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use uuid::Uuid;
+
+    use super::*;
+
+    struct NewPlayerRatingService;
+
+    #[async_trait::async_trait]
+    impl RatingService for NewPlayerRatingService {
+        async fn get_rating(&self, _: &Uuid) -> Result<Rating, actix_web::error::Error> {
+            Ok(Rating::default())
+        }
+
+        async fn create_game(&self, _: &str, _: &[Uuid]) -> Result<Uuid, actix_web::error::Error> {
+            Ok(Uuid::new_v4())
+        }
+
+        async fn record_game(
+            &self,
+            _: &Uuid,
+            _: &[Vec<Uuid>],
+        ) -> Result<HashMap<Uuid, Rating>, actix_web::error::Error> {
+            Ok(HashMap::new())
+        }
+    }
+
+    #[actix_web::test]
+    async fn server_sends_ready_message_to_clients_when_ready() {
+        struct RandomIdService;
+
+        impl IdentityService for RandomIdService {
+            fn get_identity(&self, _: &BearerToken) -> Result<Identity, actix_web::error::Error> {
+                Ok(Identity {
+                    user_id: Uuid::new_v4(),
+                })
+            }
+        }
+
+        let id_service = web::Data::from(Arc::new(RandomIdService) as Arc<dyn IdentityService>);
+        let rating_service =
+            web::Data::from(Arc::new(NewPlayerRatingService) as Arc<dyn RatingService>);
+        let queue_data = web::Data::new(queue::QueueData::new(&MatchmakingConfig::default()));
+        let party_data = web::Data::new(PartyData::new(4));
+
+        let app = test::init_service(
+            App::new()
+                .app_data(id_service)
+                .app_data(rating_service)
+                .app_data(queue_data)
+                .app_data(party_data)
+                .service(web::scope("/{mode}").service(join)),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .insert_header((AUTHORIZATION, "Bearer 0000"))
+            .uri("/solo/join/")
+            .to_request();
+        test::call_service(&app, req).await;
+
+        let req = test::TestRequest::post()
+            .insert_header((AUTHORIZATION, "Bearer 0000"))
+            .uri("/solo/join/")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        println!("{:?}", test::read_body(resp).await);
+    }
+
+    #[actix_web::test]
+    async fn parties_larger_than_a_team_cannot_join_team_modes() {
+        struct FixedIdService(Uuid);
+
+        impl IdentityService for FixedIdService {
+            fn get_identity(&self, _: &BearerToken) -> Result<Identity, actix_web::error::Error> {
+                Ok(Identity { user_id: self.0 })
+            }
+        }
+
+        struct UnavailableGameServerManager;
+
+        #[async_trait::async_trait]
+        impl GameServerManager for UnavailableGameServerManager {
+            async fn spawn_new_game_server(
+                &self,
+                _: &Uuid,
+                _: &[String],
+                _: &[Vec<Uuid>],
+            ) -> Result<GameServerDescription, Box<dyn std::error::Error>> {
+                Err("No game servers in tests".into())
+            }
+        }
+
+        let [leader, first_friend, second_friend] = [(); 3].map(|_| Uuid::new_v4());
+        let party_data = web::Data::new(PartyData::new(4));
+        {
+            let mut parties = party_data.parties.write().unwrap();
+            let party = parties.create(leader).unwrap();
+            for friend in [first_friend, second_friend] {
+                parties.invite(&leader, friend).unwrap();
+                parties.accept(friend, &party.id).unwrap();
+            }
+        }
+
+        let team_config = QueueConfig {
+            min_size: 4,
+            desired_size: 4,
+            team_count: Some(2),
+            ..QueueConfig::default()
+        };
+        let config = MatchmakingConfig {
+            queues: HashMap::from([
+                ("2v2".to_string(), team_config),
+                ("ffa".to_string(), QueueConfig::default()),
+            ]),
+            ..MatchmakingConfig::default()
+        };
+        let queue_data = web::Data::new(queue::QueueData::new(&config));
+        let rating_service =
+            web::Data::from(Arc::new(NewPlayerRatingService) as Arc<dyn RatingService>);
+        let server_address = web::Data::new(
+            WebsocketServer::new(
+                queue_data.clone(),
+                config,
+                web::Data::from(
+                    Arc::new(UnavailableGameServerManager) as Arc<dyn GameServerManager>
+                ),
+                rating_service.clone(),
+            )
+            .start(),
+        );
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::from(
+                    Arc::new(FixedIdService(leader)) as Arc<dyn IdentityService>
+                ))
+                .app_data(rating_service)
+                .app_data(queue_data)
+                .app_data(party_data)
+                .app_data(server_address)
+                .service(web::scope("/{mode}").service(join)),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .insert_header((AUTHORIZATION, "Bearer 0000"))
+            .uri("/2v2/join/")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let req = test::TestRequest::post()
+            .insert_header((AUTHORIZATION, "Bearer 0000"))
+            .uri("/ffa/join/")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+}
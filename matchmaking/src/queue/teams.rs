@@ -0,0 +1,132 @@
+/// A group of players that must be on the same team.
+#[derive(Debug, Clone, Copy)]
+pub struct TeamGroup {
+    pub size: usize,
+    /// The sum of the ratings of the players.
+    pub total_rating: f64,
+}
+
+/// Splits groups into `team_count` teams of the same size, so that the total
+/// ratings of the teams are as close as possible. Returns the team of each
+/// group, or `None` if the groups cannot be split evenly.
+pub fn balance_teams(groups: &[TeamGroup], team_count: usize) -> Option<Vec<usize>> {
+    let players: usize = groups.iter().map(|group| group.size).sum();
+    if team_count == 0 || !players.is_multiple_of(team_count) {
+        return None;
+    }
+
+    // Placing the largest groups first rules out uneven teams early.
+    let mut order: Vec<usize> = (0..groups.len()).collect();
+    order.sort_by_key(|index| std::cmp::Reverse(groups[*index].size));
+
+    let mut search = TeamSearch {
+        groups,
+        order: &order,
+        team_size: players / team_count,
+        team_sizes: vec![0; team_count],
+        team_ratings: vec![0.0; team_count],
+        teams: vec![0; groups.len()],
+        best: None,
+    };
+    search.place(0);
+
+    search.best.map(|(_, teams)| teams)
+}
+
+struct TeamSearch<'a> {
+    groups: &'a [TeamGroup],
+    order: &'a [usize],
+    team_size: usize,
+    team_sizes: Vec<usize>,
+    team_ratings: Vec<f64>,
+    /// The team of each group placed so far.
+    teams: Vec<usize>,
+    /// The smallest difference between the strongest and weakest team found,
+    /// with the team of each group.
+    best: Option<(f64, Vec<usize>)>,
+}
+
+impl TeamSearch<'_> {
+    fn place(&mut self, placed: usize) {
+        if self
+            .best
+            .as_ref()
+            .is_some_and(|(difference, _)| *difference == 0.0)
+        {
+            return;
+        }
+        let Some(&index) = self.order.get(placed) else {
+            let strongest = self.team_ratings.iter().copied().fold(f64::MIN, f64::max);
+            let weakest = self.team_ratings.iter().copied().fold(f64::MAX, f64::min);
+            let difference = strongest - weakest;
+            let is_better = match &self.best {
+                Some((best, _)) => difference < *best,
+                None => true,
+            };
+            if is_better {
+                self.best = Some((difference, self.teams.clone()));
+            }
+            return;
+        };
+
+        let group = self.groups[index];
+        for team in 0..self.team_sizes.len() {
+            if self.team_sizes[team] + group.size > self.team_size {
+                continue;
+            }
+            self.team_sizes[team] += group.size;
+            self.team_ratings[team] += group.total_rating;
+            self.teams[index] = team;
+            self.place(placed + 1);
+            self.team_sizes[team] -= group.size;
+            self.team_ratings[team] -= group.total_rating;
+
+            // Empty teams are interchangeable, so trying one is enough.
+            if self.team_sizes[team] == 0 {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solo(rating: f64) -> TeamGroup {
+        TeamGroup {
+            size: 1,
+            total_rating: rating,
+        }
+    }
+
+    #[test]
+    fn teams_have_close_total_ratings() {
+        let groups = [solo(2000.0), solo(1900.0), solo(1100.0), solo(1000.0)];
+
+        let teams = balance_teams(&groups, 2).unwrap();
+
+        assert_eq!(teams[0], teams[3]);
+        assert_eq!(teams[1], teams[2]);
+        assert_ne!(teams[0], teams[1]);
+    }
+
+    #[test]
+    fn parties_stay_on_one_team() {
+        let party = TeamGroup {
+            size: 2,
+            total_rating: 4000.0,
+        };
+        let groups = [party, solo(1000.0), solo(1000.0)];
+
+        let teams = balance_teams(&groups, 2).unwrap();
+        assert_ne!(teams[0], teams[1]);
+        assert_eq!(teams[1], teams[2]);
+
+        let too_large = TeamGroup {
+            size: 3,
+            total_rating: 4500.0,
+        };
+        assert_eq!(balance_teams(&[too_large, solo(1000.0)], 2), None);
+    }
+}
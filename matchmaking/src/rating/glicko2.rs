@@ -0,0 +1,152 @@
+//! The Glicko-2 rating system, as described in
+//! <http://www.glicko.net/glicko/glicko2.pdf>.
+
+use super::Rating;
+use std::f64::consts::PI;
+
+/// Converts between the Glicko scale and the Glicko-2 scale.
+const SCALE: f64 = 173.7178;
+
+/// Constrains how much the volatility of a player can change per game.
+const TAU: f64 = 0.5;
+
+/// The tolerance of the volatility iteration.
+const EPSILON: f64 = 0.000001;
+
+/// The result of one game against an opponent, where `score` is 1 for a win,
+/// 0.5 for a draw and 0 for a loss.
+#[derive(Debug, Clone, Copy)]
+pub struct Outcome {
+    pub opponent: Rating,
+    pub score: f64,
+}
+
+fn g(phi: f64) -> f64 {
+    1.0 / (1.0 + 3.0 * phi * phi / (PI * PI)).sqrt()
+}
+
+fn expected_score(mu: f64, opponent_mu: f64, opponent_phi: f64) -> f64 {
+    1.0 / (1.0 + (-g(opponent_phi) * (mu - opponent_mu)).exp())
+}
+
+/// Rates a player after a rating period with the given outcomes. A player
+/// without outcomes keeps their rating, but becomes less certain of it.
+pub fn rate(player: &Rating, outcomes: &[Outcome]) -> Rating {
+    let mu = (player.rating - 1500.0) / SCALE;
+    let phi = player.deviation / SCALE;
+    let sigma = player.volatility;
+
+    if outcomes.is_empty() {
+        return Rating {
+            deviation: (phi * phi + sigma * sigma).sqrt() * SCALE,
+            ..*player
+        };
+    }
+
+    let mut v_inverse = 0.0;
+    let mut improvement = 0.0;
+    for outcome in outcomes {
+        let opponent_mu = (outcome.opponent.rating - 1500.0) / SCALE;
+        let opponent_phi = outcome.opponent.deviation / SCALE;
+        let expected = expected_score(mu, opponent_mu, opponent_phi);
+        v_inverse += g(opponent_phi).powi(2) * expected * (1.0 - expected);
+        improvement += g(opponent_phi) * (outcome.score - expected);
+    }
+    let v = 1.0 / v_inverse;
+    let delta = v * improvement;
+
+    let sigma = next_volatility(phi, sigma, v, delta);
+    let phi_star = (phi * phi + sigma * sigma).sqrt();
+    let phi = 1.0 / (1.0 / (phi_star * phi_star) + 1.0 / v).sqrt();
+    let mu = mu + phi * phi * improvement;
+
+    Rating {
+        rating: mu * SCALE + 1500.0,
+        deviation: phi * SCALE,
+        volatility: sigma,
+    }
+}
+
+/// Finds the new volatility with the Illinois algorithm.
+fn next_volatility(phi: f64, sigma: f64, v: f64, delta: f64) -> f64 {
+    let a = (sigma * sigma).ln();
+    let f = |x: f64| {
+        let ex = x.exp();
+        ex * (delta * delta - phi * phi - v - ex) / (2.0 * (phi * phi + v + ex).powi(2))
+            - (x - a) / (TAU * TAU)
+    };
+
+    let mut lower = a;
+    let mut upper = if delta * delta > phi * phi + v {
+        (delta * delta - phi * phi - v).ln()
+    } else {
+        let mut k = 1.0;
+        while f(a - k * TAU) < 0.0 {
+            k += 1.0;
+        }
+        a - k * TAU
+    };
+
+    let mut f_lower = f(lower);
+    let mut f_upper = f(upper);
+    while (upper - lower).abs() > EPSILON {
+        let next = lower + (lower - upper) * f_lower / (f_upper - f_lower);
+        let f_next = f(next);
+        if f_next * f_upper <= 0.0 {
+            lower = upper;
+            f_lower = f_upper;
+        } else {
+            f_lower /= 2.0;
+        }
+        upper = next;
+        f_upper = f_next;
+    }
+
+    (lower / 2.0).exp()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rating(rating: f64, deviation: f64) -> Rating {
+        Rating {
+            rating,
+            deviation,
+            volatility: 0.06,
+        }
+    }
+
+    #[test]
+    fn rates_the_example_from_the_paper() {
+        let rated = rate(
+            &rating(1500.0, 200.0),
+            &[
+                Outcome {
+                    opponent: rating(1400.0, 30.0),
+                    score: 1.0,
+                },
+                Outcome {
+                    opponent: rating(1550.0, 100.0),
+                    score: 0.0,
+                },
+                Outcome {
+                    opponent: rating(1700.0, 300.0),
+                    score: 0.0,
+                },
+            ],
+        );
+
+        assert!((rated.rating - 1464.06).abs() < 0.01, "{rated:?}");
+        assert!((rated.deviation - 151.52).abs() < 0.01, "{rated:?}");
+        assert!((rated.volatility - 0.05999).abs() < 0.00001, "{rated:?}");
+    }
+
+    #[test]
+    fn players_without_games_become_less_certain() {
+        let player = rating(1500.0, 200.0);
+        let rated = rate(&player, &[]);
+        assert_eq!(rated.rating, player.rating);
+        assert!(rated.deviation > player.deviation);
+    }
+}
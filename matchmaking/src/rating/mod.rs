@@ -0,0 +1,325 @@
+pub mod glicko2;
+mod results;
+
+use crate::db::{DbError, DbPool};
+use crate::schema;
+use actix_web::{error, web};
+use chrono::{DateTime, Utc};
+use diesel::{pg::Pg, prelude::*, upsert::excluded};
+use diesel_async::scoped_futures::ScopedFutureExt;
+use diesel_async::{AsyncConnection, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+pub fn config_service(cfg: &mut web::ServiceConfig) {
+    cfg.service(results::report);
+}
+
+/// A Glicko-2 rating on the Glicko scale.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Queryable, Selectable)]
+#[diesel(table_name = schema::player_rating)]
+#[diesel(check_for_backend(Pg))]
+pub struct Rating {
+    pub rating: f64,
+    /// How uncertain the rating is. The rating is within about two deviations
+    /// of the player's true skill.
+    pub deviation: f64,
+    /// How erratic the player's results are.
+    pub volatility: f64,
+}
+
+impl Default for Rating {
+    fn default() -> Self {
+        Rating {
+            rating: 1500.0,
+            deviation: 350.0,
+            volatility: 0.06,
+        }
+    }
+}
+
+/// Rates every player of a game after its placements, best first, where the
+/// players in one placement tied. Every player is rated as if they played
+/// every other player of the game.
+pub fn rate_game(
+    ratings: &HashMap<Uuid, Rating>,
+    placements: &[Vec<Uuid>],
+) -> HashMap<Uuid, Rating> {
+    let placed_players: Vec<(usize, Uuid)> = placements
+        .iter()
+        .enumerate()
+        .flat_map(|(place, user_ids)| user_ids.iter().map(move |user_id| (place, *user_id)))
+        .collect();
+    let rating_of = |user_id: &Uuid| ratings.get(user_id).copied().unwrap_or_default();
+
+    placed_players
+        .iter()
+        .map(|(place, user_id)| {
+            let outcomes: Vec<glicko2::Outcome> = placed_players
+                .iter()
+                .filter(|(_, opponent_id)| opponent_id != user_id)
+                .map(|(opponent_place, opponent_id)| glicko2::Outcome {
+                    opponent: rating_of(opponent_id),
+                    score: match place.cmp(opponent_place) {
+                        std::cmp::Ordering::Less => 1.0,
+                        std::cmp::Ordering::Equal => 0.5,
+                        std::cmp::Ordering::Greater => 0.0,
+                    },
+                })
+                .collect();
+            (*user_id, glicko2::rate(&rating_of(user_id), &outcomes))
+        })
+        .collect()
+}
+
+/// Why the result of a game was not recorded.
+#[derive(Debug)]
+enum RecordGameError {
+    UnknownGame,
+    AlreadyReported,
+    NotInGame(Uuid),
+    Db(DbError),
+}
+
+impl From<DbError> for RecordGameError {
+    fn from(err: DbError) -> Self {
+        RecordGameError::Db(err)
+    }
+}
+
+impl From<RecordGameError> for error::Error {
+    fn from(err: RecordGameError) -> Self {
+        match err {
+            RecordGameError::UnknownGame => error::ErrorNotFound("Game not found"),
+            RecordGameError::AlreadyReported => {
+                error::ErrorConflict("The result of the game was already reported")
+            }
+            RecordGameError::NotInGame(user_id) => {
+                error::ErrorBadRequest(format!("Player {user_id} did not play the game"))
+            }
+            RecordGameError::Db(err) => error::ErrorInternalServerError(err),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+pub trait RatingService: Sync {
+    /// The rating of a user, or the rating of a new player if they have not
+    /// finished a game yet.
+    async fn get_rating(&self, user_id: &Uuid) -> Result<Rating, error::Error>;
+
+    /// Records a game that is starting, and returns its id.
+    async fn create_game(&self, mode: &str, user_ids: &[Uuid]) -> Result<Uuid, error::Error>;
+
+    /// Rates the players of a finished game, and returns their new ratings.
+    /// The result of a game can only be recorded once, and only for its
+    /// players.
+    async fn record_game(
+        &self,
+        game_id: &Uuid,
+        placements: &[Vec<Uuid>],
+    ) -> Result<HashMap<Uuid, Rating>, error::Error>;
+}
+
+pub struct RealRatingService {
+    pool: DbPool,
+}
+
+impl RealRatingService {
+    pub fn new(pool: DbPool) -> RealRatingService {
+        RealRatingService { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl RatingService for RealRatingService {
+    async fn get_rating(&self, user_id: &Uuid) -> Result<Rating, error::Error> {
+        let mut conn = self
+            .pool
+            .get()
+            .await
+            .map_err(error::ErrorInternalServerError)?;
+
+        let rating: Option<Rating> = schema::player_rating::table
+            .find(user_id)
+            .select(Rating::as_select())
+            .first(&mut conn)
+            .await
+            .optional()
+            .map_err(error::ErrorInternalServerError)?;
+
+        Ok(rating.unwrap_or_default())
+    }
+
+    async fn create_game(&self, mode: &str, user_ids: &[Uuid]) -> Result<Uuid, error::Error> {
+        let mut conn = self
+            .pool
+            .get()
+            .await
+            .map_err(error::ErrorInternalServerError)?;
+
+        diesel::insert_into(schema::game::table)
+            .values((
+                schema::game::mode.eq(mode),
+                schema::game::user_ids.eq(user_ids),
+            ))
+            .returning(schema::game::id)
+            .get_result(&mut conn)
+            .await
+            .map_err(error::ErrorInternalServerError)
+    }
+
+    async fn record_game(
+        &self,
+        game_id: &Uuid,
+        placements: &[Vec<Uuid>],
+    ) -> Result<HashMap<Uuid, Rating>, error::Error> {
+        let user_ids: Vec<Uuid> = placements.iter().flatten().copied().collect();
+
+        let mut conn = self
+            .pool
+            .get()
+            .await
+            .map_err(error::ErrorInternalServerError)?;
+
+        let rated = conn
+            .transaction::<_, RecordGameError, _>(|conn| {
+                async move {
+                    // Locks the game so that a result reported twice at the
+                    // same time is only rated once.
+                    let Some((game_user_ids, reported_at)) = schema::game::table
+                        .find(game_id)
+                        .select((schema::game::user_ids, schema::game::reported_at))
+                        .for_update()
+                        .first::<(Vec<Uuid>, Option<DateTime<Utc>>)>(conn)
+                        .await
+                        .optional()?
+                    else {
+                        return Err(RecordGameError::UnknownGame);
+                    };
+                    if reported_at.is_some() {
+                        return Err(RecordGameError::AlreadyReported);
+                    }
+                    if let Some(user_id) = user_ids
+                        .iter()
+                        .find(|user_id| !game_user_ids.contains(user_id))
+                    {
+                        return Err(RecordGameError::NotInGame(*user_id));
+                    }
+                    diesel::update(schema::game::table.find(game_id))
+                        .set(schema::game::reported_at.eq(Utc::now()))
+                        .execute(conn)
+                        .await?;
+
+                    // Locks the ratings so that games that finish at the same
+                    // time are rated one after the other.
+                    let ratings: HashMap<Uuid, Rating> = schema::player_rating::table
+                        .filter(schema::player_rating::user_id.eq_any(&user_ids))
+                        .select((schema::player_rating::user_id, Rating::as_select()))
+                        .for_update()
+                        .load::<(Uuid, Rating)>(conn)
+                        .await?
+                        .into_iter()
+                        .collect();
+
+                    let rated = rate_game(&ratings, placements);
+
+                    let rows: Vec<_> = rated
+                        .iter()
+                        .map(|(user_id, rating)| {
+                            (
+                                schema::player_rating::user_id.eq(user_id),
+                                schema::player_rating::rating.eq(rating.rating),
+                                schema::player_rating::deviation.eq(rating.deviation),
+                                schema::player_rating::volatility.eq(rating.volatility),
+                                schema::player_rating::updated_at.eq(Utc::now()),
+                            )
+                        })
+                        .collect();
+                    diesel::insert_into(schema::player_rating::table)
+                        .values(rows)
+                        .on_conflict(schema::player_rating::user_id)
+                        .do_update()
+                        .set((
+                            schema::player_rating::rating
+                                .eq(excluded(schema::player_rating::rating)),
+                            schema::player_rating::deviation
+                                .eq(excluded(schema::player_rating::deviation)),
+                            schema::player_rating::volatility
+                                .eq(excluded(schema::player_rating::volatility)),
+                            schema::player_rating::updated_at
+                                .eq(excluded(schema::player_rating::updated_at)),
+                        ))
+                        .execute(conn)
+                        .await?;
+
+                    Ok(rated)
+                }
+                .scope_boxed()
+            })
+            .await?;
+
+        Ok(rated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{config, db};
+    use actix_web::http::StatusCode;
+
+    use super::*;
+
+    #[test]
+    fn players_are_rated_by_their_placement() {
+        let [first, second, also_second, last] = [(); 4].map(|_| Uuid::new_v4());
+        let ratings = HashMap::from([(last, Rating::default())]);
+
+        let rated = rate_game(
+            &ratings,
+            &[vec![first], vec![second, also_second], vec![last]],
+        );
+
+        assert_eq!(rated.len(), 4);
+        assert!(rated[&first].rating > rated[&second].rating);
+        assert_eq!(rated[&second], rated[&also_second]);
+        assert!(rated[&second].rating > rated[&last].rating);
+        assert!(rated[&first].rating > 1500.0 && rated[&last].rating < 1500.0);
+        assert!(rated.values().all(|rating| rating.deviation < 350.0));
+    }
+
+    #[actix_web::test]
+    async fn games_are_only_rated_once_and_for_their_players() {
+        let service = RealRatingService::new(db::initialize_db_pool(&config::POSTGRES_URL).await);
+        let [winner, loser, stranger] = [(); 3].map(|_| Uuid::new_v4());
+        let status = |result: Result<HashMap<Uuid, Rating>, error::Error>| {
+            result.unwrap_err().as_response_error().status_code()
+        };
+
+        let game_id = service
+            .create_game("duel", &[winner, loser])
+            .await
+            .expect("Failed to create game");
+
+        let unknown_game = service
+            .record_game(&Uuid::new_v4(), &[vec![winner], vec![loser]])
+            .await;
+        assert_eq!(status(unknown_game), StatusCode::NOT_FOUND);
+        let with_stranger = service
+            .record_game(&game_id, &[vec![winner], vec![stranger]])
+            .await;
+        assert_eq!(status(with_stranger), StatusCode::BAD_REQUEST);
+
+        let rated = service
+            .record_game(&game_id, &[vec![winner], vec![loser]])
+            .await
+            .expect("Failed to record game");
+        assert!(rated[&winner].rating > rated[&loser].rating);
+
+        let reported_again = service
+            .record_game(&game_id, &[vec![winner], vec![loser]])
+            .await;
+        assert_eq!(status(reported_again), StatusCode::CONFLICT);
+    }
+}
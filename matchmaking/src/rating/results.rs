@@ -0,0 +1,47 @@
+use crate::config::GAME_RESULTS_SERVICE_KEY;
+use crate::rating::RatingService;
+use crate::service_key::ServiceKey;
+use actix_web::{error, post, web, HttpResponse, Responder};
+use serde::Deserialize;
+use std::collections::HashSet;
+use uuid::Uuid;
+
+#[derive(Deserialize)]
+struct GameResult {
+    /// The `game_id` that the game server was launched with.
+    game_id: Uuid,
+    /// The players of the game by placement, best first. Players in the same
+    /// placement tied.
+    placements: Vec<Vec<Uuid>>,
+}
+
+/// Updates the ratings of the players of a finished game, as reported by its
+/// game server.
+#[post("/results/")]
+async fn report(
+    service_key: ServiceKey,
+    result: web::Json<GameResult>,
+    rating_service: web::Data<dyn RatingService>,
+) -> actix_web::Result<impl Responder> {
+    service_key.validate(GAME_RESULTS_SERVICE_KEY.as_deref())?;
+
+    let GameResult {
+        game_id,
+        placements,
+    } = result.into_inner();
+    let mut user_ids = HashSet::new();
+    for user_id in placements.iter().flatten() {
+        if !user_ids.insert(user_id) {
+            return Err(error::ErrorBadRequest(format!(
+                "Player {user_id} is placed more than once"
+            )));
+        }
+    }
+    if user_ids.len() < 2 {
+        return Err(error::ErrorBadRequest("A game needs at least two players"));
+    }
+
+    let ratings = rating_service.record_game(&game_id, &placements).await?;
+
+    Ok(HttpResponse::Ok().json(ratings))
+}
@@ -2,7 +2,9 @@ use super::session::ClientToServerMessage;
 use crate::{
     config::MatchmakingConfig,
     game_server_manager::{GameServerDescription, GameServerManager},
-    queue::{QueueData, QueueStatus},
+    party::Party,
+    queue::{QueueData, ReadyGame},
+    rating::RatingService,
 };
 use actix::{
     dev::ContextFutureSpawner, fut, Actor, ActorFutureExt, AsyncContext, Context, Handler,
@@ -20,7 +22,16 @@ use uuid::Uuid;
 #[rtype(result = "()")]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum ServerToClientMessage {
-    StartGame(GameServerDescription),
+    StartGame {
+        /// The game mode that the player was matched in.
+        mode: String,
+        #[serde(flatten)]
+        game_server: GameServerDescription,
+    },
+    /// The party of the player changed.
+    PartyUpdated(Party),
+    /// The player was invited to a party.
+    PartyInvited(Party),
 }
 
 type Sessions = HashMap<Uuid, Recipient<ServerToClientMessage>>;
@@ -31,6 +42,7 @@ pub struct WebsocketServer {
     queue_data: web::Data<QueueData>,
     matchmaking_config: MatchmakingConfig,
     game_server_manager: web::Data<dyn GameServerManager>,
+    rating_service: web::Data<dyn RatingService>,
 }
 
 impl WebsocketServer {
@@ -38,32 +50,40 @@ impl WebsocketServer {
         queue_data: web::Data<QueueData>,
         matchmaking_config: MatchmakingConfig,
         game_server_manager: web::Data<dyn GameServerManager>,
+        rating_service: web::Data<dyn RatingService>,
     ) -> Self {
         WebsocketServer {
             sessions: Arc::new(RwLock::new(HashMap::new())),
             queue_data,
             matchmaking_config,
             game_server_manager,
+            rating_service,
         }
     }
 
+    /// Starts a game for every group of players that the queues are ready
+    /// for.
     fn check_queue(&self, ctx: &mut Context<Self>) -> Result<(), error::Error> {
-        let queue_ready = {
-            let queue = self
+        let mut ready_games = vec![];
+        {
+            let mut queues = self
                 .queue_data
-                .solo
-                .read()
-                .expect("Failed to get read lock on solo queue");
-
-            match queue.status(&self.matchmaking_config) {
-                QueueStatus::Ready | QueueStatus::LongWaitReady => true,
-                _ => false,
+                .queues
+                .write()
+                .expect("Failed to get write lock on queues");
+            for (mode, queue) in queues.iter_mut() {
+                for game in queue.remove_ready_games() {
+                    ready_games.push(StartGame {
+                        mode: mode.clone(),
+                        game,
+                    });
+                }
             }
-        };
+        }
 
-        if queue_ready {
+        for start_game in ready_games {
             ctx.address()
-                .try_send(StartGame)
+                .try_send(start_game)
                 .map_err(error::ErrorInternalServerError)?;
         }
 
@@ -71,16 +91,49 @@ impl WebsocketServer {
     }
 }
 
-async fn start_game(server: WebsocketServer) -> Result<(), error::Error> {
-    let game_server = server.game_server_manager.spawn_new_game_server().await?;
+/// Records the game and spawns its game server.
+async fn spawn_game_server(
+    server: &WebsocketServer,
+    mode: &str,
+    game: &ReadyGame,
+) -> Result<GameServerDescription, error::Error> {
+    let game_server_args = server
+        .queue_data
+        .queues
+        .read()
+        .expect("Failed to get read lock on queues")
+        .get(mode)?
+        .config
+        .game_server_args
+        .clone();
+    let user_ids: Vec<Uuid> = game.players.iter().map(|p| p.user_id).collect();
+    let game_id = server.rating_service.create_game(mode, &user_ids).await?;
+
+    server
+        .game_server_manager
+        .spawn_new_game_server(&game_id, &game_server_args, &game.teams)
+        .await
+        .map_err(|err| error::ErrorInternalServerError(err.to_string()))
+}
 
-    let ready_players = {
-        let mut queue = server
-            .queue_data
-            .solo
-            .write()
-            .expect("Failed to get write lock on solo queue");
-        queue.remove_ready_players(&server.matchmaking_config)?
+async fn start_game(
+    server: WebsocketServer,
+    mode: String,
+    game: ReadyGame,
+) -> Result<(), error::Error> {
+    let game_server = match spawn_game_server(&server, &mode, &game).await {
+        Ok(game_server) => game_server,
+        Err(err) => {
+            // The players are still waiting, so they get the next game.
+            server
+                .queue_data
+                .queues
+                .write()
+                .expect("Failed to get write lock on queues")
+                .get_mut(&mode)?
+                .requeue_players(game.players);
+            return Err(err);
+        }
     };
 
     {
@@ -89,11 +142,14 @@ async fn start_game(server: WebsocketServer) -> Result<(), error::Error> {
             .read()
             .expect("Failed to get read lock on sessions");
 
-        for player in ready_players {
+        for player in game.players {
             let Some(session) = sessions.get(&player.user_id) else {
                 continue;
             };
-            match session.try_send(ServerToClientMessage::StartGame(game_server.clone())) {
+            match session.try_send(ServerToClientMessage::StartGame {
+                mode: mode.clone(),
+                game_server: game_server.clone(),
+            }) {
                 Ok(()) => (),
                 Err(err) => println!("{err}"),
             };
@@ -105,6 +161,19 @@ async fn start_game(server: WebsocketServer) -> Result<(), error::Error> {
 
 impl Actor for WebsocketServer {
     type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        let tick_interval = self
+            .matchmaking_config
+            .queue_tick_interval
+            .to_std()
+            .expect("The queue tick interval should be positive");
+        ctx.run_interval(tick_interval, |server, ctx| {
+            if let Err(err) = server.check_queue(ctx) {
+                println!("{err}");
+            }
+        });
+    }
 }
 
 impl Handler<ClientToServerMessage> for WebsocketServer {
@@ -143,14 +212,47 @@ impl Handler<CheckQueue> for WebsocketServer {
     }
 }
 
+/// Sends a message to the players who are connected.
+#[derive(Debug, Clone, actix::Message)]
+#[rtype(result = "()")]
+pub struct Notify {
+    pub user_ids: Vec<Uuid>,
+    pub message: ServerToClientMessage,
+}
+impl Handler<Notify> for WebsocketServer {
+    type Result = ();
+
+    fn handle(&mut self, notify: Notify, _ctx: &mut Self::Context) -> Self::Result {
+        let sessions = self
+            .sessions
+            .read()
+            .expect("Failed to get read lock on sessions");
+
+        for user_id in notify.user_ids {
+            let Some(session) = sessions.get(&user_id) else {
+                continue;
+            };
+            if let Err(err) = session.try_send(notify.message.clone()) {
+                println!("{err}");
+            }
+        }
+    }
+}
+
+/// Starts a game for players that were removed from the queue.
 #[derive(Debug, Clone, actix::Message)]
 #[rtype(result = "Result<(), ()>")]
-pub struct StartGame;
+pub struct StartGame {
+    pub mode: String,
+    pub game: ReadyGame,
+}
 impl Handler<StartGame> for WebsocketServer {
     type Result = Result<(), ()>;
 
-    fn handle(&mut self, _message: StartGame, ctx: &mut Self::Context) -> Self::Result {
-        start_game(self.clone())
+    fn handle(&mut self, message: StartGame, ctx: &mut Self::Context) -> Self::Result {
+        // Games are started concurrently, so that one slow game server does
+        // not hold up the rest of the queue.
+        start_game(self.clone(), message.mode, message.game)
             .into_actor(self)
             .then(|res, _, _| {
                 match res {
@@ -159,8 +261,112 @@ impl Handler<StartGame> for WebsocketServer {
                 }
                 fut::ready(())
             })
-            .wait(ctx);
+            .spawn(ctx);
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::config::QueueConfig;
+    use crate::queue::{QueueData, QueueStatus};
+    use crate::rating::Rating;
+    use chrono::{Duration, Utc};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    struct CountingGameServerManager {
+        spawned: AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl GameServerManager for CountingGameServerManager {
+        async fn spawn_new_game_server(
+            &self,
+            game_id: &Uuid,
+            _: &[String],
+            teams: &[Vec<Uuid>],
+        ) -> Result<GameServerDescription, Box<dyn std::error::Error>> {
+            self.spawned.fetch_add(1, Ordering::SeqCst);
+            Ok(GameServerDescription {
+                port: 7000,
+                host: "localhost".to_string(),
+                created_at: Utc::now(),
+                game_id: *game_id,
+                teams: teams.to_vec(),
+            })
+        }
+    }
+
+    struct NewGameRatingService;
+
+    #[async_trait::async_trait]
+    impl RatingService for NewGameRatingService {
+        async fn get_rating(&self, _: &Uuid) -> Result<Rating, error::Error> {
+            Ok(Rating::default())
+        }
+
+        async fn create_game(&self, _: &str, _: &[Uuid]) -> Result<Uuid, error::Error> {
+            Ok(Uuid::new_v4())
+        }
+
+        async fn record_game(
+            &self,
+            _: &Uuid,
+            _: &[Vec<Uuid>],
+        ) -> Result<HashMap<Uuid, Rating>, error::Error> {
+            Ok(HashMap::new())
+        }
+    }
+
+    #[actix_web::test]
+    async fn waiting_players_are_matched_on_the_next_tick() {
+        let queue_config = QueueConfig {
+            min_size: 2,
+            desired_size: 4,
+            desired_max_wait_time: Duration::zero(),
+            ..QueueConfig::default()
+        };
+        let config = MatchmakingConfig {
+            queues: HashMap::from([("ffa".to_string(), queue_config)]),
+            queue_tick_interval: Duration::milliseconds(10),
+        };
+        let queue_data = web::Data::new(QueueData::new(&config));
+        {
+            let mut queues = queue_data.queues.write().unwrap();
+            let queue = queues.get_mut("ffa").unwrap();
+            for _ in 0..10 {
+                queue
+                    .insert_user(Uuid::new_v4(), Rating::default())
+                    .unwrap();
+            }
+        }
+        let game_server_manager = Arc::new(CountingGameServerManager {
+            spawned: AtomicUsize::new(0),
+        });
+
+        WebsocketServer::new(
+            queue_data.clone(),
+            config.clone(),
+            web::Data::from(game_server_manager.clone() as Arc<dyn GameServerManager>),
+            web::Data::from(Arc::new(NewGameRatingService) as Arc<dyn RatingService>),
+        )
+        .start();
+        actix_web::rt::time::sleep(std::time::Duration::from_millis(100)).await;
+
+        // Two full games, and a smaller game for the players who waited too long.
+        assert_eq!(game_server_manager.spawned.load(Ordering::SeqCst), 3);
+        assert_eq!(
+            queue_data
+                .queues
+                .read()
+                .unwrap()
+                .get("ffa")
+                .unwrap()
+                .status(),
+            QueueStatus::NotReady
+        );
+    }
+}
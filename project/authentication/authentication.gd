@@ -42,6 +42,12 @@ var access_token := Option.None()
 ## Option<String>
 ## [/codeblock]
 var refresh_token := Option.None()
+## The token to link the signed in provider to one of the users that it
+## matched, or to create a new user with, when sign in is pending.
+## [codeblock]
+## Option<String>
+## [/codeblock]
+var pending_link_token := Option.None()
 
 const ACCESS_TOKEN_EXPIRY_BUFFER_SEC := 20
 ## [codeblock]
@@ -62,6 +68,39 @@ var access_token_expires_at := Option.None() :
 			refresh_token_timer_node.wait_time = expires_in_sec
 			refresh_token_timer_node.start()
 
+const DEVICE_CONFIG_PATH := "user://device.cfg"
+## The id that the auth server keeps this device's refresh session under,
+## generated on first use and persisted across launches.
+var device_id: String :
+	get:
+		if device_id.is_empty():
+			device_id = load_or_create_device_id()
+		return device_id
+
+
+func load_or_create_device_id() -> String:
+	var config := ConfigFile.new()
+	config.load(DEVICE_CONFIG_PATH)
+	var id: String = config.get_value("device", "id", "")
+	if id.is_empty():
+		id = Crypto.new().generate_random_bytes(16).hex_encode()
+		config.set_value("device", "id", id)
+		var err := config.save(DEVICE_CONFIG_PATH)
+		if err != OK:
+			push_error("failed to save device id: %s" % error_string(err))
+	return id
+
+
+## The headers that identify this device to the auth server, to send on
+## every request that issues a refresh token.
+func device_headers() -> PackedStringArray:
+	return [
+		"X-Device-Id: %s" % device_id,
+		"X-Device-Label: %s" % OS.get_model_name(),
+		"X-Client-Platform: %s" % OS.get_name().to_lower(),
+	]
+
+
 var refresh_token_timer_node: Timer
 var is_token_refreshing := false
 
@@ -148,7 +187,8 @@ func sign_in() -> Result:
 				sign_in_body.payload.access_token.expires_at
 			)
 		"pending_link_or_create":
-			print("Possible existing account: ", sign_in_body.payload)
+			pending_link_token = Option.new(sign_in_body.payload.link_token.value)
+			print("Possible existing accounts: ", sign_in_body.payload.users)
 	
 	return Result.Ok(null)
 
@@ -161,7 +201,7 @@ func refresh_access_token() -> Result:
 	is_token_refreshing = true
 	var request_result: Result = await HTTPUtils.fetch(
 		Env.AUTH_SERVER_URL + AUTH_SERVER_REFRESH_PATH,
-		["Content-Type: application/json"],
+		PackedStringArray(["Content-Type: application/json"]) + device_headers(),
 		HTTPClient.METHOD_POST,
 		JSON.stringify({ "refresh_token": refresh_token.unwrap() })
 	).settled
@@ -35,7 +35,7 @@ func server_sign_in() -> Result:
 	
 	var request_result: Result = await HTTPUtils.fetch(
 		Env.AUTH_SERVER_URL + AUTH_SERVER_SIGN_IN_PATH,
-		["Content-Type: application/json"],
+		PackedStringArray(["Content-Type: application/json"]) + Authentication.device_headers(),
 		HTTPClient.METHOD_POST,
 		JSON.stringify({
 			"public_key_url": id_signature.public_key_url,
@@ -54,7 +54,10 @@ func initialize() -> Result:
 ##
 ## SignInPendingLinkOrCreate {
 ##   type: "pending_link_or_create"
-##   payload: Array<UserWithAuthProviders>
+##   payload: {
+##     link_token: Token
+##     users: Array<UserWithAuthProviders>
+##   }
 ## }
 ##
 ## SignInResult = SignInSuccess | SignInPendingLinkOrCreate
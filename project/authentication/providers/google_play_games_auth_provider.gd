@@ -45,7 +45,7 @@ func server_sign_in() -> Result:
 
 	var request_result: Result = await HTTPUtils.fetch(
 		Env.AUTH_SERVER_URL + AUTH_SERVER_SIGN_IN_PATH,
-		["Content-Type: text/plain"],
+		PackedStringArray(["Content-Type: text/plain"]) + Authentication.device_headers(),
 		HTTPClient.METHOD_POST,
 		auth_code,
 	).settled
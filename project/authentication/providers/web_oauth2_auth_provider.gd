@@ -40,7 +40,7 @@ func server_sign_in() -> Result:
 	
 	var request_result: Result = await HTTPUtils.fetch(
 		Env.AUTH_SERVER_URL + AUTH_SERVER_SIGN_IN_PATH,
-		["Authorization: Bearer %s" % access_token],
+		PackedStringArray(["Authorization: Bearer %s" % access_token]) + Authentication.device_headers(),
 		HTTPClient.METHOD_POST,
 	).settled
 	